@@ -0,0 +1,222 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Extraction of tool archives.
+//!
+//! `.tar.zst`, `.tar.gz`, `.tar.xz` and `.zip` are unpacked in process, no external
+//! `tar` is needed. The progress bar counts the bytes read from a tarball (the bytes
+//! written for a zip), so it moves evenly whatever the compression ratio. An entry
+//! with an absolute path or a `..` component fails the extraction before it is
+//! written.
+
+use crate::downloader::SharedProgress;
+use crate::tools::ArchiveFormat;
+use crate::{Result, PortableSourceError};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path};
+
+/// Unpack `archive_path` into `destination`, creating it if needed
+pub fn extract(archive_path: &Path, format: ArchiveFormat, destination: &Path) -> Result<()> {
+    extract_impl(archive_path, format, destination, None)
+}
+
+/// [`extract`] drawing into a shared view; the archive size is added to the overall bar
+pub fn extract_with_progress(archive_path: &Path, format: ArchiveFormat, destination: &Path, progress: &SharedProgress) -> Result<()> {
+    extract_impl(archive_path, format, destination, Some(progress))
+}
+
+fn extract_impl(archive_path: &Path, format: ArchiveFormat, destination: &Path, progress: Option<&SharedProgress>) -> Result<()> {
+    let label = archive_path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "archive".into());
+    fs::create_dir_all(destination)?;
+    let file = File::open(archive_path)
+        .map_err(|e| PortableSourceError::environment(format!("Failed to open archive {}: {}", label, e)))?;
+
+    match format {
+        ArchiveFormat::TarZst | ArchiveFormat::TarGz | ArchiveFormat::TarXz => {
+            let total = file.metadata()?.len();
+            let pb = create_extract_progress_bar(total, &format!("Extracting {}", label), progress);
+            let reader = counted(pb.wrap_read(file), total, progress);
+            match format {
+                ArchiveFormat::TarZst => {
+                    let decoder = zstd::stream::Decoder::new(reader)
+                        .map_err(|e| PortableSourceError::environment(format!("Failed to create zstd decoder: {}", e)))?;
+                    unpack_tar(decoder, destination, &label)?;
+                }
+                ArchiveFormat::TarGz => unpack_tar(flate2::read::MultiGzDecoder::new(reader), destination, &label)?,
+                _ => unpack_tar(xz2::read::XzDecoder::new(reader), destination, &label)?,
+            }
+            pb.finish_with_message(format!("Extracted {}", label));
+        }
+        ArchiveFormat::Zip => unpack_zip(file, destination, &label, progress)?,
+        ArchiveFormat::Binary => {
+            return Err(PortableSourceError::environment(format!("{} is not an archive", label)));
+        }
+    }
+    Ok(())
+}
+
+fn unpack_tar(reader: impl Read, destination: &Path, label: &str) -> Result<()> {
+    let tar_error = |e: io::Error| PortableSourceError::environment(format!("Failed to extract {}: {}", label, e));
+    let mut archive = tar::Archive::new(reader);
+    // Directories go last and deepest first, so read-only ones do not block their contents
+    let mut directories = Vec::new();
+    for entry in archive.entries().map_err(tar_error)? {
+        let mut entry = entry.map_err(tar_error)?;
+        check_entry_path(&entry.path().map_err(tar_error)?, label)?;
+        if entry.header().entry_type().is_hard_link() {
+            if let Some(target) = entry.link_name().map_err(tar_error)? {
+                check_entry_path(&target, label)?;
+            }
+        }
+        if entry.header().entry_type().is_dir() {
+            directories.push(entry);
+        } else {
+            entry.unpack_in(destination).map_err(tar_error)?;
+        }
+    }
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut dir in directories {
+        dir.unpack_in(destination).map_err(tar_error)?;
+    }
+    Ok(())
+}
+
+fn unpack_zip(file: File, destination: &Path, label: &str, progress: Option<&SharedProgress>) -> Result<()> {
+    let zip_error = |e: zip::result::ZipError| PortableSourceError::environment(format!("Failed to extract {}: {}", label, e));
+    let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(zip_error)?;
+    let (mut total, mut compressed) = (0, 0);
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i).map_err(zip_error)?;
+        total += entry.size();
+        compressed += entry.compressed_size();
+    }
+
+    let pb = create_extract_progress_bar(total, &format!("Extracting {}", label), progress);
+    // The overall bar counts archive bytes, as for tarballs
+    if let Some(progress) = progress { progress.expect(compressed); }
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(zip_error)?;
+        let relative = Path::new(entry.name()).to_path_buf();
+        check_entry_path(&relative, label)?;
+        let out = destination.join(&relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&out)?;
+            continue;
+        }
+        if let Some(parent) = out.parent() { fs::create_dir_all(parent)?; }
+        let mut writer = pb.wrap_write(File::create(&out)?);
+        io::copy(&mut entry, &mut writer)?;
+        if let Some(progress) = progress { progress.overall().inc(entry.compressed_size()); }
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&out, fs::Permissions::from_mode(mode & 0o7777))?;
+        }
+    }
+    pb.finish_with_message(format!("Extracted {}", label));
+    Ok(())
+}
+
+/// Entries must stay inside the target folder: relative and without `..`
+fn check_entry_path(path: &Path, label: &str) -> Result<()> {
+    if path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Ok(());
+    }
+    Err(PortableSourceError::environment(format!(
+        "Refusing to extract {}: entry {:?} points outside the target folder", label, path
+    )))
+}
+
+/// Also count the bytes read on the overall bar of a shared view
+fn counted(reader: impl Read + Send + 'static, total: u64, progress: Option<&SharedProgress>) -> Box<dyn Read + Send> {
+    match progress {
+        Some(progress) => {
+            progress.expect(total);
+            Box::new(progress.overall().wrap_read(reader))
+        }
+        None => Box::new(reader),
+    }
+}
+
+fn create_extract_progress_bar(total: u64, prefix: &str, progress: Option<&SharedProgress>) -> ProgressBar {
+    let pb = ProgressBar::new(total);
+    let style = ProgressStyle::with_template("{prefix:.bold} [{bar:40.magenta/blue}] {percent:>3}% {bytes}/{total_bytes} ETA {eta}")
+        .unwrap()
+        .progress_chars("=>-");
+    pb.set_style(style);
+    pb.set_prefix(prefix.to_string());
+    match progress {
+        Some(progress) => progress.add(pb),
+        None => pb,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn tarball(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            // Written by hand: `set_path` refuses `..`, which is what the test needs
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_extract_formats_and_reject_traversal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let good = tarball(&[("tool/bin/run", b"ok")]);
+
+        let gz = tmp.path().join("tool.tar.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&gz).unwrap(), flate2::Compression::default());
+        encoder.write_all(&good).unwrap();
+        encoder.finish().unwrap();
+        extract(&gz, ArchiveFormat::TarGz, &tmp.path().join("gz")).unwrap();
+        assert_eq!(fs::read(tmp.path().join("gz/tool/bin/run")).unwrap(), b"ok");
+
+        let xz = tmp.path().join("tool.tar.xz");
+        let mut encoder = xz2::write::XzEncoder::new(File::create(&xz).unwrap(), 6);
+        encoder.write_all(&good).unwrap();
+        encoder.finish().unwrap();
+        extract(&xz, ArchiveFormat::TarXz, &tmp.path().join("xz")).unwrap();
+        assert_eq!(fs::read(tmp.path().join("xz/tool/bin/run")).unwrap(), b"ok");
+
+        let zst = tmp.path().join("evil.tar.zst");
+        fs::write(&zst, zstd::encode_all(&tarball(&[("../evil", b"x")])[..], 0).unwrap()).unwrap();
+        assert!(extract(&zst, ArchiveFormat::TarZst, &tmp.path().join("zst")).is_err());
+        assert!(!tmp.path().join("evil").exists());
+
+        let zip_path = tmp.path().join("tool.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.start_file("tool/run", zip::write::FileOptions::default()).unwrap();
+        writer.write_all(b"ok").unwrap();
+        writer.start_file("/abs", zip::write::FileOptions::default()).unwrap();
+        writer.finish().unwrap();
+        let err = extract(&zip_path, ArchiveFormat::Zip, &tmp.path().join("zip")).unwrap_err();
+        assert!(err.to_string().contains("outside the target folder"));
+        assert_eq!(fs::read(tmp.path().join("zip/tool/run")).unwrap(), b"ok");
+    }
+}
@@ -0,0 +1,356 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Shared package cache for uv and pip.
+//!
+//! All environments use `<install_path>/cache/uv` and `<install_path>/cache/pip`
+//! (`UV_CACHE_DIR` / `PIP_CACHE_DIR`), so a package is downloaded once and the
+//! installation stays self-contained. uv keeps unpacked wheels in `archive-v0/<id>/`
+//! and hardlinks them into the environments; pip keeps built wheels in `wheels/` and
+//! downloaded ones in its HTTP cache (`http-v2/.../<key>.body`). All of them are attributed
+//! to packages through the wheel/dist-info names. pip entries whose exact version is not
+//! installed in any environment under `envs/` are removed directly; the uv cache is left to
+//! uv itself (`uv cache clean <package>` for packages no environment uses, then `uv cache prune`).
+
+use crate::installer::requirements::canonicalize_name;
+use crate::{Result, PortableSourceError};
+use std::collections::{BTreeMap, BTreeSet};
+use log::warn;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Cache folder inside the install path
+pub const CACHE_DIR: &str = "cache";
+
+/// Installed or cached distribution: canonical name and version
+pub type Distribution = (String, String);
+
+/// One cache entry attributed to a distribution
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub distribution: Distribution,
+    pub size: u64,
+    /// The exact version is installed in some environment
+    pub in_use: bool,
+}
+
+/// Sizes of the cache by area and by package
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub uv_size: u64,
+    pub pip_size: u64,
+    /// Cache entries attributed to a package
+    pub entries: Vec<CacheEntry>,
+}
+
+impl CacheStats {
+    pub fn total_size(&self) -> u64 {
+        self.uv_size + self.pip_size
+    }
+
+    /// Total size and whether any version is in use, per package, largest first
+    pub fn by_package(&self) -> Vec<(String, u64, bool)> {
+        let mut packages: BTreeMap<&str, (u64, bool)> = BTreeMap::new();
+        for entry in &self.entries {
+            let package = packages.entry(entry.distribution.0.as_str()).or_default();
+            package.0 += entry.size;
+            package.1 |= entry.in_use;
+        }
+        let mut packages: Vec<(String, u64, bool)> = packages
+            .into_iter()
+            .map(|(name, (size, in_use))| (name.to_string(), size, in_use))
+            .collect();
+        packages.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        packages
+    }
+
+    pub fn unused(&self) -> impl Iterator<Item = &CacheEntry> {
+        self.entries.iter().filter(|entry| !entry.in_use)
+    }
+}
+
+pub struct PackageCache {
+    install_path: PathBuf,
+}
+
+impl PackageCache {
+    pub fn new(install_path: impl Into<PathBuf>) -> Self {
+        Self { install_path: install_path.into() }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.install_path.join(CACHE_DIR)
+    }
+
+    /// `UV_CACHE_DIR`
+    pub fn uv_dir(&self) -> PathBuf {
+        self.dir().join("uv")
+    }
+
+    /// `PIP_CACHE_DIR`
+    pub fn pip_dir(&self) -> PathBuf {
+        self.dir().join("pip")
+    }
+
+    /// Distributions installed in any environment under `envs/`
+    pub fn installed_distributions(&self) -> BTreeSet<Distribution> {
+        let mut installed = BTreeSet::new();
+        let Ok(envs) = fs::read_dir(self.install_path.join("envs")) else {
+            return installed;
+        };
+        for env in envs.flatten() {
+            for site_packages in find_site_packages(&env.path(), 4) {
+                for entry in fs::read_dir(&site_packages).into_iter().flatten().flatten() {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if let Some(distribution) = name.strip_suffix(".dist-info").and_then(parse_distribution) {
+                        installed.insert(distribution);
+                    }
+                }
+            }
+        }
+        installed
+    }
+
+    pub fn stats(&self) -> Result<CacheStats> {
+        let installed = self.installed_distributions();
+        let mut stats = CacheStats {
+            uv_size: dir_size(&self.uv_dir()),
+            pip_size: dir_size(&self.pip_dir()),
+            entries: Vec::new(),
+        };
+        let mut add = |path: PathBuf, distribution: Distribution, size: u64| {
+            let in_use = installed.contains(&distribution);
+            stats.entries.push(CacheEntry { path, distribution, size, in_use });
+        };
+
+        // uv: unpacked wheels, one folder per wheel with its .dist-info inside
+        for archive in fs::read_dir(self.uv_dir().join("archive-v0")).into_iter().flatten().flatten() {
+            let dist_info = fs::read_dir(archive.path()).into_iter().flatten().flatten()
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .find_map(|name| name.strip_suffix(".dist-info").and_then(parse_distribution));
+            if let Some(distribution) = dist_info {
+                add(archive.path(), distribution, dir_size(&archive.path()));
+            }
+        }
+
+        // pip: wheels built from source distributions
+        for wheel in find_files(&self.pip_dir().join("wheels"), "whl") {
+            let name = wheel.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            if let Some(distribution) = parse_distribution(&name) {
+                let size = fs::metadata(&wheel).map(|m| m.len()).unwrap_or(0);
+                add(wheel, distribution, size);
+            }
+        }
+
+        // pip: downloaded files, named by a hash of the URL; a wheel is recognised by its dist-info
+        for body in find_files(&self.pip_dir().join("http-v2"), "body") {
+            if let Some(distribution) = wheel_distribution(&body) {
+                let size = fs::metadata(&body).map(|m| m.len()).unwrap_or(0) + fs::metadata(body.with_extension("")).map(|m| m.len()).unwrap_or(0);
+                add(body, distribution, size);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// uv of the first environment that has it (`python -m uv`), otherwise `uv` from PATH
+    fn uv_command(&self) -> Option<Vec<String>> {
+        let envs = fs::read_dir(self.install_path.join("envs")).into_iter().flatten().flatten();
+        let from_env = envs.map(|env| env.path()).find_map(|env| {
+            let python = if cfg!(windows) { env.join("python.exe") } else { env.join("bin").join("python") };
+            let has_uv = find_site_packages(&env, 4).iter().any(|site_packages| site_packages.join("uv").is_dir());
+            (python.exists() && has_uv).then(|| vec![python.to_string_lossy().to_string(), "-m".into(), "uv".into()])
+        });
+        from_env.or_else(|| Command::new("uv").arg("--version").output().ok().filter(|o| o.status.success()).map(|_| vec!["uv".into()]))
+    }
+
+    /// Run `uv --cache-dir <uv_dir> <args>`
+    fn run_uv(&self, uv: &[String], args: &[&str]) -> Result<()> {
+        let output = Command::new(&uv[0])
+            .args(&uv[1..])
+            .arg("--cache-dir")
+            .arg(self.uv_dir())
+            .args(args)
+            .output()
+            .map_err(|e| PortableSourceError::environment(format!("Failed to run uv: {}", e)))?;
+        if !output.status.success() {
+            return Err(PortableSourceError::environment(format!(
+                "uv {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Remove cache entries whose version no environment uses. uv can only drop a package
+    /// with all its versions, so its entries go only when no version is in use.
+    /// Returns the removed entries.
+    pub fn prune(&self, dry_run: bool) -> Result<Vec<CacheEntry>> {
+        let stats = self.stats()?;
+        let uv_dir = self.uv_dir();
+        let in_use: BTreeSet<&str> = stats.entries.iter().filter(|e| e.in_use).map(|e| e.distribution.0.as_str()).collect();
+        let (mut uv_entries, pip_entries): (Vec<CacheEntry>, Vec<CacheEntry>) = stats.unused()
+            .filter(|e| !e.path.starts_with(&uv_dir) || !in_use.contains(e.distribution.0.as_str()))
+            .cloned()
+            .partition(|e| e.path.starts_with(&uv_dir));
+        if dry_run {
+            return Ok(uv_entries.into_iter().chain(pip_entries).collect());
+        }
+
+        for entry in &pip_entries {
+            let result = if entry.path.is_dir() { fs::remove_dir_all(&entry.path) } else { fs::remove_file(&entry.path) };
+            result.map_err(|e| PortableSourceError::environment(format!("Failed to remove {:?}: {}", entry.path, e)))?;
+            // The HTTP cache keeps the response headers next to the body
+            if entry.path.extension().is_some_and(|e| e == "body") {
+                let _ = fs::remove_file(entry.path.with_extension(""));
+            }
+        }
+
+        if uv_dir.exists() {
+            match self.uv_command() {
+                Some(uv) => {
+                    let packages: BTreeSet<&str> = uv_entries.iter().map(|e| e.distribution.0.as_str()).collect();
+                    if !packages.is_empty() {
+                        let mut args = vec!["cache", "clean"];
+                        args.extend(packages);
+                        self.run_uv(&uv, &args)?;
+                    }
+                    self.run_uv(&uv, &["cache", "prune"])?;
+                }
+                None => {
+                    warn!("uv not found, the uv cache in {:?} was not pruned", uv_dir);
+                    uv_entries.clear();
+                }
+            }
+            uv_entries.retain(|e| !e.path.exists());
+        }
+        Ok(uv_entries.into_iter().chain(pip_entries).collect())
+    }
+
+    /// Remove the whole cache. Returns the freed size.
+    pub fn clear(&self) -> Result<u64> {
+        let dir = self.dir();
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let size = dir_size(&dir);
+        fs::remove_dir_all(&dir)
+            .map_err(|e| PortableSourceError::environment(format!("Failed to remove {:?}: {}", dir, e)))?;
+        Ok(size)
+    }
+}
+
+/// `torch-2.7.0+cu128` or `typing_extensions-4.12.2-py3-none-any` -> (`torch`, `2.7.0+cu128`)
+fn parse_distribution(stem: &str) -> Option<Distribution> {
+    let mut parts = stem.splitn(3, '-');
+    let name = parts.next().filter(|n| !n.is_empty())?;
+    let version = parts.next().filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))?;
+    Some((canonicalize_name(name), version.to_string()))
+}
+
+/// Distribution of a wheel file (whatever its name) from the `.dist-info` folder inside
+fn wheel_distribution(path: &Path) -> Option<Distribution> {
+    let archive = zip::ZipArchive::new(File::open(path).ok()?).ok()?;
+    let distribution = archive.file_names()
+        .find_map(|name| name.split('/').next()?.strip_suffix(".dist-info").and_then(parse_distribution));
+    distribution
+}
+
+fn find_site_packages(dir: &Path, depth: usize) -> Vec<PathBuf> {
+    if dir.file_name().is_some_and(|name| name == "site-packages") {
+        return vec![dir.to_path_buf()];
+    }
+    if depth == 0 {
+        return Vec::new();
+    }
+    fs::read_dir(dir).into_iter().flatten().flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .flat_map(|entry| find_site_packages(&entry.path(), depth - 1))
+        .collect()
+}
+
+fn find_files(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let Ok(file_type) = entry.file_type() else { continue };
+        if file_type.is_dir() {
+            files.extend(find_files(&entry.path(), extension));
+        } else if entry.path().extension().is_some_and(|e| e == extension) {
+            files.push(entry.path());
+        }
+    }
+    files
+}
+
+/// Size of a file or folder; symlinks are not followed
+fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else { return 0 };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path).into_iter().flatten().flatten()
+        .map(|entry| dir_size(&entry.path()))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_keeps_installed_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &[u8]| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        let mut wheel = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        wheel.start_file("pillow-10.4.0.dist-info/METADATA", zip::write::FileOptions::default()).unwrap();
+        let wheel = wheel.finish().unwrap().into_inner();
+
+        write("envs/demo/lib/python3.11/site-packages/Typing_Extensions-4.12.2.dist-info/METADATA", b"");
+        write("cache/uv/archive-v0/a1/typing_extensions-4.12.2.dist-info/METADATA", b"used");
+        write("cache/uv/archive-v0/b2/numpy-1.26.4.dist-info/METADATA", b"unused wheel");
+        write("cache/pip/wheels/ab/cd/insightface-0.7.3-cp311-cp311-linux_x86_64.whl", b"built");
+        write("cache/pip/http-v2/1/2/3f9a.body", &wheel);
+        write("cache/pip/http-v2/1/2/3f9a", b"headers");
+        write("cache/pip/http-v2/4/5/77c1.body", b"<html>simple index</html>");
+
+        let cache = PackageCache::new(dir.path());
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.entries.len(), 4);
+        assert_eq!(stats.by_package()[0].0, "pillow");
+        assert_eq!(stats.by_package()[1], ("numpy".to_string(), 12, false));
+
+        let names = |entries: Vec<CacheEntry>| {
+            let mut names: Vec<String> = entries.into_iter().map(|e| e.distribution.0).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(cache.prune(true).unwrap()), vec!["insightface", "numpy", "pillow"]);
+
+        // The uv side depends on a uv binary being around; the pip side is removed here
+        cache.prune(false).unwrap();
+        assert!(!dir.path().join("cache/pip/http-v2/1/2/3f9a.body").exists());
+        assert!(!dir.path().join("cache/pip/http-v2/1/2/3f9a").exists());
+        assert!(dir.path().join("cache/pip/http-v2/4/5/77c1.body").exists());
+        assert!(dir.path().join("cache/uv/archive-v0/a1").exists());
+
+        assert!(cache.clear().unwrap() > 0);
+        assert!(!cache.dir().exists());
+    }
+}
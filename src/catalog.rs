@@ -0,0 +1,435 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Repository catalog for PortableSource
+//!
+//! Describes repositories that can be installed by name. Entries come from
+//! several layers which are merged field by field, highest precedence first, except
+//! that `url`, `branch` and `main_file` describe one checkout and are taken together
+//! from the highest layer that sets `url`:
+//!
+//! 1. the local catalog (`<install_path>/catalog.toml`), edited by the user
+//!    directly or through `catalog add/remove`;
+//! 2. shared catalogs (`<install_path>/catalogs/*.toml|*.json` and any file or
+//!    directory listed in `PORTABLESOURCE_CATALOGS`), usually maintained by a team;
+//! 3. the answer of the PortableSource server;
+//! 4. the entries built into the binary.
+
+use crate::{Result, PortableSourceError};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with extra shared catalog files or directories
+pub const SHARED_CATALOGS_ENV: &str = "PORTABLESOURCE_CATALOGS";
+
+/// Single repository description
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_args: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl CatalogEntry {
+    /// Fill fields that are still empty from a lower-precedence entry. An entry with its
+    /// own `url` (e.g. a fork) keeps none of the lower branch and main file
+    pub fn merge_from(&mut self, lower: &CatalogEntry) {
+        if self.url.is_none() {
+            self.url = lower.url.clone();
+            if self.main_file.is_none() { self.main_file = lower.main_file.clone(); }
+            if self.branch.is_none() { self.branch = lower.branch.clone(); }
+        }
+        if self.program_args.is_none() { self.program_args = lower.program_args.clone(); }
+        if self.python_version.is_none() { self.python_version = lower.python_version.clone(); }
+        if self.description.is_none() { self.description = lower.description.clone(); }
+    }
+}
+
+/// On-disk catalog layout (TOML or JSON)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CatalogFile {
+    #[serde(default)]
+    pub repositories: BTreeMap<String, CatalogEntry>,
+}
+
+impl CatalogFile {
+    /// Load a catalog file; the format is chosen by extension (`.json`, otherwise TOML)
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut file: CatalogFile = if is_json(path) {
+            serde_json::from_str(&content)?
+        } else {
+            toml::from_str(&content)
+                .map_err(|e| PortableSourceError::config(format!("Failed to parse catalog {:?}: {}", path, e)))?
+        };
+        // Names are case-insensitive, like on the server
+        file.repositories = file.repositories
+            .into_iter()
+            .map(|(name, entry)| (name.to_lowercase(), entry))
+            .collect();
+        Ok(file)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string_pretty(self)
+                .map_err(|e| PortableSourceError::config(format!("Failed to serialize catalog: {}", e)))?
+        };
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Where a catalog entry came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CatalogSource {
+    Local,
+    Shared(PathBuf),
+    Server,
+    Builtin,
+}
+
+impl std::fmt::Display for CatalogSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatalogSource::Local => write!(f, "local"),
+            CatalogSource::Shared(path) => write!(f, "shared: {}", path.display()),
+            CatalogSource::Server => write!(f, "server"),
+            CatalogSource::Builtin => write!(f, "built-in"),
+        }
+    }
+}
+
+/// Merged view over the local, shared and built-in catalogs
+pub struct RepositoryCatalog {
+    local_path: PathBuf,
+    local: CatalogFile,
+    shared: Vec<(PathBuf, CatalogFile)>,
+    builtin: CatalogFile,
+}
+
+impl RepositoryCatalog {
+    /// Load all catalog layers for the given install path
+    pub fn load(install_path: &Path) -> Result<Self> {
+        let local_path = Self::local_catalog_path(install_path);
+        let local = if local_path.exists() {
+            CatalogFile::load(&local_path)?
+        } else {
+            CatalogFile::default()
+        };
+
+        let mut shared = Vec::new();
+        for path in shared_catalog_files(install_path) {
+            match CatalogFile::load(&path) {
+                Ok(file) => shared.push((path, file)),
+                Err(e) => warn!("Skipping shared catalog {:?}: {}", path, e),
+            }
+        }
+
+        Ok(Self { local_path, local, shared, builtin: builtin_catalog() })
+    }
+
+    /// Catalog with only the built-in entries (used when the local catalog cannot be read)
+    pub fn empty(install_path: &Path) -> Self {
+        Self {
+            local_path: Self::local_catalog_path(install_path),
+            local: CatalogFile::default(),
+            shared: Vec::new(),
+            builtin: builtin_catalog(),
+        }
+    }
+
+    /// Path of the user-editable catalog
+    pub fn local_catalog_path(install_path: &Path) -> PathBuf {
+        install_path.join("catalog.toml")
+    }
+
+    pub fn local_path(&self) -> &Path {
+        &self.local_path
+    }
+
+    /// Whether the local or a shared catalog defines the name (built-ins do not count)
+    pub fn is_user_defined(&self, name: &str) -> bool {
+        let key = name.to_lowercase();
+        self.local.repositories.contains_key(&key)
+            || self.shared.iter().any(|(_, file)| file.repositories.contains_key(&key))
+    }
+
+    /// Resolve a repository by name, merging the server answer at its precedence level
+    pub fn resolve(&self, name: &str, server: Option<CatalogEntry>) -> Option<CatalogEntry> {
+        let key = name.to_lowercase();
+        let mut layers: Vec<&CatalogEntry> = Vec::new();
+        if let Some(entry) = self.local.repositories.get(&key) {
+            layers.push(entry);
+        }
+        for (_, file) in &self.shared {
+            if let Some(entry) = file.repositories.get(&key) {
+                layers.push(entry);
+            }
+        }
+        if let Some(entry) = server.as_ref() {
+            layers.push(entry);
+        }
+        if let Some(entry) = self.builtin.repositories.get(&key) {
+            layers.push(entry);
+        }
+
+        let (first, rest) = layers.split_first()?;
+        let mut merged = (*first).clone();
+        for lower in rest {
+            merged.merge_from(lower);
+        }
+        Some(merged)
+    }
+
+    /// All known entries with their source; higher-precedence layers hide lower ones
+    pub fn list(&self) -> Vec<(String, CatalogEntry, CatalogSource)> {
+        let mut items: BTreeMap<String, (CatalogEntry, CatalogSource)> = BTreeMap::new();
+        for (name, entry) in &self.builtin.repositories {
+            items.insert(name.clone(), (entry.clone(), CatalogSource::Builtin));
+        }
+        for (path, file) in self.shared.iter().rev() {
+            for (name, entry) in &file.repositories {
+                items.insert(name.clone(), (entry.clone(), CatalogSource::Shared(path.clone())));
+            }
+        }
+        for (name, entry) in &self.local.repositories {
+            items.insert(name.clone(), (entry.clone(), CatalogSource::Local));
+        }
+        items.into_iter().map(|(name, (entry, source))| (name, entry, source)).collect()
+    }
+
+    /// Entries matching the query by substring (name, URL, description) or by a close name
+    pub fn search(&self, query: &str) -> Vec<(String, CatalogEntry, CatalogSource)> {
+        let mut scored: Vec<(usize, (String, CatalogEntry, CatalogSource))> = self.list()
+            .into_iter()
+            .filter_map(|item| {
+                let text = [item.1.url.as_deref(), item.1.description.as_deref()]
+                    .iter()
+                    .flatten()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                match_score(query, &item.0, &text).map(|score| (score, item))
+            })
+            .collect();
+        scored.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.0.cmp(&b.1.0)));
+        scored.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Add or replace an entry in the local catalog and save it
+    pub fn add_local(&mut self, name: &str, entry: CatalogEntry) -> Result<()> {
+        if entry.url.as_deref().map(str::trim).unwrap_or("").is_empty() {
+            return Err(PortableSourceError::config(format!("Catalog entry '{}' needs a repository URL", name)));
+        }
+        self.local.repositories.insert(name.to_lowercase(), entry);
+        self.local.save(&self.local_path)
+    }
+
+    /// Remove an entry from the local catalog; returns false if it was not there
+    pub fn remove_local(&mut self, name: &str) -> Result<bool> {
+        if self.local.repositories.remove(&name.to_lowercase()).is_none() {
+            return Ok(false);
+        }
+        self.local.save(&self.local_path)?;
+        Ok(true)
+    }
+}
+
+/// Rank how well `query` matches a repository; lower is better, `None` means no match.
+///
+/// Exact and prefix name matches come first, then substrings of the name or of
+/// the extra text (URL, description), then names within a small edit distance.
+pub fn match_score(query: &str, name: &str, text: &str) -> Option<usize> {
+    let query = query.trim().to_lowercase();
+    let name = name.to_lowercase();
+    if query.is_empty() {
+        return None;
+    }
+    if name == query {
+        return Some(0);
+    }
+    if name.starts_with(&query) {
+        return Some(1);
+    }
+    if name.contains(&query) {
+        return Some(2);
+    }
+    if text.to_lowercase().contains(&query) {
+        return Some(3);
+    }
+    let distance = levenshtein(&query, &name);
+    let allowed = (query.chars().count() / 3).max(2);
+    if distance <= allowed {
+        Some(4 + distance)
+    } else {
+        None
+    }
+}
+
+/// Names from `candidates` that look like a misspelling of `name`, best first
+pub fn suggest_names<'a, I>(name: &str, candidates: I, limit: usize) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut scored: Vec<(usize, String)> = candidates
+        .into_iter()
+        .filter_map(|c| match_score(name, c, "").map(|score| (score, c.to_lowercase())))
+        .collect();
+    scored.sort();
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored.into_iter().take(limit).map(|(_, n)| n).collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b_chars.len() + 1];
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b_chars.len()]
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+fn is_catalog_file(path: &Path) -> bool {
+    path.is_file() && path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("json") || e.eq_ignore_ascii_case("toml"))
+        .unwrap_or(false)
+}
+
+/// Shared catalog files in precedence order (install dir first, then the env var list)
+fn shared_catalog_files(install_path: &Path) -> Vec<PathBuf> {
+    let mut roots = vec![install_path.join("catalogs")];
+    if let Some(extra) = std::env::var_os(SHARED_CATALOGS_ENV) {
+        roots.extend(std::env::split_paths(&extra));
+    }
+
+    let mut files = Vec::new();
+    for root in roots {
+        if root.is_dir() {
+            let mut entries: Vec<PathBuf> = match fs::read_dir(&root) {
+                Ok(rd) => rd.flatten().map(|e| e.path()).filter(|p| is_catalog_file(p)).collect(),
+                Err(_) => continue,
+            };
+            entries.sort();
+            files.extend(entries);
+        } else if is_catalog_file(&root) {
+            files.push(root);
+        }
+    }
+    files
+}
+
+fn builtin_catalog() -> CatalogFile {
+    let mut file = CatalogFile::default();
+
+    file.repositories.insert("stable-diffusion-webui".to_string(), CatalogEntry {
+        url: Some("https://github.com/AUTOMATIC1111/stable-diffusion-webui.git".to_string()),
+        main_file: Some("webui.py".to_string()),
+        ..Default::default()
+    });
+
+    file.repositories.insert("comfyui".to_string(), CatalogEntry {
+        url: Some("https://github.com/comfyanonymous/ComfyUI.git".to_string()),
+        main_file: Some("main.py".to_string()),
+        ..Default::default()
+    });
+
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut catalog = RepositoryCatalog::load(dir.path()).unwrap();
+        catalog.add_local("ComfyUI", CatalogEntry {
+            url: Some("https://example.com/fork/ComfyUI.git".to_string()),
+            ..Default::default()
+        }).unwrap();
+
+        let server = CatalogEntry {
+            url: Some("https://example.com/server/ComfyUI.git".to_string()),
+            branch: Some("master".to_string()),
+            program_args: Some("--listen".to_string()),
+            ..Default::default()
+        };
+        // The fork keeps its own checkout: no branch or main file of the upstream entries
+        let merged = catalog.resolve("comfyui", Some(server.clone())).unwrap();
+        assert_eq!(merged.url.as_deref(), Some("https://example.com/fork/ComfyUI.git"));
+        assert_eq!(merged.program_args.as_deref(), Some("--listen"));
+        assert_eq!(merged.branch, None);
+        assert_eq!(merged.main_file, None);
+
+        // Without a URL of its own an entry only adjusts the upstream checkout
+        catalog.local.repositories.insert("comfyui".to_string(), CatalogEntry {
+            branch: Some("dev".to_string()),
+            ..Default::default()
+        });
+        let merged = catalog.resolve("comfyui", Some(server)).unwrap();
+        assert_eq!(merged.url.as_deref(), Some("https://example.com/server/ComfyUI.git"));
+        assert_eq!(merged.branch.as_deref(), Some("dev"));
+        assert_eq!(merged.main_file, None);
+        let merged = catalog.resolve("comfyui", None).unwrap();
+        assert_eq!(merged.url.as_deref(), Some("https://github.com/comfyanonymous/ComfyUI.git"));
+        assert_eq!(merged.main_file.as_deref(), Some("main.py"));
+
+        // The local catalog survives a reload
+        let reloaded = RepositoryCatalog::load(dir.path()).unwrap();
+        assert!(reloaded.list().iter().any(|(n, _, s)| n == "comfyui" && *s == CatalogSource::Local));
+        assert!(catalog.resolve("unknown-repo", None).is_none());
+    }
+
+    #[test]
+    fn test_suggest_names() {
+        let names = ["comfyui", "stable-diffusion-webui", "facefusion"];
+        assert_eq!(suggest_names("comfyiu", names, 3), vec!["comfyui".to_string()]);
+        assert_eq!(suggest_names("stable", names, 3), vec!["stable-diffusion-webui".to_string()]);
+        assert!(suggest_names("zzzzzz", names, 3).is_empty());
+    }
+}
@@ -32,6 +32,14 @@ pub struct Cli {
     #[arg(long)]
     pub install_path: Option<PathBuf>,
     
+    /// Record every command run by install-repo/update-repo (args, cwd, env, exit code, output) into a JSON transcript
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay_transcript")]
+    pub record_transcript: Option<PathBuf>,
+    
+    /// Answer install-repo/update-repo commands from a recorded transcript instead of running them
+    #[arg(long, global = true, value_name = "FILE")]
+    pub replay_transcript: Option<PathBuf>,
+    
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -39,7 +47,12 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Setup environment (Portable)
-    SetupEnv,
+    SetupEnv {
+        /// Install the tools from a directory made by fetch-tools, without network access;
+        /// each file is checked against the sha256 fetch-tools recorded for it
+        #[arg(long, value_name = "DIR")]
+        from_dir: Option<PathBuf>,
+    },
     
     /// Register installation path in registry (Unix only)
     #[cfg(unix)]
@@ -65,6 +78,59 @@ pub enum Commands {
         /// Python version to use (310, 311)
         #[arg(long)]
         python_ver: Option<String>,
+        /// Print the commands and files the install would run/create, without executing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Pin the repository to a branch
+        #[arg(long, group = "pin")]
+        branch: Option<String>,
+        /// Pin the repository to a tag
+        #[arg(long, group = "pin")]
+        tag: Option<String>,
+        /// Pin the repository to a commit
+        #[arg(long, group = "pin")]
+        commit: Option<String>,
+        /// Install exactly the package versions from the repository lockfile
+        #[arg(long)]
+        locked: bool,
+        /// Lockfile to install from (implies --locked)
+        #[arg(long, value_name = "FILE")]
+        lock_file: Option<PathBuf>,
+        /// Install these requirements files instead of the discovered ones (repeatable)
+        #[arg(long = "requirements", value_name = "FILE", conflicts_with_all = ["locked", "lock_file"])]
+        requirements: Vec<PathBuf>,
+        /// Extras or dependency groups from pyproject.toml / setup.cfg to install, e.g. gpu,ui
+        #[arg(long, value_name = "NAMES", value_delimiter = ',', conflicts_with_all = ["locked", "lock_file"])]
+        extras: Vec<String>,
+        /// PyTorch build: auto, cpu, cu118, cu121, cu124, cu126, cu128, rocm6.0..rocm6.4 or xpu (remembered for updates)
+        #[arg(long, value_name = "BACKEND")]
+        torch_backend: Option<String>,
+        /// Fail when pip check finds dependency conflicts after the install
+        #[arg(long)]
+        strict: bool,
+        /// Install without network access, only from the wheels in --wheelhouse (see download-wheels)
+        #[arg(long, requires = "wheelhouse")]
+        offline: bool,
+        /// Wheel folder to install from with --offline
+        #[arg(long, value_name = "DIR", requires = "offline")]
+        wheelhouse: Option<PathBuf>,
+    },
+    
+    /// Download the packages an installed repository needs on this machine into a wheelhouse,
+    /// for install-repo --offline on machines without network access
+    DownloadWheels {
+        /// Repository name
+        repo: String,
+        /// Wheel folder (default: wheelhouse/<repo> in the install path)
+        #[arg(long, value_name = "DIR")]
+        wheelhouse: Option<PathBuf>,
+    },
+    
+    /// Download the tools setup-env needs on this machine into a directory,
+    /// for setup-env --from-dir on machines without network access
+    FetchTools {
+        /// Target directory
+        dir: PathBuf,
     },
     
     /// Update repository (alias: ur)
@@ -72,6 +138,36 @@ pub enum Commands {
     UpdateRepo {
         /// Repository name (optional; if omitted, a TUI selector will be shown)
         repo: Option<String>,
+        /// Print the commands the update would run, without executing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Move the repository pin to this branch, tag or commit
+        #[arg(long, value_name = "REF", requires = "repo")]
+        to: Option<String>,
+        /// Discard local changes instead of stashing them
+        #[arg(long)]
+        force: bool,
+        /// Abort if the repository has local changes instead of stashing them
+        #[arg(long, conflicts_with = "force")]
+        no_stash: bool,
+        /// Reinstall exactly the package versions from the repository lockfile
+        #[arg(long)]
+        locked: bool,
+        /// Lockfile to install from (implies --locked)
+        #[arg(long, value_name = "FILE", requires = "repo")]
+        lock_file: Option<PathBuf>,
+        /// Install these requirements files instead of the discovered ones (repeatable)
+        #[arg(long = "requirements", value_name = "FILE", conflicts_with_all = ["locked", "lock_file"], requires = "repo")]
+        requirements: Vec<PathBuf>,
+        /// Extras or dependency groups from pyproject.toml / setup.cfg to install, e.g. gpu,ui
+        #[arg(long, value_name = "NAMES", value_delimiter = ',', conflicts_with_all = ["locked", "lock_file"], requires = "repo")]
+        extras: Vec<String>,
+        /// PyTorch build: auto, cpu, cu118, cu121, cu124, cu126, cu128, rocm6.0..rocm6.4 or xpu (remembered for updates)
+        #[arg(long, value_name = "BACKEND", requires = "repo")]
+        torch_backend: Option<String>,
+        /// Fail (and roll back) when pip check finds dependency conflicts after the update
+        #[arg(long)]
+        strict: bool,
     },
     
     /// Delete repository (alias: dr)
@@ -81,9 +177,38 @@ pub enum Commands {
         repo: String,
     },
     
+    /// Search repositories on the server and in the catalogs (alias: sr)
+    #[command(alias = "sr")]
+    SearchRepo {
+        /// Name or part of a name, URL or description
+        query: String,
+    },
+    
+    /// Show the validated server installation plan for a repository
+    ShowPlan {
+        /// Repository name
+        repo: String,
+        /// Print the plan as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
     /// List installed repositories (alias: lr)
     #[command(alias = "lr")]
-    ListRepos,
+    ListRepos {
+        /// Print the repository manifests as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Show what is installed for a repository: source, commit, Python, torch/onnx builds
+    Info {
+        /// Repository name
+        repo: String,
+        /// Print the manifest as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Run repository start script (alias: rr)
     #[command(alias = "rr")]
@@ -95,6 +220,24 @@ pub enum Commands {
         args: Vec<String>,
     },
     
+    /// Manage the local repository catalog
+    Catalog {
+        #[command(subcommand)]
+        action: CatalogAction,
+    },
+    
+    /// Manage repository lockfiles
+    Lock {
+        #[command(subcommand)]
+        action: LockAction,
+    },
+    
+    /// Manage the shared uv/pip package cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    
     /// Show system information
     SystemInfo,
     
@@ -131,6 +274,68 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum CatalogAction {
+    /// Add or replace a repository in the local catalog
+    Add {
+        /// Repository name used with install-repo
+        name: String,
+        /// Git URL of the repository
+        #[arg(long)]
+        url: String,
+        /// Main file to launch (relative to the repository root)
+        #[arg(long)]
+        main_file: Option<String>,
+        /// Arguments passed to the main file
+        #[arg(long, allow_hyphen_values = true)]
+        program_args: Option<String>,
+        /// Branch to clone
+        #[arg(long)]
+        branch: Option<String>,
+        /// Python version to use (310, 311)
+        #[arg(long)]
+        python_ver: Option<String>,
+        /// Short description shown by search-repo
+        #[arg(long)]
+        description: Option<String>,
+    },
+    
+    /// Remove a repository from the local catalog
+    Remove {
+        /// Repository name
+        name: String,
+    },
+    
+    /// List repositories known to the local, shared and built-in catalogs
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum LockAction {
+    /// Export the lockfile of an installed repository to share it with a team
+    Export {
+        /// Repository name
+        repo: String,
+        /// Output file (default: portablesource.lock.json in the repository folder, ready to commit)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Show cache size by package
+    Stats,
+    /// Remove cached packages that no environment uses
+    Prune {
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove the whole cache
+    Clear,
+}
+
 impl Cli {
     /// Parse command line arguments
     pub fn parse_args() -> Self {
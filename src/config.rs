@@ -53,6 +53,12 @@ impl PythonVersion {
             _ => None,
         }
     }
+
+    /// Portable Python matching an interpreter version (3.10.x -> 310)
+    pub fn from_interpreter_version(version: &str) -> Option<Self> {
+        let mut parts = version.split('.');
+        Self::from_str(&format!("{}{}", parts.next()?, parts.next()?))
+    }
 }
 
 impl Default for PythonVersion {
@@ -61,6 +67,77 @@ impl Default for PythonVersion {
     }
 }
 
+/// PyTorch build to install (`--torch-backend`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TorchBackend {
+    /// Chosen from the detected GPU and CUDA installation
+    #[default]
+    Auto,
+    Cpu,
+    /// CUDA build, e.g. 124 for cu124
+    Cuda(u32),
+    /// ROCm build, e.g. "6.2" for rocm6.2
+    Rocm(String),
+    /// Intel GPUs
+    Xpu,
+}
+
+/// CUDA builds published on the PyTorch index
+pub const TORCH_CUDA_VERSIONS: &[u32] = &[118, 121, 124, 126, 128];
+/// ROCm build used when the installed ROCm version is unknown or has no torch build
+pub const DEFAULT_ROCM_VERSION: &str = "6.3";
+pub const TORCH_ROCM_VERSIONS: &[&str] = &["6.0", "6.1", "6.2", "6.2.4", "6.3", "6.4"];
+
+impl TorchBackend {
+    /// Parse `auto`, `cpu`, `cu118`..`cu128`, `rocm6.0`..`rocm6.4` or `xpu`
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "auto" => return Some(TorchBackend::Auto),
+            "cpu" => return Some(TorchBackend::Cpu),
+            "xpu" => return Some(TorchBackend::Xpu),
+            _ => {}
+        }
+        if let Some(version) = s.strip_prefix("cu").and_then(|v| v.parse::<u32>().ok()) {
+            return TORCH_CUDA_VERSIONS.contains(&version).then_some(TorchBackend::Cuda(version));
+        }
+        let version = s.strip_prefix("rocm")?;
+        TORCH_ROCM_VERSIONS.contains(&version).then(|| TorchBackend::Rocm(version.to_string()))
+    }
+
+    /// PyTorch index for the backend; `None` in auto mode
+    pub fn index_url(&self) -> Option<String> {
+        match self {
+            TorchBackend::Auto => None,
+            backend => Some(format!("https://download.pytorch.org/whl/{}", backend)),
+        }
+    }
+
+    /// ROCm build matching the ROCm installation in /opt/rocm
+    pub fn detect_rocm() -> Self {
+        let installed = std::fs::read_to_string("/opt/rocm/.info/version").ok().and_then(|version| {
+            let mut parts = version.trim().split(['.', '-']);
+            Some(format!("{}.{}", parts.next()?, parts.next()?))
+        });
+        match installed {
+            Some(version) if TORCH_ROCM_VERSIONS.contains(&version.as_str()) => TorchBackend::Rocm(version),
+            _ => TorchBackend::Rocm(DEFAULT_ROCM_VERSION.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for TorchBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TorchBackend::Auto => write!(f, "auto"),
+            TorchBackend::Cpu => write!(f, "cpu"),
+            TorchBackend::Cuda(version) => write!(f, "cu{}", version),
+            TorchBackend::Rocm(version) => write!(f, "rocm{}", version),
+            TorchBackend::Xpu => write!(f, "xpu"),
+        }
+    }
+}
+
 // Constants
 pub const SERVER_DOMAIN: &str = "server.portables.dev";
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -106,39 +183,16 @@ pub enum CudaVersionLinux {
 }
 
 impl CudaVersion {
-    pub fn get_download_url(&self) -> &'static str {
+    /// Name of the CUDA archive in the tool manifest
+    pub fn tool_name(&self) -> &'static str {
         match self {
-            CudaVersion::Cuda118 => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/Tl1V3xhXqOG5Eg",
-            CudaVersion::Cuda124 => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/8xp_2uTZ5HtHIw",
-            CudaVersion::Cuda128 => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/W85WVAi_6kdcJQ",
+            CudaVersion::Cuda118 => "cuda118",
+            CudaVersion::Cuda124 => "cuda124",
+            CudaVersion::Cuda128 => "cuda128",
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum ToolLinks {
-    Git,
-    Ffmpeg,
-    Python(PythonVersion),
-    MsvcBuildTools,
-    // SevenZip удален, так как перешли на tar zstd
-}
-
-impl ToolLinks {
-    pub fn url(&self) -> &'static str {
-        match self {
-            ToolLinks::Git => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/BQx3WpS_9KERWg",
-            ToolLinks::Ffmpeg => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/M3gb4nZAqSUhRQ",
-            ToolLinks::Python(PythonVersion::Python310) => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/q4ipYM52CdLthA",
-            ToolLinks::Python(PythonVersion::Python311) => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/frTjUovZLkujiA",
-            ToolLinks::MsvcBuildTools => "https://getfile.dokpub.com/yandex/get/https://disk.yandex.ru/d/LwUqbpnI7lsxyg",
-            // ToolLinks::SevenZip больше не используется, так как перешли на tar zstd
-        }
-    }
-}
-
-
-
 // GpuConfig removed - all GPU parameters are now computed dynamically
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -485,16 +539,6 @@ impl ConfigManager {
     }
     
 
-     pub fn get_cuda_download_link(&self, cuda_version: Option<&CudaVersion>) -> Option<String> {
-         let version = if let Some(v) = cuda_version {
-             v.clone()
-         } else {
-             self.get_cuda_version()?
-         };
-         
-         Some(version.get_download_url().to_string())
-     }
-     
      pub fn msvc_bt_config(&self) -> (String, String) {
          // Не используется больше для финального списка; оставлено для совместимости
          ("https://aka.ms/vs/17/release/vs_buildtools.exe".to_string(), String::new())
@@ -0,0 +1,701 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Downloads of tool archives and binaries.
+//!
+//! An [`Artifact`] has an ordered list of mirror URLs and optionally a SHA-256.
+//! Data goes to `<destination>.part` and is moved to the destination only once it is
+//! complete and verified. A partial file is resumed with a `Range` request guarded by
+//! `If-Range` (the ETag / Last-Modified of the original response is kept in
+//! `<destination>.part.validator`), so a file that changed on the server is never
+//! stitched together with an old prefix. Transient failures (network errors, 5xx, 408,
+//! 429, checksum mismatch) are retried with exponential backoff; other HTTP errors move
+//! on to the next mirror. `file://` URLs are copied and must come with a sha256, so a
+//! directory of pre-downloaded archives goes through the same checks.
+
+use crate::{Result, PortableSourceError};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Upper bound for the delay between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A file to download
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Artifact {
+    /// Name shown in progress and errors
+    pub name: String,
+    /// Mirrors, tried in order
+    pub urls: Vec<String>,
+    /// Expected SHA-256 (hex); not verified when `None`
+    pub sha256: Option<String>,
+}
+
+impl Artifact {
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self { name: name.into(), urls: vec![url.into()], sha256: None }
+    }
+
+    pub fn with_mirror(mut self, url: impl Into<String>) -> Self {
+        self.urls.push(url.into());
+        self
+    }
+
+    pub fn with_sha256(mut self, sha256: Option<String>) -> Self {
+        self.sha256 = sha256.map(|s| s.trim().to_lowercase());
+        self
+    }
+}
+
+/// Why a single attempt failed
+enum Failure {
+    /// Worth retrying the same URL
+    Transient(String),
+    /// The URL will not work, go to the next mirror
+    Permanent(String),
+}
+
+/// Bars of several downloads and extractions in one view, above an overall bar that
+/// counts the bytes of all of them
+#[derive(Clone)]
+pub struct SharedProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    /// Part of the overall length estimated up front and not yet claimed by [`Self::expect`]
+    reserved: Arc<AtomicU64>,
+}
+
+impl SharedProgress {
+    pub fn new(multi: MultiProgress, overall: ProgressBar) -> Self {
+        let overall = multi.add(overall);
+        Self { multi, overall, reserved: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn overall(&self) -> &ProgressBar {
+        &self.overall
+    }
+
+    /// Size the overall bar before the work starts
+    pub fn reserve(&self, bytes: u64) {
+        self.reserved.fetch_add(bytes, Ordering::SeqCst);
+        self.overall.inc_length(bytes);
+    }
+
+    /// `bytes` more work on the overall bar; taken out of the reserved estimate while it lasts
+    pub fn expect(&self, bytes: u64) {
+        let reserved = self.reserved
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |r| Some(r.saturating_sub(bytes)))
+            .unwrap_or(0);
+        self.overall.inc_length(bytes.saturating_sub(reserved));
+    }
+
+    /// Undo an [`Self::expect`] of `bytes`, `done` of them counted, for work that is redone
+    /// next; the length stays so the next attempt claims it again
+    pub fn retract(&self, bytes: u64, done: u64) {
+        self.reserved.fetch_add(bytes, Ordering::SeqCst);
+        self.overall.set_position(self.overall.position().saturating_sub(done));
+    }
+
+    /// Show `pb` above the overall bar
+    pub fn add(&self, pb: ProgressBar) -> ProgressBar {
+        self.multi.insert_before(&self.overall, pb)
+    }
+
+    /// Print a line above the bars
+    pub fn println(&self, msg: &str) {
+        let _ = self.multi.println(msg);
+    }
+}
+
+pub struct Downloader {
+    retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+    progress: Option<SharedProgress>,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Downloader {
+    pub fn new() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(600),
+            progress: None,
+        }
+    }
+
+    /// Retries per mirror after the first attempt
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Delay before the first retry; doubled for every further one
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Draw the progress into a shared view instead of a standalone bar
+    pub fn with_progress(mut self, progress: SharedProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Download `artifact` to `destination`, trying the mirrors in order
+    pub fn download(&self, artifact: &Artifact, destination: &Path) -> Result<()> {
+        // The blocking client runs its own runtime, which must not be created or dropped on an async worker
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return tokio::task::block_in_place(|| self.download_blocking(artifact, destination));
+        }
+        self.download_blocking(artifact, destination)
+    }
+
+    fn download_blocking(&self, artifact: &Artifact, destination: &Path) -> Result<()> {
+        if artifact.urls.is_empty() {
+            return Err(PortableSourceError::environment(format!("No download URL for {}", artifact.name)));
+        }
+        // A local copy is trusted only against a checksum from the manifest
+        if artifact.sha256.is_none() && artifact.urls.iter().any(|url| local_path(url).is_some()) {
+            return Err(PortableSourceError::environment(format!(
+                "{} is taken from a local file but has no sha256 in the tool manifest; fetch it again with fetch-tools, or add its version, urls and sha256 to tools.toml",
+                artifact.name
+            )));
+        }
+        if let Some(parent) = destination.parent() { fs::create_dir_all(parent)?; }
+        let client = Client::builder().timeout(self.timeout).build()?;
+
+        if self.is_downloaded(&client, artifact, destination)? {
+            let msg = format!("[Setup] {} already downloaded.", file_label(destination));
+            match &self.progress {
+                Some(progress) => {
+                    let len = destination.metadata()?.len();
+                    progress.expect(len);
+                    progress.overall().inc(len);
+                    progress.println(&msg);
+                }
+                None => println!("{}", msg),
+            }
+            return Ok(());
+        }
+
+        let part = part_path(destination);
+        let mut last_error = String::new();
+        for (index, url) in artifact.urls.iter().enumerate() {
+            if index > 0 {
+                log::warn!("Download of {} failed: {} — trying mirror {}", artifact.name, last_error, url);
+                // The partial file belongs to the previous mirror
+                remove_partial(&part);
+            }
+            for attempt in 0..=self.retries {
+                if attempt > 0 {
+                    let delay = self.backoff_delay(attempt);
+                    log::warn!(
+                        "Download of {} failed: {} — retry {}/{} in {:.1}s",
+                        artifact.name, last_error, attempt, self.retries, delay.as_secs_f64()
+                    );
+                    std::thread::sleep(delay);
+                }
+                match self.fetch(&client, url, &part, destination).and_then(|_| verify(artifact, &part)) {
+                    Ok(()) => {
+                        if destination.exists() { fs::remove_file(destination)?; }
+                        fs::rename(&part, destination)?;
+                        let _ = fs::remove_file(validator_path(&part));
+                        return Ok(());
+                    }
+                    Err(Failure::Transient(e)) => last_error = e,
+                    Err(Failure::Permanent(e)) => {
+                        last_error = e;
+                        break;
+                    }
+                }
+            }
+        }
+        remove_partial(&part);
+        Err(PortableSourceError::environment(format!(
+            "Failed to download {}: {}", artifact.name, last_error
+        )))
+    }
+
+    /// Size of `artifact` as its first source reports it: the file size for `file://`,
+    /// otherwise the Content-Length of a HEAD request
+    pub fn content_length(&self, artifact: &Artifact) -> Option<u64> {
+        let url = artifact.urls.first()?;
+        if let Some(path) = local_path(url) {
+            return fs::metadata(path).ok().map(|m| m.len());
+        }
+        let head = || {
+            let client = Client::builder().timeout(self.timeout).build().ok()?;
+            head_length(&client.head(url).send().ok()?.error_for_status().ok()?)
+        };
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return tokio::task::block_in_place(head);
+        }
+        head()
+    }
+
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_BACKOFF)
+    }
+
+    /// An existing destination is kept if it matches the checksum, or without one,
+    /// the size the first mirror reports
+    fn is_downloaded(&self, client: &Client, artifact: &Artifact, destination: &Path) -> Result<bool> {
+        if !destination.is_file() {
+            return Ok(false);
+        }
+        let matches = match &artifact.sha256 {
+            Some(expected) => &sha256_file(destination)? == expected,
+            None => {
+                let existing_len = destination.metadata()?.len();
+                client.head(&artifact.urls[0]).send().ok()
+                    .and_then(|resp| head_length(&resp))
+                    .is_some_and(|total| total == existing_len)
+            }
+        };
+        Ok(matches)
+    }
+
+    /// One attempt: resume or start `part` from `url`
+    fn fetch(&self, client: &Client, url: &str, part: &Path, destination: &Path) -> std::result::Result<(), Failure> {
+        if let Some(path) = local_path(url) {
+            return self.copy_local(&path, part, destination);
+        }
+        let existing_len = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        let validator = fs::read_to_string(validator_path(part)).ok().filter(|v| !v.is_empty());
+        // Without a validator there is no way to know the partial file is from the same version
+        let resume = existing_len > 0 && validator.is_some();
+
+        let mut request = client.get(url);
+        if let (true, Some(validator)) = (resume, &validator) {
+            request = request.header(RANGE, format!("bytes={}-", existing_len)).header(IF_RANGE, validator.as_str());
+        }
+        let mut resp = request.send().map_err(|e| Failure::Transient(e.to_string()))?;
+        let status = resp.status();
+
+        let (mut file, offset, total) = if status == StatusCode::PARTIAL_CONTENT && resume {
+            let range = resp.headers().get(CONTENT_RANGE).and_then(|hv| hv.to_str().ok()).and_then(parse_content_range);
+            match range {
+                Some((start, total)) if start == existing_len && total.is_none_or(|t| t >= existing_len) => {
+                    let file = OpenOptions::new().append(true).open(part).map_err(io_failure)?;
+                    (file, existing_len, total)
+                }
+                _ => {
+                    remove_partial(part);
+                    return Err(Failure::Transient(format!("server resumed at an unexpected offset ({})", url)));
+                }
+            }
+        } else if status.is_success() {
+            // Full body: the server ignored the range or the file changed since the partial download
+            let validator = resp.headers().get(ETAG).or_else(|| resp.headers().get(LAST_MODIFIED))
+                .and_then(|hv| hv.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+            fs::write(validator_path(part), validator).map_err(io_failure)?;
+            let file = File::create(part).map_err(io_failure)?;
+            let total = resp.content_length();
+            (file, 0, total)
+        } else if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            remove_partial(part);
+            return Err(Failure::Transient(format!("partial file does not match {}", url)));
+        } else if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Failure::Transient(format!("HTTP {} from {}", status, url)));
+        } else {
+            return Err(Failure::Permanent(format!("HTTP {} from {}", status, url)));
+        };
+
+        let label = file_label(destination);
+        let pb = self.progress_bar(total, &format!("Downloading {}", label));
+        let downloaded = copy_with_progress(&mut resp, &mut file, offset, total, &pb, self.overall());
+        pb.finish_with_message(format!("Downloaded {}", label));
+        downloaded.map_err(|e| match e {
+            Failure::Transient(e) => Failure::Transient(format!("{} ({})", e, url)),
+            permanent => permanent,
+        })?;
+        Ok(())
+    }
+
+    /// Copy a `file://` source; a missing file will not appear on retry
+    fn copy_local(&self, source: &Path, part: &Path, destination: &Path) -> std::result::Result<(), Failure> {
+        let mut input = File::open(source).map_err(|e| Failure::Permanent(format!("{}: {}", source.display(), e)))?;
+        let total = input.metadata().map(|m| m.len()).ok();
+        remove_partial(part);
+        let mut file = File::create(part).map_err(io_failure)?;
+        let label = file_label(destination);
+        let pb = self.progress_bar(total, &format!("Copying {}", label));
+        let copied = copy_with_progress(&mut input, &mut file, 0, total, &pb, self.overall());
+        pb.finish_with_message(format!("Copied {}", label));
+        copied.map(|_| ())
+    }
+
+    fn progress_bar(&self, total: Option<u64>, prefix: &str) -> ProgressBar {
+        let pb = create_download_progress_bar(total, prefix);
+        match &self.progress {
+            Some(progress) => progress.add(pb),
+            None => pb,
+        }
+    }
+
+    fn overall(&self) -> Option<&SharedProgress> {
+        self.progress.as_ref()
+    }
+}
+
+/// Content-Length of a HEAD response; `Response::content_length` is the size of the (empty) body
+fn head_length(resp: &reqwest::blocking::Response) -> Option<u64> {
+    resp.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok().filter(|len| *len > 0)
+}
+
+/// Path of a `file://` URL
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    let url = url::Url::parse(url).ok().filter(|u| u.scheme() == "file")?;
+    url.to_file_path().ok()
+}
+
+/// `file://` URL of an absolute path
+pub fn file_url(path: &Path) -> Option<String> {
+    url::Url::from_file_path(path).ok().map(String::from)
+}
+
+/// Check the completed partial file; a mismatch discards it so the next attempt starts over
+fn verify(artifact: &Artifact, part: &Path) -> std::result::Result<(), Failure> {
+    let Some(expected) = &artifact.sha256 else { return Ok(()) };
+    let actual = sha256_file(part).map_err(|e| Failure::Transient(e.to_string()))?;
+    if &actual != expected {
+        remove_partial(part);
+        return Err(Failure::Transient(format!(
+            "SHA-256 mismatch for {}: expected {}, got {}", artifact.name, expected, actual
+        )));
+    }
+    Ok(())
+}
+
+/// SHA-256 of a file as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Returns the size of the file after the copy; stops on the first read or write error
+/// or when the source ends early. `overall` gets the bytes of this copy, and gives them
+/// back if it fails, so a retry is not counted twice.
+fn copy_with_progress(resp: &mut impl Read, file: &mut File, offset: u64, total: Option<u64>, pb: &ProgressBar, overall: Option<&SharedProgress>) -> std::result::Result<u64, Failure> {
+    let expected = total.map(|t| t.saturating_sub(offset));
+    if let (Some(overall), Some(expected)) = (overall, expected) {
+        overall.expect(expected);
+    }
+    let mut downloaded = offset;
+    let copied = copy_chunks(resp, file, &mut downloaded, offset, total, pb, overall);
+    if let (Err(_), Some(overall)) = (&copied, overall) {
+        let received = downloaded - offset;
+        overall.retract(expected.unwrap_or(received), received);
+    }
+    copied.map(|_| downloaded)
+}
+
+fn copy_chunks(resp: &mut impl Read, file: &mut File, downloaded: &mut u64, offset: u64, total: Option<u64>, pb: &ProgressBar, overall: Option<&SharedProgress>) -> std::result::Result<(), Failure> {
+    let start = Instant::now();
+    pb.set_position(total.map_or(offset, |t| offset.min(t)));
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = resp.read(&mut buf).map_err(|e| Failure::Transient(e.to_string()))?;
+        if n == 0 { break; }
+        file.write_all(&buf[..n]).map_err(io_failure)?;
+        *downloaded += n as u64;
+        pb.set_position(total.map_or(*downloaded, |t| (*downloaded).min(t)));
+        if let Some(overall) = overall {
+            // Without a known size the work grows with what arrives
+            if total.is_none() { overall.expect(n as u64); }
+            overall.overall().inc(n as u64);
+        }
+        update_download_pb_message(pb, *downloaded - offset, *downloaded, total, start);
+    }
+    file.flush().map_err(io_failure)?;
+    if let Some(total) = total {
+        if *downloaded != total {
+            return Err(Failure::Transient(format!("got {} of {} bytes", downloaded, total)));
+        }
+    }
+    Ok(())
+}
+
+/// Local I/O problems will not go away by retrying
+fn io_failure(e: std::io::Error) -> Failure {
+    Failure::Permanent(e.to_string())
+}
+
+fn part_path(destination: &Path) -> PathBuf {
+    let mut name = destination.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    destination.with_file_name(name)
+}
+
+fn validator_path(part: &Path) -> PathBuf {
+    let mut name = part.file_name().unwrap_or_default().to_os_string();
+    name.push(".validator");
+    part.with_file_name(name)
+}
+
+fn remove_partial(part: &Path) {
+    let _ = fs::remove_file(part);
+    let _ = fs::remove_file(validator_path(part));
+}
+
+fn file_label(path: &Path) -> String {
+    path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "download".into())
+}
+
+/// `bytes start-end/total` -> (start, total); total is `None` for `*`
+fn parse_content_range(hv: &str) -> Option<(u64, Option<u64>)> {
+    let range = hv.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let start = span.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+// ===== Progress helpers =====
+fn create_download_progress_bar(total_opt: Option<u64>, prefix: &str) -> ProgressBar {
+    match total_opt {
+        Some(total) if total > 0 => {
+            let pb = ProgressBar::new(total);
+            let style = ProgressStyle::with_template("{prefix:.bold} [{bar:40.cyan/blue}] {percent:>3}% {msg} ETA {eta}")
+                .unwrap()
+                .progress_chars("=>-");
+            pb.set_style(style);
+            pb.set_prefix(prefix.to_string());
+            pb
+        }
+        _ => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(ProgressStyle::with_template("{prefix:.bold} {spinner} {msg}").unwrap());
+            pb.set_prefix(prefix.to_string());
+            pb.enable_steady_tick(std::time::Duration::from_millis(120));
+            pb
+        }
+    }
+}
+
+/// `received` is what this attempt transferred (for the speed), `downloaded` the file size so far
+fn update_download_pb_message(pb: &ProgressBar, received: u64, downloaded: u64, total_opt: Option<u64>, start: Instant) {
+    let elapsed = start.elapsed().as_secs_f64();
+    let mb_downloaded = bytes_to_mb(downloaded);
+    let speed_mb_s = if elapsed > 0.0 { bytes_to_mb((received as f64 / elapsed) as u64) } else { 0.0 };
+    let msg = match total_opt {
+        Some(total) if total > 0 => {
+            let total_mb = bytes_to_mb(total);
+            format!("{:.2} MB/{:.2} MB @ {:.2} MB/s", mb_downloaded, total_mb, speed_mb_s)
+        }
+        _ => format!("{:.2} MB @ {:.2} MB/s", mb_downloaded, speed_mb_s),
+    };
+    pb.set_message(msg);
+}
+
+fn bytes_to_mb(bytes: u64) -> f64 {
+    (bytes as f64) / 1_000_000.0
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Minimal HTTP server with ETag `"v1"`: `/missing` is 404, `/data` serves `body` and honours
+    /// `Range` unless `If-Range` names another version, `/shifted` answers a range from the wrong
+    /// offset. Returns the base URL and the header lines of every request received.
+    pub(crate) fn serve(body: &'static [u8]) -> (String, Arc<Mutex<Vec<Vec<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line.trim().is_empty() { break; }
+                    headers.push(line.trim().to_lowercase());
+                }
+                let header = |name: &str| headers.iter().find_map(|h| h.strip_prefix(name).map(str::to_string));
+                let range_start = header("range: bytes=").and_then(|v| v.trim_end_matches('-').parse::<usize>().ok())
+                    .filter(|_| header("if-range: ").is_none_or(|v| v == "\"v1\""));
+                received.lock().unwrap().push(headers.clone());
+
+                let mut stream = stream;
+                let response = if request_line.contains("/missing") {
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+                } else {
+                    let start = if request_line.contains("/shifted") { range_start.map(|_| 0) } else { range_start };
+                    let status = if start.is_some() { "206 Partial Content" } else { "200 OK" };
+                    let start = start.unwrap_or(0);
+                    let mut response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                        status, body.len() - start, start, body.len() - 1, body.len()
+                    ).into_bytes();
+                    if !request_line.starts_with("HEAD") { response.extend_from_slice(&body[start..]); }
+                    response
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    fn sha256_of(body: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(body);
+        format!("{:x}", hasher.finalize())
+    }
+
+    #[test]
+    fn test_download_mirrors_and_checksum() {
+        let body: &'static [u8] = b"portable tool archive contents";
+        let (base, _) = serve(body);
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool.tar.zst");
+        let part = part_path(&destination);
+
+        // A partial file of the failed mirror is not resumed from the next one
+        fs::write(&part, b"XXXXXXXX").unwrap();
+        fs::write(validator_path(&part), "\"v1\"").unwrap();
+        let artifact = Artifact::new("tool", format!("{}/missing", base))
+            .with_mirror(format!("{}/data", base))
+            .with_sha256(Some(sha256_of(body).to_uppercase()));
+        let downloader = Downloader::new().with_retries(2).with_backoff(Duration::from_millis(1));
+        downloader.download(&artifact, &destination).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), body);
+        assert!(!part.exists());
+        assert!(!validator_path(&part).exists());
+
+        // Verified file is kept as is
+        downloader.download(&artifact, &destination).unwrap();
+
+        let wrong = artifact.clone().with_sha256(Some("00".repeat(32)));
+        fs::remove_file(&destination).unwrap();
+        let err = downloader.download(&wrong, &destination).unwrap_err().to_string();
+        assert!(err.contains("SHA-256 mismatch"), "{}", err);
+        assert!(!destination.exists() && !part.exists());
+
+        // A local copy needs a checksum to be trusted
+        let source = dir.path().join("source.tar.zst");
+        fs::write(&source, body).unwrap();
+        let local = Artifact::new("tool", file_url(&source).unwrap());
+        let err = downloader.download(&local, &destination).unwrap_err().to_string();
+        assert!(err.contains("no sha256"), "{}", err);
+        downloader.download(&local.with_sha256(Some(sha256_of(body))), &destination).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), body);
+
+        assert_eq!(downloader.content_length(&Artifact::new("tool", format!("{}/data", base))), Some(body.len() as u64));
+
+        // Work sized up front is claimed before the overall bar grows
+        let hidden = indicatif::ProgressDrawTarget::hidden;
+        let progress = SharedProgress::new(MultiProgress::with_draw_target(hidden()), ProgressBar::with_draw_target(Some(0), hidden()));
+        progress.reserve(100);
+        progress.expect(60);
+        assert_eq!(progress.overall().length(), Some(100));
+        progress.expect(60);
+        assert_eq!(progress.overall().length(), Some(120));
+
+        assert_eq!(parse_content_range("bytes 8-29/30"), Some((8, Some(30))));
+        assert_eq!(downloader.backoff_delay(3), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_download_resumes_with_matching_validator() {
+        let body: &'static [u8] = b"portable tool archive contents";
+        let (base, requests) = serve(body);
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool.tar.zst");
+        let part = part_path(&destination);
+        let artifact = Artifact::new("tool", format!("{}/data", base)).with_sha256(Some(sha256_of(body)));
+        let downloader = Downloader::new().with_retries(1).with_backoff(Duration::from_millis(1));
+
+        fs::write(&part, &body[..8]).unwrap();
+        fs::write(validator_path(&part), "\"v1\"").unwrap();
+        downloader.download(&artifact, &destination).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), body);
+        let sent = requests.lock().unwrap().pop().unwrap();
+        assert!(sent.contains(&"range: bytes=8-".to_string()), "{:?}", sent);
+        assert!(sent.contains(&"if-range: \"v1\"".to_string()), "{:?}", sent);
+
+        // A 206 that does not start at the end of the partial file is thrown away and retried in full
+        fs::remove_file(&destination).unwrap();
+        fs::write(&part, &body[..8]).unwrap();
+        fs::write(validator_path(&part), "\"v1\"").unwrap();
+        let shifted = Artifact::new("tool", format!("{}/shifted", base)).with_sha256(Some(sha256_of(body)));
+        downloader.download(&shifted, &destination).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), body);
+        let sent = requests.lock().unwrap().clone();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].contains(&"range: bytes=8-".to_string()), "{:?}", sent[0]);
+        assert!(!sent[1].iter().any(|h| h.starts_with("range:")), "{:?}", sent[1]);
+    }
+
+    #[test]
+    fn test_download_restarts_when_validator_changed() {
+        let body: &'static [u8] = b"portable tool archive contents";
+        let (base, requests) = serve(body);
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("tool.tar.zst");
+        let part = part_path(&destination);
+
+        // The partial file is from an older version: the server ignores the range and sends it all
+        fs::write(&part, b"old version").unwrap();
+        fs::write(validator_path(&part), "\"v0\"").unwrap();
+        let artifact = Artifact::new("tool", format!("{}/data", base)).with_sha256(Some(sha256_of(body)));
+        Downloader::new().with_retries(0).download(&artifact, &destination).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), body);
+        assert!(!part.exists() && !validator_path(&part).exists());
+
+        let sent = requests.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains(&"range: bytes=11-".to_string()), "{:?}", sent[0]);
+        assert!(sent[0].contains(&"if-range: \"v0\"".to_string()), "{:?}", sent[0]);
+    }
+}
@@ -21,25 +21,43 @@
 //! For PySM
 
 use crate::{Result, PortableSourceError};
-use crate::config::{ConfigManager, ToolLinks};
-use std::fs::{self, OpenOptions};
-use std::io::{self, Seek, SeekFrom, Read, Write};
+use crate::config::ConfigManager;
+use crate::archive;
+use crate::cache::PackageCache;
+use crate::downloader::{Downloader, SharedProgress};
+use crate::tools::{ArchiveFormat, InstalledTools, PortableToolSpec, ToolManifest};
+use std::fs;
+use std::io;
 use std::path::Path;
 use std::process::{Command, Stdio};
 use crate::gpu::GpuDetector;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::time::Instant;
-
-#[derive(Clone, Debug)]
-struct PortableToolSpec {
-    name: String,
-    url: String,
-    extract_path: String,
-    executable_path: String,
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+
+/// Progress callback of `setup_environment_with_progress`: `(tool_key, steps_done, total_steps)`
+type StepCallback<'a> = dyn Fn(String, usize, usize) + Send + Sync + 'a;
+
+/// Tools downloaded and unpacked at the same time by setup-env
+const MAX_PARALLEL_TOOL_INSTALLS: usize = 3;
+
+/// A tool to install; `key` is what the progress callback and the summary show
+struct ToolJob<'a> {
+    key: String,
+    spec: &'a PortableToolSpec,
+    /// CUDA archives hold a `cuda_<version>` folder that becomes `ps_env/CUDA`
+    cuda: bool,
+}
+
+/// How a tool ended up after setup-env
+enum ToolOutcome {
+    AlreadyInstalled,
+    Installed(Duration),
+    Failed(String),
 }
 
 pub struct PortableEnvironmentManager {
@@ -47,20 +65,20 @@ pub struct PortableEnvironmentManager {
     ps_env_path: PathBuf,
     config_manager: ConfigManager,
     gpu_detector: GpuDetector,
-    tool_specs: HashMap<String, PortableToolSpec>,
+    tool_specs: BTreeMap<String, PortableToolSpec>,
 }
 
 impl PortableEnvironmentManager {
     pub fn new(install_path: PathBuf) -> Self {
         let ps_env_path = install_path.join("ps_env");
         let config_manager = ConfigManager::new(None).expect("ConfigManager init failed");
-        let tool_specs = Self::build_tool_specs();
+        let tool_specs = ToolManifest::for_install_path(&install_path).specs();
         Self { install_path, ps_env_path, config_manager, gpu_detector: GpuDetector::new(), tool_specs }
     }
 
     pub fn with_config(install_path: PathBuf, config_manager: ConfigManager) -> Self {
         let ps_env_path = install_path.join("ps_env");
-        let tool_specs = Self::build_tool_specs();
+        let tool_specs = ToolManifest::for_install_path(&install_path).specs();
         Self { install_path, ps_env_path, config_manager, gpu_detector: GpuDetector::new(), tool_specs }
     }
 
@@ -79,310 +97,46 @@ impl PortableEnvironmentManager {
         cuda_dir.join("bin").exists()
     }
 
-    fn build_tool_specs() -> HashMap<String, PortableToolSpec> {
-        let mut map = HashMap::new();
-        let is_windows = cfg!(windows);
-        map.insert(
-            "ffmpeg".to_string(),
-            PortableToolSpec {
-                name: "ffmpeg".to_string(),
-                url: ToolLinks::Ffmpeg.url().to_string(),
-                extract_path: "ffmpeg".to_string(),
-                executable_path: if is_windows { "ffmpeg/ffmpeg.exe" } else { "ffmpeg/ffmpeg" }.to_string(),
-            },
-        );
-        map.insert(
-            "git".to_string(),
-            PortableToolSpec {
-                name: "git".to_string(),
-                url: ToolLinks::Git.url().to_string(),
-                extract_path: "git".to_string(),
-                executable_path: if is_windows { "git/cmd/git.exe" } else { "git/bin/git" }.to_string(),
-            },
-        );
-        map.insert(
-            "python310".to_string(),
-            PortableToolSpec {
-                name: "python310".to_string(),
-                url: ToolLinks::Python(crate::config::PythonVersion::Python310).url().to_string(),
-                extract_path: "python310".to_string(),
-                executable_path: if is_windows { "python310/python.exe" } else { "python310/bin/python" }.to_string(),
-            },
-        );
-        map.insert(
-            "python311".to_string(),
-            PortableToolSpec {
-                name: "python311".to_string(),
-                url: ToolLinks::Python(crate::config::PythonVersion::Python311).url().to_string(),
-                extract_path: "python311".to_string(),
-                executable_path: if is_windows { "python311/python.exe" } else { "python311/bin/python" }.to_string(),
-            },
-        );
-        map
-    }
-
-    // --- Downloads ---
-    fn download_with_resume(&self, url: &str, destination: &Path) -> Result<()> {
-        use reqwest::blocking::Client;
-        use reqwest::header::{RANGE, CONTENT_RANGE};
-
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(600))
-            .build()?;
-
-        let mut existing_len: u64 = 0;
-        if destination.exists() {
-            existing_len = destination.metadata()?.len();
-        } else if let Some(parent) = destination.parent() { fs::create_dir_all(parent)?; }
-
-        // ИЗМЕНЕНИЕ: Не падать, если HEAD запрос не прошел (игнорируем ошибку и идем дальше)
-        // Проверяем полный размер файла с сервера, если возможно
-        if let Ok(head_resp) = client.head(url).send() {
-            if let Some(total_size) = head_resp.content_length() {
-                if existing_len == total_size && total_size > 0 {
-                    let file_name = destination.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "file".into());
-                    println!("[Setup] {} already downloaded.", file_name);
-                    return Ok(());
-                }
-            }
+    /// CUDA archive for the detected GPU, if the CUDA backend is used
+    fn cuda_tool_spec(&self) -> Option<&PortableToolSpec> {
+        if !self.config_manager.has_cuda() || !self.config_manager.get_recommended_backend().contains("cuda") {
+            return None;
         }
-
-        // Try ranged request if we have partial file
-        let mut resp = if existing_len > 0 {
-            client.get(url).header(RANGE, format!("bytes={}-", existing_len)).send()?
-        } else {
-            client.get(url).send()?
-        };
-
-        if !resp.status().is_success() {
-            // If ranged not supported, retry from start
-            if existing_len > 0 {
-                resp = client.get(url).send()?;
-                if !resp.status().is_success() {
-                    return Err(PortableSourceError::environment(format!(
-                        "Download failed: HTTP {}", resp.status()
-                    )));
-                }
-                // truncate file
-                let _ = fs::remove_file(destination);
-                let mut f = OpenOptions::new().create(true).write(true).open(destination)?;
-                // Setup progress bar
-                let total_opt = resp.content_length();
-                let file_name = destination.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "download".into());
-                let pb = create_download_progress_bar(total_opt, &format!("Downloading {}", file_name));
-                let mut downloaded: u64 = 0;
-                let start = Instant::now();
-                let mut buf = [0u8; 64 * 1024];
-                loop {
-                    let n = resp.read(&mut buf)?;
-                    if n == 0 { break; }
-                    f.write_all(&buf[..n])?;
-                    downloaded += n as u64;
-                    if let Some(total) = total_opt { pb.set_position(downloaded.min(total)); } else { pb.set_position(downloaded); }
-                    update_download_pb_message(&pb, downloaded, total_opt, start);
-                }
-                finish_progress(pb, &format!("Downloaded {}", file_name));
-                return Ok(());
-            } else {
-                return Err(PortableSourceError::environment(format!(
-                    "Download failed: HTTP {}", resp.status()
-                )));
-            }
-        }
-
-        // Write response to file (append or create)
-        let mut file = if destination.exists() && existing_len > 0 {
-            let mut f = OpenOptions::new().read(true).write(true).open(destination)?;
-            f.seek(SeekFrom::End(0))?;
-            f
-        } else {
-            OpenOptions::new().create(true).write(true).open(destination)?
-        };
-        // Setup progress bar with total length if available
-        let total_opt = match resp.headers().get(CONTENT_RANGE) {
-            Some(hv) => parse_total_from_content_range(hv.to_str().unwrap_or("")),
-            None => resp.content_length().map(|len| existing_len + len),
-        };
-        let file_name = destination.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "download".into());
-        let pb = create_download_progress_bar(total_opt, &format!("Downloading {}", file_name));
-        if let Some(total) = total_opt { pb.set_position(existing_len.min(total)); }
-        let mut downloaded = existing_len;
-        let start = Instant::now();
-        let mut buf = [0u8; 64 * 1024];
-        loop {
-            let n = resp.read(&mut buf)?;
-            if n == 0 { break; }
-            file.write_all(&buf[..n])?;
-            downloaded += n as u64;
-            if let Some(total) = total_opt { pb.set_position(downloaded.min(total)); } else { pb.set_position(downloaded); }
-            update_download_pb_message(&pb, downloaded, total_opt, start);
-        }
-        finish_progress(pb, &format!("Downloaded {}", file_name));
-        Ok(())
-    }
-
-    // Static helpers for parallel tasks
-    fn download_with_resume_static(url: String, destination: PathBuf) -> Result<()> {
-        use reqwest::blocking::Client;
-        use reqwest::header::{RANGE, CONTENT_RANGE};
-        let client = Client::builder().timeout(std::time::Duration::from_secs(600)).build()?;
-        if let Some(parent) = destination.parent() { fs::create_dir_all(parent)?; }
-        let existing_len: u64 = if destination.exists() { destination.metadata()?.len() } else { 0 };
-        
-        // ИЗМЕНЕНИЕ: Soft check для HEAD
-        if let Ok(head_resp) = client.head(&url).send() {
-            if let Some(total_size) = head_resp.content_length() {
-                if existing_len == total_size && total_size > 0 {
-                    let file_name = destination.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "file".into());
-                    println!("[Setup] {} already downloaded.", file_name);
-                    return Ok(());
-                }
-            }
-        }
-        
-        let mut resp = if existing_len > 0 {
-            client.get(&url).header(RANGE, format!("bytes={}-", existing_len)).send()?
-        } else { client.get(&url).send()? };
-        if !resp.status().is_success() {
-            if existing_len > 0 { resp = client.get(&url).send()?; }
-            if !resp.status().is_success() {
-                return Err(PortableSourceError::environment(format!("Download failed: HTTP {}", resp.status())));
-            }
-            let _ = fs::remove_file(&destination);
-            let mut f = OpenOptions::new().create(true).write(true).open(&destination)?;
-            let total_opt = resp.content_length();
-            let file_name = destination.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "download".into());
-            let pb = create_download_progress_bar(total_opt, &format!("Downloading {}", file_name));
-            let mut downloaded: u64 = 0;
-            let start = Instant::now();
-            let mut buf = [0u8; 64 * 1024];
-            loop {
-                let n = resp.read(&mut buf)?;
-                if n == 0 { break; }
-                f.write_all(&buf[..n])?;
-                downloaded += n as u64;
-                if let Some(total) = total_opt { pb.set_position(downloaded.min(total)); } else { pb.set_position(downloaded); }
-                update_download_pb_message(&pb, downloaded, total_opt, start);
-            }
-            finish_progress(pb, &format!("Downloaded {}", file_name));
-            return Ok(());
-        }
-        let mut file = if destination.exists() && existing_len > 0 {
-            let mut f = OpenOptions::new().read(true).write(true).open(&destination)?;
-            use std::io::Seek; use std::io::SeekFrom;
-            f.seek(SeekFrom::End(0))?; f
-        } else { OpenOptions::new().create(true).write(true).open(&destination)? };
-        let total_opt = match resp.headers().get(CONTENT_RANGE) {
-            Some(hv) => parse_total_from_content_range(hv.to_str().unwrap_or("")),
-            None => resp.content_length().map(|len| existing_len + len),
-        };
-        let file_name = destination.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "download".into());
-        let pb = create_download_progress_bar(total_opt, &format!("Downloading {}", file_name));
-        if let Some(total) = total_opt { pb.set_position(existing_len.min(total)); }
-        let mut downloaded = existing_len;
-        let start = Instant::now();
-        let mut buf = [0u8; 64 * 1024];
-        loop {
-            let n = resp.read(&mut buf)?;
-            if n == 0 { break; }
-            file.write_all(&buf[..n])?;
-            downloaded += n as u64;
-            if let Some(total) = total_opt { pb.set_position(downloaded.min(total)); } else { pb.set_position(downloaded); }
-            update_download_pb_message(&pb, downloaded, total_opt, start);
-        }
-        finish_progress(pb, &format!("Downloaded {}", file_name));
-        Ok(())
+        self.tool_specs.get(self.config_manager.get_cuda_version()?.tool_name())
     }
 
-    // --- Extraction (via tar zstd) ---
-    fn extract_tar_zstd(&self, archive_path: &Path, extract_to: &Path) -> Result<()> {
-        if let Some(parent) = extract_to.parent() { fs::create_dir_all(parent)?; }
-        fs::create_dir_all(extract_to)?;
-        self.extract_with_tar_zstd_binary(archive_path, extract_to)
-    }
-    fn extract_tar_zstd_static(archive_path: PathBuf, extract_to: PathBuf) -> Result<()> {
-        if let Some(parent) = extract_to.parent() { fs::create_dir_all(parent)?; }
-        fs::create_dir_all(&extract_to)?;
-        Self::extract_with_tar_zstd_binary_static(&archive_path, &extract_to)
+    /// Take all tools from a `fetch-tools` directory instead of the mirrors, checked
+    /// against the checksums recorded there
+    pub fn with_artifact_dir(mut self, dir: &Path) -> Result<Self> {
+        self.tool_specs = ToolManifest::for_install_path(&self.install_path).specs_in_dir(dir)?;
+        Ok(self)
     }
 
-    // ensure_tar_binary больше не нужна - используем Rust крейты напрямую
-
-    fn extract_with_tar_zstd_binary(&self, archive_path: &Path, extract_to: &Path) -> Result<()> {
-        use std::fs::File;
-        use std::io::BufReader;
-        
-        let file_label = archive_path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "archive".into());
-        let pb = create_extract_progress_bar(&format!("Extracting {}", file_label));
-        
-        pb.set_position(25);
-        
-        // Открываем файл и создаем zstd декодер
-        let file = File::open(archive_path)
-            .map_err(|e| PortableSourceError::environment(format!("Failed to open archive: {}", e)))?;
-        let buf_reader = BufReader::new(file);
-        let zstd_decoder = zstd::stream::Decoder::new(buf_reader)
-            .map_err(|e| PortableSourceError::environment(format!("Failed to create zstd decoder: {}", e)))?;
-        
-        pb.set_position(50);
-        
-        // Создаем tar архив из декодированного потока
-        let mut archive = tar::Archive::new(zstd_decoder);
-        
-        pb.set_position(75);
-        
-        // Извлекаем архив
-        archive.unpack(extract_to)
-            .map_err(|e| PortableSourceError::environment(format!("Failed to extract tar archive: {}", e)))?;
-        
-        finish_progress(pb, &format!("Extracted {}", file_label));
-        Ok(())
+    /// Download the archives `setup_environment` would install into `dir`,
+    /// for a later `setup-env --from-dir`. Returns the downloaded files.
+    pub fn fetch_tools(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir)?;
+        let mut specs: Vec<&PortableToolSpec> = self.tool_specs.values().filter(|spec| !spec.optional).collect();
+        specs.extend(self.cuda_tool_spec());
+        let mut fetched = Vec::new();
+        for spec in specs {
+            println!("[Setup] Fetching {}...", spec.archive_name());
+            fetched.push(spec.fetch_into(dir)?);
+        }
+        Ok(fetched)
     }
 
-    fn extract_with_tar_zstd_binary_static(archive_path: &Path, extract_to: &Path) -> Result<()> {
-        use std::fs::File;
-        use std::io::BufReader;
-        
-        let file_label = archive_path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "archive".into());
-        let pb = create_extract_progress_bar(&format!("Extracting {}", file_label));
-        
-        pb.set_position(25);
-        
-        // Открываем файл и создаем zstd декодер
-        let file = File::open(archive_path)
-            .map_err(|e| PortableSourceError::environment(format!("Failed to open archive: {}", e)))?;
-        let buf_reader = BufReader::new(file);
-        let zstd_decoder = zstd::stream::Decoder::new(buf_reader)
-            .map_err(|e| PortableSourceError::environment(format!("Failed to create zstd decoder: {}", e)))?;
-        
-        pb.set_position(50);
-        
-        // Создаем tar архив из декодированного потока
-        let mut archive = tar::Archive::new(zstd_decoder);
-        
-        pb.set_position(75);
-        
-        // Извлекаем архив
-        archive.unpack(extract_to)
-            .map_err(|e| PortableSourceError::environment(format!("Failed to extract tar archive: {}", e)))?;
-        
-        finish_progress(pb, &format!("Extracted {}", file_label));
-        Ok(())
-    }
-    
     fn install_portable_tool(&self, key: &str) -> Result<()> {
         let spec = self.tool_specs.get(key).ok_or_else(|| PortableSourceError::environment(format!("Unknown tool: {}", key)))?;
         let exe_path = self.ps_env_path.join(&spec.executable_path);
         if exe_path.exists() { return Ok(()); }
 
         // ИЗМЕНЕНИЕ: Жестко задаем имя архива, не полагаясь на URL
-        let archive_name = format!("{}.tar.zst", spec.name);
-        let archive_path = self.ps_env_path.join(&archive_name);
+        let archive_path = self.ps_env_path.join(spec.archive_name());
 
-        self.download_with_resume(&spec.url, &archive_path)?;
+        Downloader::new().download(&spec.artifact(), &archive_path)?;
         // Extract to ps_env root; archives are structured with top-level folder (ffmpeg/git/python)
-        self.extract_tar_zstd(&archive_path, &self.ps_env_path)?;
-        let _ = fs::remove_file(&archive_path);
+        Self::unpack_tool_static(spec, &archive_path, &self.ps_env_path, None)?;
 
         if !exe_path.exists() {
             return Err(PortableSourceError::environment(format!(
@@ -390,16 +144,93 @@ impl PortableEnvironmentManager {
                 spec.name, exe_path
             )));
         }
+        InstalledTools::record(&self.ps_env_path, spec)
+    }
+
+    /// Put a downloaded tool into ps_env according to its archive format
+    fn unpack_tool_static(spec: &PortableToolSpec, archive_path: &Path, ps_env: &Path, progress: Option<&SharedProgress>) -> Result<()> {
+        match spec.format {
+            ArchiveFormat::TarZst | ArchiveFormat::TarGz | ArchiveFormat::TarXz | ArchiveFormat::Zip => {
+                match progress {
+                    Some(progress) => archive::extract_with_progress(archive_path, spec.format, ps_env, progress)?,
+                    None => archive::extract(archive_path, spec.format, ps_env)?,
+                }
+                let _ = fs::remove_file(archive_path);
+            }
+            ArchiveFormat::Binary => {
+                let exe_path = ps_env.join(&spec.executable_path);
+                if let Some(parent) = exe_path.parent() { fs::create_dir_all(parent)?; }
+                fs::rename(archive_path, &exe_path)?;
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&exe_path, fs::Permissions::from_mode(0o755))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Install the missing tools (and CUDA) a few at a time, then print a line per tool.
+    /// A tool that fails does not stop the others; the error names all that failed.
+    fn install_missing_tools(&self, progress_cb: Option<&StepCallback<'_>>) -> Result<()> {
+        // In display order; `None` is filled in from the jobs, which are pushed in the same order
+        let mut summary: Vec<(String, Option<ToolOutcome>)> = Vec::new();
+        let mut jobs: Vec<ToolJob> = Vec::new();
+        if let Some(spec) = self.cuda_tool_spec() {
+            if self.is_cuda_installed() {
+                summary.push(("cuda".to_string(), Some(ToolOutcome::AlreadyInstalled)));
+            } else {
+                summary.push(("cuda".to_string(), None));
+                jobs.push(ToolJob { key: "cuda".to_string(), spec, cuda: true });
+            }
+        }
+        for (key, spec) in self.tool_specs.iter().filter(|(_, spec)| !spec.optional) {
+            if self.is_tool_installed(key) {
+                summary.push((key.clone(), Some(ToolOutcome::AlreadyInstalled)));
+            } else {
+                summary.push((key.clone(), None));
+                jobs.push(ToolJob { key: key.clone(), spec, cuda: false });
+            }
+        }
+        if let Some(cb) = progress_cb { cb("init".to_string(), 0, jobs.len() * 2); }
+
+        let outcomes = run_tool_jobs(&self.ps_env_path, &jobs, MAX_PARALLEL_TOOL_INSTALLS, progress_cb);
+        // The record file is shared, so it is written once all workers are done
+        for (job, outcome) in jobs.iter().zip(&outcomes) {
+            if matches!(outcome, ToolOutcome::Installed(_)) {
+                InstalledTools::record(&self.ps_env_path, job.spec)?;
+            }
+        }
+        let mut outcomes = outcomes.into_iter();
+        let summary: Vec<(String, ToolOutcome)> = summary.into_iter()
+            .map(|(key, outcome)| (key, outcome.or_else(|| outcomes.next()).unwrap_or(ToolOutcome::Failed("not run".into()))))
+            .collect();
+        print_tool_summary(&summary);
+
+        let failed: Vec<&str> = summary.iter()
+            .filter(|(_, outcome)| matches!(outcome, ToolOutcome::Failed(_)))
+            .map(|(key, _)| key.as_str())
+            .collect();
+        if !failed.is_empty() {
+            return Err(PortableSourceError::environment(format!("Failed to install: {}", failed.join(", "))));
+        }
         Ok(())
     }
 
     // --- Env for subprocess ---
     pub fn setup_environment_for_subprocess(&self) -> HashMap<String, String> {
         let mut env_vars: HashMap<String, String> = std::env::vars().collect();
+
+        // Shared download/wheel cache for all environments of this installation
+        let cache = PackageCache::new(&self.install_path);
+        env_vars.insert("UV_CACHE_DIR".to_string(), cache.uv_dir().to_string_lossy().to_string());
+        env_vars.insert("PIP_CACHE_DIR".to_string(), cache.pip_dir().to_string_lossy().to_string());
+
         if !self.ps_env_path.exists() { return env_vars; }
 
         let mut tool_paths: Vec<String> = Vec::new();
-        for (_name, spec) in &self.tool_specs {
+        for spec in self.tool_specs.values().filter(|spec| !spec.optional) {
             let exe_dir = self.ps_env_path.join(&spec.executable_path).parent().map(|p| p.to_path_buf());
             if let Some(exe_dir) = exe_dir { if exe_dir.exists() { tool_paths.push(exe_dir.to_string_lossy().to_string()); } }
         }
@@ -583,144 +414,7 @@ impl PortableEnvironmentManager {
         // GPU detection is now handled dynamically
         // let cfg_now = cfgm.get_config().clone();
 
-        // Prepare progress tracking
-        let print_lock = Arc::new(Mutex::new(()));
-        let completed = Arc::new(AtomicUsize::new(0));
-        let mut total_steps: usize = 0;
-
-        // Determine total steps before starting any tasks
-        let mut cuda_plan: Option<(String, String)> = None; // (download_link, expected_folder)
-        if self.config_manager.has_cuda() {
-            // ВОТ ЗДЕСЬ БЫЛА ОШИБКА ↓
-            if let Some(cuda_ver) = self.config_manager.get_cuda_version() {
-                if self.config_manager.get_recommended_backend().contains("cuda") {
-                    if let Some(link) = self.config_manager.get_cuda_download_link(Some(&cuda_ver)) {
-                        if !self.is_cuda_installed() { total_steps += 2; }
-                        let version_debug = format!("{:?}", cuda_ver).to_lowercase();
-                        let cleaned = version_debug.replace("cuda", "").replace(['_', '"'], "");
-                        let expected_folder = format!("cuda_{}", cleaned);
-                        cuda_plan = Some((link, expected_folder));
-                    }
-                }
-            }
-        }
-
-        // Each tool: download + extract (only for missing ones)
-        let mut tools_to_install: Vec<&str> = Vec::new();
-        for key in ["python310", "python311", "git", "ffmpeg"] {
-            if !self.is_tool_installed(key) {
-                total_steps += 2;
-                tools_to_install.push(key);
-            }
-        }
-
-        // Announce total steps
-        {
-            let _g = print_lock.lock().unwrap();
-            println!("[Setup] Total steps: {}", total_steps);
-        }
-
-        // Переходим на последовательную установку для стабильного вывода прогресса
-        let total_c = total_steps; // используем для сообщений
-
-        if let Some((link, expected_folder)) = cuda_plan {
-            // Skip CUDA task if already installed
-            if !self.is_cuda_installed() {
-                let ps_env = self.ps_env_path.clone();
-                let archive_path = ps_env.join(format!(
-                    "CUDA_{}.tar.zst",
-                    expected_folder.trim_start_matches("cuda_").to_uppercase()
-                ));
-                {
-                    let _g = print_lock.lock().unwrap();
-                    let done = completed.load(Ordering::SeqCst);
-                    println!("[Setup] Downloading CUDA archive... (step {}/{})", done + 1, total_c);
-                }
-                PortableEnvironmentManager::download_with_resume_static(link, archive_path.clone())?;
-                completed.fetch_add(1, Ordering::SeqCst);
-                {
-                    let _g = print_lock.lock().unwrap();
-                    let done = completed.load(Ordering::SeqCst);
-                    println!("[Setup] Progress: {}/{} ({:.0}%)", done, total_c, (done as f32/ total_c as f32)*100.0);
-                    println!("[Setup] CUDA downloaded.\n[Setup] Extracting CUDA... (next step)");
-                }
-                let temp_extract = ps_env.join("__cuda_extract_temp__");
-                if temp_extract.exists() { let _ = fs::remove_dir_all(&temp_extract); }
-                PortableEnvironmentManager::extract_tar_zstd_static(archive_path.clone(), temp_extract.clone())?;
-                let extracted_sub = temp_extract.join(&expected_folder);
-                let cuda_dir = ps_env.join("CUDA");
-                if cuda_dir.exists() { let _ = fs::remove_dir_all(&cuda_dir); }
-                if !extracted_sub.exists() { return Err(PortableSourceError::environment("Expected CUDA folder missing after extraction")); }
-                fs::rename(&extracted_sub, &cuda_dir)?;
-                let _ = fs::remove_dir_all(&temp_extract);
-                let _ = fs::remove_file(&archive_path);
-                completed.fetch_add(1, Ordering::SeqCst);
-                {
-                    let _g = print_lock.lock().unwrap();
-                    let done = completed.load(Ordering::SeqCst);
-                    println!("[Setup] CUDA extracted.");
-                    println!("[Setup] Progress: {}/{} ({:.0}%)", done, total_c, (done as f32/ total_c as f32)*100.0);
-                }
-            }
-        }
-
-        // Other tools — последовательная установка для корректного отображения прогресса
-        for key in tools_to_install {
-            if let Some(spec) = self.tool_specs.get(key) {
-                let url = spec.url.clone();
-
-				let archive_name = format!("{}.tar.zst", spec.name);
-
-                let ps_env = self.ps_env_path.clone();
-                let exe_rel = spec.executable_path.clone();
-                {
-                    let _g = print_lock.lock().unwrap();
-                    let done = completed.load(Ordering::SeqCst);
-                    println!("[Setup] Downloading {}... (step {}/{})", archive_name, done + 1, total_c);
-                }
-                let archive_path = ps_env.join(&archive_name);
-                PortableEnvironmentManager::download_with_resume_static(url, archive_path.clone())?;
-                completed.fetch_add(1, Ordering::SeqCst);
-                {
-                    let _g = print_lock.lock().unwrap();
-                    let done = completed.load(Ordering::SeqCst);
-                    println!("[Setup] Progress: {}/{} ({:.0}%)", done, total_c, (done as f32/ total_c as f32)*100.0);
-                    println!("[Setup] Extracting {}...", archive_name);
-                }
-                PortableEnvironmentManager::extract_tar_zstd_static(archive_path.clone(), ps_env.clone())?;
-                let _ = fs::remove_file(&archive_path);
-                let exe_path = ps_env.join(&exe_rel);
-                if !exe_path.exists() {
-                    return Err(PortableSourceError::environment(format!("Executable not found: {:?}", exe_path)));
-                }
-                completed.fetch_add(1, Ordering::SeqCst);
-                {
-                    let _g = print_lock.lock().unwrap();
-                    let done = completed.load(Ordering::SeqCst);
-                    println!("[Setup] {} installed.", exe_rel);
-                    println!("[Setup] Progress: {}/{} ({:.0}%)", done, total_c, (done as f32/ total_c as f32)*100.0);
-                }
-            }
-        }
-
-        // Итоговая печать прогресса (только если не было 100%)
-        let total = total_steps;
-        let done = completed.load(Ordering::SeqCst);
-        if done < total {
-            let pct = if total > 0 { (done as f32 / total as f32) * 100.0 } else { 100.0 };
-            let _g = print_lock.lock().unwrap();
-            println!("[Setup] Progress: {}/{} ({:.0}%)", done, total, pct);
-        }
-
-        // Ensure final 100% line if not printed
-        {
-            let done = completed.load(Ordering::SeqCst);
-            if done < total {
-                let pct = if total > 0 { (done as f32 / total as f32) * 100.0 } else { 100.0 };
-                let _g = print_lock.lock().unwrap();
-                println!("[Setup] Progress: {}/{} ({:.0}%)", done, total, pct);
-            }
-        }
+        self.install_missing_tools(None)?;
 
         // Install Git LFS (always run to ensure it's initialized)
         self.install_git_lfs().await?;
@@ -740,7 +434,7 @@ impl PortableEnvironmentManager {
 
     /// Setup environment with progress callback.
     /// The callback receives `(tool_key, steps_done, total_steps)`.
-    /// tool_key is one of: "python", "git", "ffmpeg", "cuda".
+    /// tool_key is "init" first (with the total), then a tool name from the manifest or "cuda".
     pub async fn setup_environment_with_progress<F>(&self, progress_cb: F) -> Result<()>
     where
         F: Fn(String, usize, usize) + Send + Sync + 'static,
@@ -755,114 +449,7 @@ impl PortableEnvironmentManager {
         // GPU detection is now handled dynamically
         // let cfg_now = cfgm.get_config().clone();
 
-        let completed = Arc::new(AtomicUsize::new(0));
-        let cb_arc: Arc<dyn Fn(String, usize, usize) + Send + Sync> = Arc::new(progress_cb);
-        let mut total_steps: usize = 0;
-
-        // CUDA plan detection same as in setup_environment
-        let mut cuda_plan: Option<(String, String)> = None; // (download_link, expected_folder)
-        if self.config_manager.has_cuda() {
-            if let Some(cuda_ver) = self.config_manager.get_cuda_version() {
-                if self.config_manager.get_recommended_backend().contains("cuda") {
-                    if let Some(link) = self.config_manager.get_cuda_download_link(Some(&cuda_ver)) {
-                        if !self.is_cuda_installed() { total_steps += 2; }
-                        let version_debug = format!("{:?}", cuda_ver).to_lowercase();
-                        let cleaned = version_debug.replace("cuda", "").replace(['_', '"'], "");
-                        let expected_folder = format!("cuda_{}", cleaned);
-                        cuda_plan = Some((link, expected_folder));
-                    }
-                }
-            }
-        }
-        // python310, python311, git, ffmpeg each: download + extract (only for missing ones)
-        let mut tools_to_install: Vec<&str> = Vec::new();
-        for key in ["python310", "python311", "git", "ffmpeg"] {
-            if !self.is_tool_installed(key) {
-                total_steps += 2;
-                tools_to_install.push(key);
-            }
-        }
-
-        // Tell UI initial total
-        cb_arc.clone()("init".to_string(), 0, total_steps);
-
-        let mut handles = Vec::new();
-        let total_c = total_steps;
-        let cb_cuda = cb_arc.clone();
-        if let Some((link, expected_folder)) = cuda_plan {
-            if !self.is_cuda_installed() {
-            let ps_env = self.ps_env_path.clone();
-            let archive_path = ps_env.join(format!(
-                "CUDA_{}.tar.zst",
-                expected_folder.trim_start_matches("cuda_").to_uppercase()
-            ));
-            let completed_c = completed.clone();
-            handles.push(tokio::task::spawn_blocking(move || {
-                // Step: CUDA download
-                let done_now = completed_c.load(Ordering::SeqCst);
-                cb_cuda("cuda".to_string(), done_now, total_c);
-                PortableEnvironmentManager::download_with_resume_static(link, archive_path.clone())?;
-                completed_c.fetch_add(1, Ordering::SeqCst);
-                // Step: CUDA extract
-                let done_now = completed_c.load(Ordering::SeqCst);
-                cb_cuda("cuda".to_string(), done_now, total_c);
-                let temp_extract = ps_env.join("__cuda_extract_temp__");
-                if temp_extract.exists() { let _ = fs::remove_dir_all(&temp_extract); }
-                PortableEnvironmentManager::extract_tar_zstd_static(archive_path.clone(), temp_extract.clone())?;
-                let extracted_sub = temp_extract.join(&expected_folder);
-                let cuda_dir = ps_env.join("CUDA");
-                if cuda_dir.exists() { let _ = fs::remove_dir_all(&cuda_dir); }
-                if !extracted_sub.exists() { return Err(PortableSourceError::environment("Expected CUDA folder missing after extraction")); }
-                fs::rename(&extracted_sub, &cuda_dir)?;
-                let _ = fs::remove_dir_all(&temp_extract);
-                let _ = fs::remove_file(&archive_path);
-                completed_c.fetch_add(1, Ordering::SeqCst);
-                // Emit final state after finishing CUDA extraction
-                let done_now = completed_c.load(Ordering::SeqCst);
-                cb_cuda("cuda".to_string(), done_now, total_c);
-                Ok::<(), PortableSourceError>(())
-            }));
-            }
-        }
-
-        // Other tools in parallel
-        for key in tools_to_install {
-            if let Some(spec) = self.tool_specs.get(key) {
-                let url = spec.url.clone();
-				let archive_name = format!("{}.tar.zst", spec.name);
-                let ps_env = self.ps_env_path.clone();
-                let exe_rel = spec.executable_path.clone();
-                let completed_t = completed.clone();
-                let cb_t = cb_arc.clone();
-                handles.push(tokio::task::spawn_blocking(move || {
-                    // Step: download
-                    let done_now = completed_t.load(Ordering::SeqCst);
-                    cb_t(key.to_string(), done_now, total_c);
-                    let archive_path = ps_env.join(&archive_name);
-                    PortableEnvironmentManager::download_with_resume_static(url, archive_path.clone())?;
-                    completed_t.fetch_add(1, Ordering::SeqCst);
-                    // Step: extract
-                    let done_now = completed_t.load(Ordering::SeqCst);
-                    cb_t(key.to_string(), done_now, total_c);
-                    PortableEnvironmentManager::extract_tar_zstd_static(archive_path.clone(), ps_env.clone())?;
-                    let _ = fs::remove_file(&archive_path);
-                    let exe_path = ps_env.join(&exe_rel);
-                    if !exe_path.exists() {
-                        return Err(PortableSourceError::environment(format!("Executable not found: {:?}", exe_path)));
-                    }
-                    completed_t.fetch_add(1, Ordering::SeqCst);
-                    // Emit final update after tool extraction completes
-                    let done_now = completed_t.load(Ordering::SeqCst);
-                    cb_t(key.to_string(), done_now, total_c);
-                    Ok::<(), PortableSourceError>(())
-                }));
-            }
-        }
-
-        for h in handles {
-            let res = h.await.map_err(|e| PortableSourceError::environment(format!("Join error: {}", e)))?;
-            if let Err(err) = res { return Err(err); }
-        }
+        self.install_missing_tools(Some(&progress_cb))?;
 
         // CUDA paths are now computed dynamically when needed
         if !self.verify_environment_tools()? { return Err(PortableSourceError::environment("Environment tools verification failed")); }
@@ -946,24 +533,18 @@ impl PortableEnvironmentManager {
                 let cuda_dir = self.ps_env_path.join("CUDA");
                 if cuda_dir.join("bin").exists() { return Ok(()); }
 
-                // Ссылка на архив
-                let link = self
-                    .config_manager
-                    .get_cuda_download_link(Some(&cuda_ver))
-                    .ok_or_else(|| PortableSourceError::environment("CUDA download link not available"))?;
-
-                // Вычисляем версию в имени папки: CUDA_118.tar.zst -> cuda_118
-                let version_debug = format!("{:?}", cuda_ver).to_lowercase();
-                let cleaned = version_debug.replace("cuda", "").replace(['_', '"'], "");
-                let expected_folder = format!("cuda_{}", cleaned);
+                // Архив из манифеста; внутри одна папка cuda_{ver}
+                let cuda_spec = self.tool_specs.get(cuda_ver.tool_name())
+                    .ok_or_else(|| PortableSourceError::environment(format!("No {} build in the tool manifest", cuda_ver.tool_name())))?;
+                let expected_folder = cuda_spec.extract_path.clone();
 
-                let archive_path = self.ps_env_path.join(format!("CUDA_{}.tar.zst", cleaned.to_uppercase()));
-                self.download_with_resume(&link, &archive_path)?;
+                let archive_path = self.ps_env_path.join(cuda_spec.archive_name());
+                Downloader::new().download(&cuda_spec.artifact(), &archive_path)?;
 
                 // Распаковка во временную директорию
                 let temp_extract = self.ps_env_path.join("__cuda_extract_temp__");
                 if temp_extract.exists() { let _ = fs::remove_dir_all(&temp_extract); }
-                self.extract_tar_zstd(&archive_path, &temp_extract)?;
+                archive::extract(&archive_path, cuda_spec.format, &temp_extract)?;
 
                 // Переименование папки cuda_{ver} -> CUDA (строго без манкипатчей)
                 let extracted_sub = temp_extract.join(&expected_folder);
@@ -1004,6 +585,7 @@ impl PortableEnvironmentManager {
                 if !cuda_dir.join("bin").exists() {
                     return Err(PortableSourceError::environment("CUDA installation failed: bin not found"));
                 }
+                InstalledTools::record(&self.ps_env_path, cuda_spec)?;
                 // CUDA paths are now computed dynamically when needed
                 log::info!("Successfully processed CUDA");
             }
@@ -1251,69 +833,121 @@ impl PortableEnvironmentManager {
 // Удалены функции sanitize_windows_path_for_7z и format_7z_out_arg
 // так как они больше не нужны для tar zstd
 
-// ===== Progress helpers =====
-fn create_download_progress_bar(total_opt: Option<u64>, prefix: &str) -> ProgressBar {
-    match total_opt {
-        Some(total) if total > 0 => {
-            let pb = ProgressBar::new(total);
-            let style = ProgressStyle::with_template("{prefix:.bold} [{bar:40.cyan/blue}] {percent:>3}% {msg} ETA {eta}")
-                .unwrap()
-                .progress_chars("=>-");
-            pb.set_style(style);
-            pb.set_prefix(prefix.to_string());
-            pb
-        }
-        _ => {
-            let pb = ProgressBar::new_spinner();
-            pb.set_style(ProgressStyle::with_template("{prefix:.bold} {spinner} {msg}").unwrap());
-            pb.set_prefix(prefix.to_string());
-            pb.enable_steady_tick(std::time::Duration::from_millis(120));
-            pb
+/// Run `jobs` on up to `workers` threads. A worker unpacks its archive as soon as it is
+/// downloaded, while the other workers keep downloading.
+fn run_tool_jobs(ps_env: &Path, jobs: &[ToolJob], workers: usize, progress_cb: Option<&StepCallback<'_>>) -> Vec<ToolOutcome> {
+    if jobs.is_empty() { return Vec::new(); }
+    let progress = SharedProgress::new(MultiProgress::new(), create_overall_progress_bar(jobs.len()));
+    progress.reserve(estimate_tool_bytes(jobs));
+    let total_steps = jobs.len() * 2;
+    let next_job = AtomicUsize::new(0);
+    let steps_done = AtomicUsize::new(0);
+    let tools_done = AtomicUsize::new(0);
+    let outcomes: Vec<Mutex<Option<ToolOutcome>>> = jobs.iter().map(|_| Mutex::new(None)).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, jobs.len()) {
+            scope.spawn(|| loop {
+                let index = next_job.fetch_add(1, Ordering::SeqCst);
+                let Some(job) = jobs.get(index) else { break };
+                let job_steps = Cell::new(0);
+                let step = || {
+                    job_steps.set(job_steps.get() + 1);
+                    let done = steps_done.fetch_add(1, Ordering::SeqCst) + 1;
+                    if let Some(cb) = progress_cb { cb(job.key.clone(), done, total_steps); }
+                };
+                let start = Instant::now();
+                let outcome = match install_tool_job(ps_env, job, &progress, &step) {
+                    Ok(()) => ToolOutcome::Installed(start.elapsed()),
+                    Err(e) => ToolOutcome::Failed(e.to_string()),
+                };
+                // Steps a failed tool skipped still count, so the callback reaches the total
+                while job_steps.get() < 2 { step(); }
+                let done = tools_done.fetch_add(1, Ordering::SeqCst) + 1;
+                progress.overall().set_message(format!("{}/{} tools", done, jobs.len()));
+                *outcomes[index].lock().unwrap() = Some(outcome);
+            });
         }
-    }
-}
+    });
+    progress.overall().finish();
 
-fn create_extract_progress_bar(prefix: &str) -> ProgressBar {
-    let pb = ProgressBar::new(100);
-    let style = ProgressStyle::with_template("{prefix:.bold} [{bar:40.magenta/blue}] {pos:>3}% ETA {eta}")
-        .unwrap()
-        .progress_chars("=>-");
-    pb.set_style(style);
-    pb.set_prefix(prefix.to_string());
-    pb
+    outcomes.into_iter()
+        .map(|outcome| outcome.into_inner().ok().flatten().unwrap_or(ToolOutcome::Failed("not run".into())))
+        .collect()
 }
 
-fn finish_progress(pb: ProgressBar, msg: &str) {
-    pb.finish_with_message(msg.to_string());
+/// Bytes the overall bar will count for `jobs`: each archive is downloaded, then read
+/// again to unpack it. Sizes come from the sources, probed in parallel; unknown ones are
+/// added to the bar as the downloads start.
+fn estimate_tool_bytes(jobs: &[ToolJob]) -> u64 {
+    std::thread::scope(|scope| {
+        let probes: Vec<_> = jobs.iter()
+            .map(|job| scope.spawn(move || {
+                let passes = if job.spec.format == ArchiveFormat::Binary { 1 } else { 2 };
+                Downloader::new().with_timeout(Duration::from_secs(15)).content_length(&job.spec.artifact()).map(|len| len * passes)
+            }))
+            .collect();
+        probes.into_iter().filter_map(|probe| probe.join().ok().flatten()).sum()
+    })
 }
 
-fn parse_total_from_content_range(hv: &str) -> Option<u64> {
-    // Expected like: "bytes start-end/total"
-    if let Some(slash_pos) = hv.rfind('/') {
-        let total_str = hv[slash_pos + 1..].trim();
-        if let Ok(total) = total_str.parse::<u64>() { return Some(total); }
+/// Download one tool and put it into ps_env; `step` is called after each of the two steps
+fn install_tool_job(ps_env: &Path, job: &ToolJob, progress: &SharedProgress, step: &dyn Fn()) -> Result<()> {
+    let archive_path = ps_env.join(job.spec.archive_name());
+    Downloader::new().with_progress(progress.clone()).download(&job.spec.artifact(), &archive_path)?;
+    step();
+    if job.cuda {
+        install_cuda_archive(job.spec, &archive_path, ps_env, progress)?;
+    } else {
+        PortableEnvironmentManager::unpack_tool_static(job.spec, &archive_path, ps_env, Some(progress))?;
+        let exe_path = ps_env.join(&job.spec.executable_path);
+        if !exe_path.exists() {
+            return Err(PortableSourceError::environment(format!("Executable not found: {:?}", exe_path)));
+        }
     }
-    None
+    step();
+    Ok(())
 }
 
-// Функция extract_percent удалена, так как tar не выводит прогресс в процентах
+/// The `cuda_<version>` folder of a CUDA archive (`extract_path`) becomes `ps_env/CUDA`
+fn install_cuda_archive(spec: &PortableToolSpec, archive_path: &Path, ps_env: &Path, progress: &SharedProgress) -> Result<()> {
+    let temp_extract = ps_env.join("__cuda_extract_temp__");
+    if temp_extract.exists() { let _ = fs::remove_dir_all(&temp_extract); }
+    archive::extract_with_progress(archive_path, spec.format, &temp_extract, progress)?;
+    let extracted_sub = temp_extract.join(&spec.extract_path);
+    let cuda_dir = ps_env.join("CUDA");
+    if cuda_dir.exists() { let _ = fs::remove_dir_all(&cuda_dir); }
+    if !extracted_sub.exists() { return Err(PortableSourceError::environment("Expected CUDA folder missing after extraction")); }
+    fs::rename(&extracted_sub, &cuda_dir)?;
+    let _ = fs::remove_dir_all(&temp_extract);
+    let _ = fs::remove_file(archive_path);
+    Ok(())
+}
 
-fn update_download_pb_message(pb: &ProgressBar, downloaded: u64, total_opt: Option<u64>, start: Instant) {
-    let elapsed = start.elapsed().as_secs_f64();
-    let mb_downloaded = bytes_to_mb(downloaded);
-    let speed_mb_s = if elapsed > 0.0 { bytes_to_mb((downloaded as f64 / elapsed) as u64) } else { 0.0 };
-    let msg = match total_opt {
-        Some(total) if total > 0 => {
-            let total_mb = bytes_to_mb(total);
-            format!("{:.2} MB/{:.2} MB @ {:.2} MB/s", mb_downloaded, total_mb, speed_mb_s)
-        }
-        _ => format!("{:.2} MB @ {:.2} MB/s", mb_downloaded, speed_mb_s),
-    };
-    pb.set_message(msg);
+fn print_tool_summary(summary: &[(String, ToolOutcome)]) {
+    if summary.is_empty() { return; }
+    println!("[Setup] Tools:");
+    let width = summary.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, outcome) in summary {
+        let status = match outcome {
+            ToolOutcome::AlreadyInstalled => "already installed".to_string(),
+            ToolOutcome::Installed(took) => format!("installed in {}", HumanDuration(*took)),
+            ToolOutcome::Failed(e) => format!("FAILED: {}", e),
+        };
+        println!("[Setup]   {:<width$}  {}", key, status, width = width);
+    }
 }
 
-fn bytes_to_mb(bytes: u64) -> f64 {
-    (bytes as f64) / 1_000_000.0
+// ===== Progress helpers =====
+fn create_overall_progress_bar(tools: usize) -> ProgressBar {
+    let pb = ProgressBar::new(0);
+    let style = ProgressStyle::with_template("{prefix:.bold} [{bar:40.green/blue}] {percent:>3}% {bytes}/{total_bytes} {msg} ETA {eta}")
+        .unwrap()
+        .progress_chars("=>-");
+    pb.set_style(style);
+    pb.set_prefix("Total");
+    pb.set_message(format!("0/{} tools", tools));
+    pb
 }
 
 impl PortableEnvironmentManager {
@@ -1352,4 +986,56 @@ pub struct EnvironmentInfo {
     pub base_env_pip: Option<String>,
     pub installed_tools: HashMap<String, bool>,
     pub paths: EnvironmentPaths,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str, dir: &Path, sha256: Option<String>) -> PortableToolSpec {
+        PortableToolSpec {
+            name: name.to_string(),
+            version: None,
+            urls: Vec::new(),
+            sha256,
+            format: ArchiveFormat::TarGz,
+            extract_path: name.to_string(),
+            executable_path: format!("{}/bin/run", name),
+            optional: false,
+        }.in_dir(dir)
+    }
+
+    #[test]
+    fn test_failed_tool_does_not_stop_the_others() {
+        let tmp = tempfile::tempdir().unwrap();
+        let artifacts = tmp.path().join("artifacts");
+        let ps_env = tmp.path().join("ps_env");
+        fs::create_dir_all(&artifacts).unwrap();
+
+        let encoder = flate2::write::GzEncoder::new(fs::File::create(artifacts.join("good.tar.gz")).unwrap(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o755);
+        builder.append_data(&mut header, "good/bin/run", &b"ok"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        // "missing" has no archive in the directory
+        let good_sha256 = crate::downloader::sha256_file(&artifacts.join("good.tar.gz")).unwrap();
+        let specs = [spec("missing", &artifacts, Some("00".repeat(32))), spec("good", &artifacts, Some(good_sha256))];
+        let jobs: Vec<ToolJob> = specs.iter().map(|spec| ToolJob { key: spec.name.clone(), spec, cuda: false }).collect();
+        // Only the archive that exists is sized, for its download and its unpacking
+        let good_len = fs::metadata(artifacts.join("good.tar.gz")).unwrap().len();
+        assert_eq!(estimate_tool_bytes(&jobs), 2 * good_len);
+        let steps = Mutex::new(Vec::new());
+        let record = |key: String, done: usize, total: usize| steps.lock().unwrap().push((key, done, total));
+        let outcomes = run_tool_jobs(&ps_env, &jobs, 2, Some(&record));
+
+        assert!(matches!(outcomes[0], ToolOutcome::Failed(_)));
+        assert!(matches!(outcomes[1], ToolOutcome::Installed(_)));
+        assert_eq!(fs::read(ps_env.join("good/bin/run")).unwrap(), b"ok");
+        let steps = steps.into_inner().unwrap();
+        assert_eq!(steps.len(), 4);
+        assert!(steps.iter().any(|(_, done, total)| *done == 4 && *total == 4));
+    }
 }
\ No newline at end of file
@@ -51,6 +51,9 @@ pub enum PortableSourceError {
     #[error("Environment error: {message}")]
     Environment { message: String },
     
+    #[error("Installation plan error: {message}")]
+    Plan { message: String },
+    
     #[error("Command execution failed: {message}")]
     Command { message: String },
     
@@ -95,6 +98,12 @@ impl PortableSourceError {
         }
     }
     
+    pub fn plan(message: impl Into<String>) -> Self {
+        Self::Plan {
+            message: message.into(),
+        }
+    }
+    
     pub fn command(message: impl Into<String>) -> Self {
         Self::Command {
             message: message.into(),
@@ -18,13 +18,13 @@
 
 use crate::{Result, PortableSourceError};
 use crate::envs_manager::PortableEnvironmentManager;
+use crate::installer::executor::{CommandExecutor, CommandOutput, CommandRequest, OutputMode, ProcessExecutor};
 use log::{info, debug};
-use std::io::{BufRead, BufReader};
-use std::path::Path;
-use std::process::{Command, Stdio};
-
-#[cfg(windows)]
-use std::os::windows::process::CommandExt;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // Enum для типизации команд. Он может остаться здесь.
 #[derive(Clone, Copy, Debug)]
@@ -36,43 +36,163 @@ pub enum CommandType {
     Other,
 }
 
+/// Действие, которое в режиме dry-run записывается вместо выполнения.
+#[derive(Clone, Debug)]
+pub enum PlannedAction {
+    Command { args: Vec<String>, cwd: Option<PathBuf>, label: Option<String> },
+    WriteFile(PathBuf),
+    CopyDir { from: PathBuf, to: PathBuf },
+    RemoveDir(PathBuf),
+    Note(String),
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedAction::Command { args, cwd, label } => {
+                write!(f, "run    {}", args.join(" "))?;
+                if let Some(dir) = cwd { write!(f, "\n         (in {})", dir.display())?; }
+                if let Some(l) = label { write!(f, "\n         # {}", l)?; }
+                Ok(())
+            }
+            PlannedAction::WriteFile(path) => write!(f, "write  {}", path.display()),
+            PlannedAction::CopyDir { from, to } => write!(f, "copy   {} -> {}", from.display(), to.display()),
+            PlannedAction::RemoveDir(path) => write!(f, "remove {}", path.display()),
+            PlannedAction::Note(note) => write!(f, "note   {}", note),
+        }
+    }
+}
+
+/// Журнал dry-run: действия по порядку и "виртуальные" файлы, которые были бы записаны.
+#[derive(Default)]
+struct DryRunLog {
+    actions: Vec<PlannedAction>,
+    files: HashMap<PathBuf, String>,
+}
+
 /// CommandRunner - это централизованный исполнитель всех внешних команд.
 /// Он держит ссылку на EnvironmentManager, чтобы правильно настраивать окружение.
+/// В режиме dry-run команды и изменения файлов только записываются.
+/// Сами процессы запускает `CommandExecutor` (реальный, запись или воспроизведение транскрипта).
 pub struct CommandRunner<'a> {
     env_manager: &'a PortableEnvironmentManager,
+    executor: Arc<dyn CommandExecutor>,
+    dry_run: Option<RefCell<DryRunLog>>,
 }
 
 
 impl<'a> CommandRunner<'a> {
     pub fn new(env_manager: &'a PortableEnvironmentManager) -> Self {
-        Self { env_manager }
+        Self::with_executor(env_manager, Arc::new(ProcessExecutor))
+    }
+
+    /// Runner с другим исполнителем (запись/воспроизведение транскрипта, тесты)
+    pub fn with_executor(env_manager: &'a PortableEnvironmentManager, executor: Arc<dyn CommandExecutor>) -> Self {
+        Self { env_manager, executor, dry_run: None }
+    }
+
+    /// Runner, который ничего не выполняет, а только записывает план действий
+    pub fn new_dry_run(env_manager: &'a PortableEnvironmentManager) -> Self {
+        Self { env_manager, executor: Arc::new(ProcessExecutor), dry_run: Some(RefCell::new(DryRunLog::default())) }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    /// Записать действие в план (только в режиме dry-run)
+    pub fn record(&self, action: PlannedAction) {
+        if let Some(log) = &self.dry_run {
+            log.borrow_mut().actions.push(action);
+        }
+    }
+
+    /// Записанные действия по порядку (пусто вне dry-run)
+    pub fn planned_actions(&self) -> Vec<PlannedAction> {
+        self.dry_run.as_ref().map(|log| log.borrow().actions.clone()).unwrap_or_default()
+    }
+
+    /// Записать файл (в dry-run файл только запоминается)
+    pub fn write_file(&self, path: &Path, content: &str) -> Result<()> {
+        if let Some(log) = &self.dry_run {
+            let mut log = log.borrow_mut();
+            log.actions.push(PlannedAction::WriteFile(path.to_path_buf()));
+            log.files.insert(path.to_path_buf(), content.to_string());
+            return Ok(());
+        }
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Прочитать файл с учетом файлов, "записанных" в dry-run
+    pub fn read_to_string(&self, path: &Path) -> Result<String> {
+        if let Some(log) = &self.dry_run {
+            if let Some(content) = log.borrow().files.get(path) {
+                return Ok(content.clone());
+            }
+        }
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    /// Существует ли файл (с учетом файлов, "записанных" в dry-run)
+    pub fn file_exists(&self, path: &Path) -> bool {
+        if let Some(log) = &self.dry_run {
+            if log.borrow().files.contains_key(path) {
+                return true;
+            }
+        }
+        path.exists()
+    }
+
+    /// Удалить директорию (в dry-run только записывается)
+    pub fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        if self.is_dry_run() {
+            self.record(PlannedAction::RemoveDir(path.to_path_buf()));
+            return Ok(());
+        }
+        std::fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    /// Возвращает true, если команда была записана вместо выполнения
+    fn record_command(&self, args: &[String], label: Option<&str>, cwd: Option<&Path>) -> bool {
+        if !self.is_dry_run() { return false; }
+        self.record(PlannedAction::Command {
+            args: args.to_vec(),
+            cwd: cwd.map(Path::to_path_buf),
+            label: label.map(str::to_string),
+        });
+        true
     }
 
     /// Публичный метод для запуска команды с логированием
     pub fn run(&self, args: &[String], label: Option<&str>, cwd: Option<&Path>) -> Result<()> {
         if args.is_empty() { return Ok(()); }
-        
-        // false = скрывать окно (стандартное поведение)
-        let mut cmd = self.create_command(args, cwd, false);
-        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
-        
-        let command_type = self.determine_command_type(args);
-        
-        self.run_with_progress(cmd, label, command_type)
+        if self.record_command(args, label, cwd) { return Ok(()); }
+        if let Some(l) = label { info!("{}...", l); }
+
+        let output = self.execute(args, cwd, OutputMode::Captured, BTreeMap::new())?;
+        self.check_output(args, &output)
+    }
+
+    /// Запустить команду и вернуть ее вывод (ненулевой код возврата не считается ошибкой).
+    /// В dry-run команда записывается, а возвращается пустой успешный результат.
+    pub fn output(&self, args: &[String], cwd: Option<&Path>) -> Result<CommandOutput> {
+        if args.is_empty() || self.record_command(args, None, cwd) {
+            return Ok(CommandOutput { exit_code: Some(0), ..Default::default() });
+        }
+        self.execute(args, cwd, OutputMode::Captured, BTreeMap::new())
     }
 
     /// Публичный метод для "тихого" запуска
     pub fn run_silent(&self, args: &[String], label: Option<&str>, cwd: Option<&Path>) -> Result<()> {
         if args.is_empty() { return Ok(()); }
+        if self.record_command(args, label, cwd) { return Ok(()); }
         if let Some(l) = label { info!("{}...", l); }
 
-        // false = скрывать окно
-        let mut cmd = self.create_command(args, cwd, false);
-        cmd.stdout(Stdio::null()).stderr(Stdio::null());
-        
-        let status = cmd.status().map_err(|e| PortableSourceError::command(e.to_string()))?;
-        if !status.success() {
-            return Err(PortableSourceError::command(format!("Silent command failed with status: {}", status)));
+        let output = self.execute(args, cwd, OutputMode::Silent, BTreeMap::new())?;
+        if !output.success() {
+            return Err(PortableSourceError::command(format!("Silent command failed with {}", output.status_text())));
         }
         Ok(())
     }
@@ -80,57 +200,42 @@ impl<'a> CommandRunner<'a> {
     /// Запуск команды с прямым выводом в консоль (для отображения прогресс-баров)
     pub fn run_verbose(&self, args: &[String], label: Option<&str>, cwd: Option<&Path>) -> Result<()> {
         if args.is_empty() { return Ok(()); }
-        
+        if self.record_command(args, label, cwd) { return Ok(()); }
         if let Some(l) = label { info!("{}...", l); }
 
-        // true = НЕ СКРЫВАТЬ ОКНО (Важное изменение!)
-        let mut cmd = self.create_command(args, cwd, true);
-        
-        // Направляем вывод прямо в консоль пользователя
-        cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
-        
         // Принудительно включаем прогресс-бары для pip/uv, даже если они думают, что это не TTY
-        cmd.env("PYTHONUNBUFFERED", "1");
-        cmd.env("PIP_PROGRESS_BAR", "on"); // Заставляет pip показывать полоску
-        cmd.env("FORCE_COLOR", "1");       // Заставляет инструменты использовать цвета
-        
-        let status = cmd.status().map_err(|e| PortableSourceError::command(e.to_string()))?;
-        
-        if !status.success() {
-            return Err(PortableSourceError::command(format!("Command failed with status: {}", status)));
+        let extra_env = BTreeMap::from([
+            ("PYTHONUNBUFFERED".to_string(), "1".to_string()),
+            ("PIP_PROGRESS_BAR".to_string(), "on".to_string()), // Заставляет pip показывать полоску
+            ("FORCE_COLOR".to_string(), "1".to_string()),       // Заставляет инструменты использовать цвета
+        ]);
+
+        // Inherited: вывод идет прямо в консоль пользователя (при записи transcript еще и сохраняется), окно на Windows не скрывается
+        let output = self.execute(args, cwd, OutputMode::Inherited, extra_env)?;
+        if !output.success() {
+            return Err(PortableSourceError::command(format!("Command failed with {}", output.status_text())));
         }
         Ok(())
     }
 
     // --- Приватные хелперы ---
 
-    /// Создает объект `Command` с настроенным окружением.
-    /// visible: если true, окно консоли будет видно (для run_verbose).
-    fn create_command(&self, args: &[String], cwd: Option<&Path>, visible: bool) -> Command {
-        // ИЗМЕНЕНИЕ ЗДЕСЬ: Добавляем эту строку, чтобы компилятор не ругался на Linux
-        #[cfg(not(windows))]
-        let _ = visible; 
-		
-        let mut cmd = Command::new(&args[0]);
-        cmd.args(&args[1..]);
-        if let Some(dir) = cwd {
-            cmd.current_dir(dir);
-        }
-        let envs = self.env_manager.setup_environment_for_subprocess();
-        cmd.envs(envs);
-        
-        // Hide console window on Windows ONLY if visible is false
-        #[cfg(windows)]
-        {
-            if !visible {
-                use std::os::windows::process::CommandExt;
-                cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-            }
-        }
-        
-        cmd
+    /// Собирает запрос с настроенным окружением и передает его исполнителю.
+    /// В запрос попадают только переменные, отличающиеся от окружения текущего процесса.
+    fn execute(&self, args: &[String], cwd: Option<&Path>, mode: OutputMode, extra_env: BTreeMap<String, String>) -> Result<CommandOutput> {
+        let mut env = env_delta(self.env_manager.setup_environment_for_subprocess());
+        env.extend(extra_env);
+
+        let request = CommandRequest {
+            args: args.to_vec(),
+            cwd: cwd.map(Path::to_path_buf),
+            env,
+            mode,
+            capture: false,
+        };
+        self.executor.execute(&request)
     }
-    
+
     /// Определяет тип команды по аргументам.
     fn determine_command_type(&self, args: &[String]) -> CommandType {
         // (Оставьте старый код этого метода)
@@ -164,44 +269,34 @@ impl<'a> CommandRunner<'a> {
         }
     }
 
-    fn run_with_progress(&self, mut cmd: Command, label: Option<&str>, command_type: CommandType) -> Result<()> {
-        // (Оставьте старый код этого метода)
-        if let Some(l) = label { info!("{}...", l); }
-        let mut child = cmd.spawn().map_err(|e| PortableSourceError::command(e.to_string()))?;
-        
-        let mut stderr_lines = Vec::new();
-        
-        let error_prefix = match command_type {
+    fn check_output(&self, args: &[String], output: &CommandOutput) -> Result<()> {
+        for line in output.stdout.lines() { debug!("[stdout] {}", line); }
+        for line in output.stderr.lines() { debug!("[stderr] {}", line); }
+        if output.success() {
+            return Ok(());
+        }
+
+        let error_prefix = match self.determine_command_type(args) {
             CommandType::Git => "Git command failed",
             CommandType::Pip => "Pip command failed",
             CommandType::Uv => "UV command failed",
             CommandType::Python => "Python command failed",
             CommandType::Other => "Command failed",
         };
-        
-        if let Some(out) = child.stdout.take() {
-            let reader = BufReader::new(out);
-            for line in reader.lines().flatten() { debug!("[stdout] {}", line); }
-        }
-        
-        if let Some(err) = child.stderr.take() {
-            let reader = BufReader::new(err);
-            for line in reader.lines().flatten() {
-                debug!("[stderr] {}", line);
-                stderr_lines.push(line);
-            }
-        }
-        
-        let status = child.wait().map_err(|e| PortableSourceError::command(e.to_string()))?;
-        if !status.success() {
-            let error_msg = if !stderr_lines.is_empty() {
-                format!("Command failed with status: {}\nOutput:\n{}", status, stderr_lines.join("\n"))
-            } else {
-                format!("Command failed with status: {}", status)
-            };
-            debug!("{}: {}", error_prefix, error_msg);
-            return Err(PortableSourceError::command(error_msg));
-        }
-        Ok(())
+        let stderr = output.stderr.trim_end();
+        let error_msg = if !stderr.is_empty() {
+            format!("Command failed with {}\nOutput:\n{}", output.status_text(), stderr)
+        } else {
+            format!("Command failed with {}", output.status_text())
+        };
+        debug!("{}: {}", error_prefix, error_msg);
+        Err(PortableSourceError::command(error_msg))
     }
-}
\ No newline at end of file
+}
+
+/// Переменные окружения, которые отличаются от окружения текущего процесса
+fn env_delta(envs: HashMap<String, String>) -> BTreeMap<String, String> {
+    envs.into_iter()
+        .filter(|(key, value)| std::env::var(key).ok().as_deref() != Some(value.as_str()))
+        .collect()
+}
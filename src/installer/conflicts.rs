@@ -0,0 +1,206 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Dependency conflicts reported by `pip check` / `uv pip check`.
+//!
+//! Requirements are installed in several passes (base packages, ONNX Runtime, torch,
+//! InsightFace), and a later pass can break what an earlier one installed. The check
+//! at the end of an install shows what is left broken.
+
+use crate::installer::requirements::Requirement;
+use std::fmt;
+
+/// A requirement of an installed package that is not satisfied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DependencyConflict {
+    /// Package whose requirement is broken
+    pub package: String,
+    /// Its version (pip reports it, uv does not)
+    pub version: Option<String>,
+    /// The requirement as declared, e.g. `numpy<2`
+    pub requirement: String,
+    /// Installed version of the required package; `None` when it is missing
+    pub installed: Option<String>,
+}
+
+impl DependencyConflict {
+    /// Name of the required package
+    pub fn required_name(&self) -> String {
+        Requirement::parse(&self.requirement)
+            .map(|r| r.name)
+            .unwrap_or_else(|_| self.requirement.clone())
+    }
+}
+
+/// Parsed output of a dependency check
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyCheck {
+    pub conflicts: Vec<DependencyConflict>,
+    /// Reported problems in a format we do not know (e.g. unsupported platform)
+    pub other: Vec<String>,
+}
+
+impl DependencyCheck {
+    /// Parse `pip check` or `uv pip check` output. Conflicts are read from both streams.
+    /// Unknown lines count as problems only from stdout of a failed check: stderr also
+    /// carries notices (uv's `Using Python ... environment at: ...`), and a check that
+    /// exited 0 without a conflict line is clean.
+    pub fn parse(stdout: &str, stderr: &str, success: bool) -> Self {
+        let mut check = Self::default();
+        for (text, from_stdout) in [(stdout, true), (stderr, false)] {
+            for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                if let Some(conflict) = parse_pip_line(line).or_else(|| parse_uv_line(line)) {
+                    if !check.conflicts.contains(&conflict) {
+                        check.conflicts.push(conflict);
+                    }
+                } else if from_stdout && !success && !is_status_line(line) {
+                    check.other.push(line.to_string());
+                }
+            }
+        }
+        check
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty() && self.other.is_empty()
+    }
+
+    pub fn count(&self) -> usize {
+        self.conflicts.len() + self.other.len()
+    }
+}
+
+/// Summary table: package, requirement, what is installed
+impl fmt::Display for DependencyCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<[String; 3]> = self.conflicts.iter()
+            .map(|c| [
+                match &c.version {
+                    Some(version) => format!("{} {}", c.package, version),
+                    None => c.package.clone(),
+                },
+                c.requirement.clone(),
+                match &c.installed {
+                    Some(version) => format!("{} {}", c.required_name(), version),
+                    None => "not installed".to_string(),
+                },
+            ])
+            .collect();
+        let header = ["package".to_string(), "requires".to_string(), "installed".to_string()];
+        let widths: Vec<usize> = (0..3)
+            .map(|i| rows.iter().chain([&header]).map(|row| row[i].len()).max().unwrap_or(0))
+            .collect();
+        if !rows.is_empty() {
+            for row in [&header].into_iter().chain(&rows) {
+                writeln!(f, "  {:<w0$}  {:<w1$}  {}", row[0], row[1], row[2], w0 = widths[0], w1 = widths[1])?;
+            }
+        }
+        for line in &self.other {
+            writeln!(f, "  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// `torch 2.7.0 has requirement numpy<2, but you have numpy 2.1.0.` or
+/// `insightface 0.7.3 requires onnx, which is not installed.`
+fn parse_pip_line(line: &str) -> Option<DependencyConflict> {
+    let line = line.trim_end_matches('.');
+    let (package, rest) = line.split_once(' ')?;
+    let (version, rest) = rest.split_once(' ')?;
+    let (requirement, installed) = if let Some(rest) = rest.strip_prefix("has requirement ") {
+        let (requirement, have) = rest.rsplit_once(", but you have ")?;
+        (requirement, Some(have.rsplit(' ').next()?.to_string()))
+    } else {
+        (rest.strip_prefix("requires ")?.strip_suffix(", which is not installed")?, None)
+    };
+    Some(DependencyConflict {
+        package: package.to_string(),
+        version: Some(version.to_string()),
+        requirement: requirement.to_string(),
+        installed,
+    })
+}
+
+/// ``The package `torch` requires `numpy<2`, but `2.1.0` is installed`` or
+/// ``The package `insightface` requires `onnx`, but it's not installed``
+fn parse_uv_line(line: &str) -> Option<DependencyConflict> {
+    let rest = line.strip_prefix("The package `")?;
+    let (package, rest) = rest.split_once("` requires `")?;
+    let (requirement, rest) = rest.split_once("`, but ")?;
+    let installed = match rest.strip_prefix('`') {
+        Some(rest) => Some(rest.split_once('`')?.0.to_string()),
+        None if rest.starts_with("it's not installed") => None,
+        None => return None,
+    };
+    Some(DependencyConflict {
+        package: package.to_string(),
+        version: None,
+        requirement: requirement.to_string(),
+        installed,
+    })
+}
+
+/// Lines that are not problems: "No broken requirements found.", uv's counters
+fn is_status_line(line: &str) -> bool {
+    line.starts_with("No broken requirements")
+        || line.starts_with("Checked ")
+        || line.starts_with("Found ")
+        || line.starts_with("All installed packages are compatible")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pip_and_uv_check() {
+        let pip = DependencyCheck::parse(
+            "torch 2.7.0 has requirement numpy<2, but you have numpy 2.1.0.\n\
+             insightface 0.7.3 requires onnx, which is not installed.\n\
+             foo 1.0 is not supported on this platform\n",
+            "",
+            false,
+        );
+        assert_eq!(pip.conflicts, vec![
+            DependencyConflict { package: "torch".into(), version: Some("2.7.0".into()), requirement: "numpy<2".into(), installed: Some("2.1.0".into()) },
+            DependencyConflict { package: "insightface".into(), version: Some("0.7.3".into()), requirement: "onnx".into(), installed: None },
+        ]);
+        assert_eq!(pip.other, vec!["foo 1.0 is not supported on this platform"]);
+        assert!(pip.to_string().contains("  torch 2.7.0        numpy<2   numpy 2.1.0\n"));
+
+        let uv = DependencyCheck::parse(
+            "",
+            "Using Python 3.11.9 environment at: envs/demo\n\
+             Checked 42 packages in 3ms\nFound 2 incompatibilities\n\
+             The package `torch` requires `numpy<2`, but `2.1.0` is installed\n\
+             The package `insightface` requires `onnx`, but it's not installed\n",
+            false,
+        );
+        assert_eq!(uv.count(), 2);
+        assert_eq!(uv.conflicts[0].installed.as_deref(), Some("2.1.0"));
+        assert_eq!(uv.conflicts[1].installed, None);
+
+        // A healthy env: the preamble on stderr is not a conflict
+        let uv = DependencyCheck::parse(
+            "",
+            "Using Python 3.11.9 environment at: envs/demo\nChecked 42 packages in 3ms\nAll installed packages are compatible\n",
+            true,
+        );
+        assert!(uv.is_clean());
+        assert!(DependencyCheck::parse("No broken requirements found.\n", "", true).is_clean());
+    }
+}
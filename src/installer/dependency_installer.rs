@@ -16,19 +16,22 @@
 
 //! Dependency installer module for managing Python environments and package installations.
 
-use crate::installer::{PipManager, ServerClient};
+use crate::installer::{PipManager, PlanSource, PlannedAction, ProjectDependencies, ProjectFile, RepoLock, ServerClient, ServerInstallPlan};
+use crate::config::PythonVersion;
 
 use crate::PortableSourceError;
 use crate::Result;
 use log::{info, warn};
 use std::path::{Path, PathBuf};
 use std::fs;
-use serde_json::Value as JsonValue;
 
 pub struct DependencyInstaller<'a> {
     pip_manager: &'a PipManager<'a>,
     server_client: &'a ServerClient,
     install_path: PathBuf,
+    python_version: Option<PythonVersion>,
+    requirements: Vec<PathBuf>,
+    extras: Vec<String>,
 }
 
 impl<'a> DependencyInstaller<'a> {
@@ -41,22 +44,68 @@ impl<'a> DependencyInstaller<'a> {
             pip_manager,
             server_client,
             install_path,
+            python_version: None,
+            requirements: Vec::new(),
+            extras: Vec::new(),
         }
     }
 
-    /// Main entry point for installing dependencies for a repository
-    pub async fn install_dependencies(&self, repo_path: &Path) -> Result<()> {
+    /// Use a specific portable Python version instead of the default one
+    pub fn with_python_version(mut self, python_version: Option<PythonVersion>) -> Self {
+        self.python_version = python_version;
+        self
+    }
+
+    /// Install exactly these requirements files instead of discovering them.
+    /// Relative paths are resolved against the repository folder
+    pub fn with_requirements(mut self, requirements: Vec<PathBuf>) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    /// Extras and dependency groups to install from pyproject.toml / setup.cfg
+    pub fn with_extras(mut self, extras: Vec<String>) -> Self {
+        self.extras = extras;
+        self
+    }
+
+    /// Main entry point for installing dependencies for a repository.
+    /// Returns what the dependencies were installed from.
+    pub async fn install_dependencies(&self, repo_path: &Path) -> Result<PlanSource> {
         info!("Installing dependencies for: {:?}", repo_path);
         let repo_name = repo_path.file_name().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
 
-        // Ensure project environment exists (Windows: copy portable python; Linux: create venv)
-        self.create_venv_environment(&repo_name)?;
+        // Ensure project environment exists (Windows: copy portable python; Linux: create venv).
+        // Downloading into a wheelhouse uses the existing environment for its Python version and platform
+        if !self.pip_manager.is_download_only() {
+            self.create_venv_environment(&repo_name)?;
+        }
 
-        // Try server installation plan first
-        if let Some(plan) = self.server_client.get_installation_plan(&repo_name)? {
+        // Explicit requirements files take precedence over the server plan and local discovery
+        if !self.requirements.is_empty() {
+            let files: Vec<PathBuf> = self.requirements.iter().map(|file| repo_path.join(file)).collect();
+            if !self.pip_manager.command_runner().is_dry_run() {
+                if let Some(missing) = files.iter().find(|file| !file.exists()) {
+                    return Err(PortableSourceError::repository(format!("Requirements file not found: {:?}", missing)));
+                }
+            }
+            if !self.extras.is_empty() {
+                warn!("Extras {} ignored: --requirements installs only the given files", self.extras.join(","));
+            }
+            return self.install_requirement_files(&repo_name, repo_path, &files, "given with --requirements");
+        }
+
+        // Try server installation plan first; a wheelhouse is always built from and installed with
+        // the local files, so the download and the offline install resolve the same packages.
+        // The server plan knows nothing about extras, so they are read from the project files
+        if self.pip_manager.wheelhouse().is_some() {
+            info!("Using a wheelhouse, the server installation plan is not used");
+        } else if !self.extras.is_empty() {
+            info!("Extras {} requested, the server installation plan is not used", self.extras.join(","));
+        } else if let Some(plan) = self.server_client.get_installation_plan(&repo_name)? {
             info!("Using server installation plan");
             if self.execute_server_installation_plan(&repo_name, &plan, Some(repo_path))? {
-                return Ok(());
+                return Ok(PlanSource::Server);
             } else {
                 warn!("Server installation failed, falling back to local requirements.txt");
             }
@@ -64,32 +113,112 @@ impl<'a> DependencyInstaller<'a> {
             info!("No server installation plan, using local files");
         }
 
+        if !repo_path.exists() && self.pip_manager.command_runner().is_dry_run() {
+            self.pip_manager.command_runner().record(PlannedAction::Note(
+                "Repository is not cloned yet; local requirements files are discovered after cloning".into()
+            ));
+        }
+
         // Check for pyproject.toml first
-        let pyproject_path = repo_path.join("pyproject.toml");
-        if pyproject_path.exists() {
-            info!("Found pyproject.toml, extracting dependencies");
-            if let Ok(requirements_path) = self.pip_manager.extract_dependencies_from_pyproject(&pyproject_path, repo_path) {
-                info!("Installing from extracted pyproject.toml dependencies: {:?}", requirements_path);
-                self.pip_manager.install_requirements_with_uv_or_pip(&repo_name, &requirements_path, Some(repo_path))?;
-                
-                // Install the repository itself as a package
-                info!("Installing repository as package with uv pip install .");
-                self.pip_manager.install_repo_as_package(&repo_name, repo_path)?;
-                
-                return Ok(());
-            } else {
-                warn!("Failed to extract dependencies from pyproject.toml, falling back to requirements.txt");
+        match ProjectDependencies::load_pyproject(repo_path) {
+            Ok(Some(project)) => {
+                if let Some(source) = self.install_project(&repo_name, repo_path, &project)? {
+                    return Ok(source);
+                }
+                info!("pyproject.toml declares no dependencies, falling back to requirements.txt");
             }
+            Ok(None) => {}
+            Err(e) => warn!("{}, falling back to requirements.txt", e),
         }
 
         // Fallback to requirements.txt variants using smart search
-        if let Some(requirements_file) = self.pip_manager.find_requirements_files(repo_path) {
-            info!("Installing from {:?}", requirements_file);
-            self.pip_manager.install_requirements_with_uv_or_pip(&repo_name, &requirements_file, Some(repo_path))?;
+        let requirements_files = self.pip_manager.find_requirements_files(repo_path);
+        if !requirements_files.is_empty() {
+            if !self.extras.is_empty() {
+                warn!("Extras {} ignored: they are only read from pyproject.toml or setup.cfg", self.extras.join(","));
+            }
+            let reason = format!("for {}", self.pip_manager.hardware_profile());
+            return self.install_requirement_files(&repo_name, repo_path, &requirements_files, &reason);
+        }
+
+        // setup.cfg install_requires as the last resort
+        match ProjectDependencies::load_setup_cfg(repo_path) {
+            Ok(Some(project)) => {
+                if let Some(source) = self.install_project(&repo_name, repo_path, &project)? {
+                    return Ok(source);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{}", e),
+        }
+
+        info!("No requirements.txt, pyproject.toml or setup.cfg dependencies found");
+        Ok(PlanSource::Empty)
+    }
+
+    /// Install the dependencies declared by the project (with the selected extras) and the
+    /// project itself. Returns `None` when there is nothing to install.
+    fn install_project(&self, repo_name: &str, repo_path: &Path, project: &ProjectDependencies) -> Result<Option<PlanSource>> {
+        let dependencies = project.select(&self.extras)?;
+        if dependencies.is_empty() {
+            return Ok(None);
+        }
+        let file_name = project.file.file_name();
+        if self.extras.is_empty() {
+            println!("[PortableSource] Dependencies from {}", file_name);
         } else {
-            info!("No requirements.txt or pyproject.toml found");
+            println!("[PortableSource] Dependencies from {} with {}", file_name, self.extras.join(", "));
         }
-        Ok(())
+        let requirements_path = self.pip_manager.write_project_requirements(&dependencies, repo_path)?;
+        self.pip_manager.install_requirements_with_uv_or_pip(repo_name, &requirements_path, Some(repo_path))?;
+
+        // Install the repository itself as a package
+        if project.is_package {
+            info!("Installing repository as package");
+            self.pip_manager.install_repo_as_package(repo_name, repo_path)?;
+        } else {
+            info!("{} does not describe a buildable package, not installing the repository itself", file_name);
+        }
+
+        Ok(Some(match project.file {
+            ProjectFile::Pyproject => PlanSource::Pyproject,
+            ProjectFile::SetupCfg => PlanSource::SetupCfg,
+        }))
+    }
+
+    /// Install the given requirements files; the plan source lists them relative to the repository
+    fn install_requirement_files(&self, repo_name: &str, repo_path: &Path, files: &[PathBuf], reason: &str) -> Result<PlanSource> {
+        info!("Installing from {:?}", files);
+        let names: Vec<String> = files.iter()
+            .map(|file| file.strip_prefix(repo_path).unwrap_or(file).to_string_lossy().replace('\\', "/"))
+            .collect();
+        println!("[PortableSource] Requirements files: {} ({})", names.join(", "), reason);
+        self.pip_manager.install_requirement_files(repo_name, files, Some(repo_path))?;
+        Ok(PlanSource::Requirements(names.join(", ")))
+    }
+
+    /// Recreate the environment with exactly the packages from a lockfile
+    pub fn install_locked(&self, repo_path: &Path, lock: &RepoLock, lock_path: &Path) -> Result<PlanSource> {
+        info!("Installing locked dependencies for {:?} from {:?}", repo_path, lock_path);
+        let repo_name = repo_path.file_name().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+        let runner = self.pip_manager.command_runner();
+
+        self.create_venv_environment(&repo_name)?;
+
+        let requirements = repo_path.join("requirements_locked.txt");
+        runner.write_file(&requirements, &lock.requirements())?;
+        let result = self.pip_manager.install_locked(&repo_name, &requirements, lock.torch_index_url.as_deref(), &lock.extra_index_urls, Some(repo_path));
+        if !runner.is_dry_run() {
+            let _ = fs::remove_file(&requirements);
+        }
+        result?;
+
+        if lock.install_repository {
+            self.pip_manager.install_repo_as_package(&repo_name, repo_path)?;
+        }
+
+        let relative = lock_path.strip_prefix(repo_path).unwrap_or(lock_path);
+        Ok(PlanSource::Lockfile(relative.to_string_lossy().replace('\\', "/")))
     }
 
     /// Create virtual environment for the repository
@@ -97,22 +226,33 @@ impl<'a> DependencyInstaller<'a> {
         let install_path = self.install_path.clone();
         let envs_path = install_path.join("envs");
         let venv_path = envs_path.join(repo_name);
+        let runner = self.pip_manager.command_runner();
         
         // Remove existing environment if present
         if venv_path.exists() { 
-            fs::remove_dir_all(&venv_path)?; 
+            runner.remove_dir_all(&venv_path)?; 
         }
 
         if cfg!(windows) {
             // Windows: копируем портативный Python в envs/{repo}
             // Определяем версию Python для использования
-            let config_manager = crate::config::ConfigManager::new(Some(install_path.join("portablesource_config.json")))?;
-            let python_version = config_manager.get_default_python_version();
+            let python_version = match &self.python_version {
+                Some(v) => v.clone(),
+                None => {
+                    let mut config_manager = crate::config::ConfigManager::new(Some(install_path.join("portablesource_config.json")))?;
+                    config_manager.get_config_mut().install_path = install_path.clone();
+                    config_manager.get_default_python_version()
+                }
+            };
             let ps_env_python = install_path.join("ps_env").join(python_version.folder_name());
             
             if !ps_env_python.exists() { 
                 return Err(PortableSourceError::installation(format!("Portable Python {} not found at: {:?}", python_version.as_str(), ps_env_python))); 
             }
+            if runner.is_dry_run() {
+                runner.record(PlannedAction::CopyDir { from: ps_env_python, to: venv_path });
+                return Ok(());
+            }
             info!("Creating environment by copying portable Python {}: {:?} -> {:?}", python_version.as_str(), ps_env_python, venv_path);
             self.copy_dir_recursive(&ps_env_python, &venv_path)?;
             let python_exe = venv_path.join("python.exe");
@@ -121,7 +261,9 @@ impl<'a> DependencyInstaller<'a> {
             }
         } else {
             // Linux: в DESK режиме используем python из micromamba-базы, в CLOUD — системный python3
-            fs::create_dir_all(&envs_path)?;
+            if !runner.is_dry_run() {
+                fs::create_dir_all(&envs_path)?;
+            }
             let mamba_py = install_path.join("ps_env").join("mamba_env").join("bin").join("python");
             
             #[cfg(unix)]
@@ -134,60 +276,28 @@ impl<'a> DependencyInstaller<'a> {
             #[cfg(not(unix))]
             let py_bin = mamba_py; // unreachable, just to satisfy type
             
-            let status = {
-                let mut cmd = std::process::Command::new(&py_bin);
-                cmd.args(["-m", "venv", venv_path.to_string_lossy().as_ref()]);
-                
-                // Hide console window on Windows
-                #[cfg(windows)]
-                {
-                    use std::os::windows::process::CommandExt;
-                    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-                }
-                
-                cmd.status()
-                    .map_err(|e| PortableSourceError::environment(format!("Failed to create venv: {}", e)))?
-            };
-            if !status.success() {
-                return Err(PortableSourceError::environment("python -m venv failed"));
-            }
+            let venv_args = vec![
+                py_bin.to_string_lossy().to_string(),
+                "-m".into(),
+                "venv".into(),
+                venv_path.to_string_lossy().to_string(),
+            ];
+            runner.run(&venv_args, Some("Creating virtual environment"), None)
+                .map_err(|e| PortableSourceError::environment(format!("Failed to create venv: {}", e)))?;
             
             // Ensure pip is present in the new venv
-            let venv_py = venv_path.join("bin").join("python");
-            let pip_ok = {
-                let mut cmd = std::process::Command::new(&venv_py);
-                cmd.args(["-m", "pip", "--version"]);
-                
-                // Hide console window on Windows
-                #[cfg(windows)]
-                {
-                    use std::os::windows::process::CommandExt;
-                    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-                }
-                
-                cmd.status()
-                    .map(|s| s.success())
-                    .unwrap_or(false)
-            };
-            if !pip_ok {
-                let mut cmd = std::process::Command::new(&venv_py);
-                cmd.args(["-m", "ensurepip", "-U"]);
-                
-                // Hide console window on Windows
-                #[cfg(windows)]
-                {
-                    use std::os::windows::process::CommandExt;
-                    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-                }
-                
-                let _ = cmd.status();
+            let venv_py = venv_path.join("bin").join("python").to_string_lossy().to_string();
+            let pip_check = vec![venv_py.clone(), "-m".into(), "pip".into(), "--version".into()];
+            if runner.run_silent(&pip_check, None, None).is_err() {
+                let ensurepip = vec![venv_py, "-m".into(), "ensurepip".into(), "-U".into()];
+                let _ = runner.run_silent(&ensurepip, Some("Bootstrapping pip"), None);
             }
         }
         Ok(())
     }
 
     /// Execute server installation plan
-    fn execute_server_installation_plan(&self, repo_name: &str, plan: &JsonValue, repo_path: Option<&Path>) -> Result<bool> {
+    fn execute_server_installation_plan(&self, repo_name: &str, plan: &ServerInstallPlan, repo_path: Option<&Path>) -> Result<bool> {
         self.pip_manager.execute_server_installation_plan(repo_name, plan, repo_path)
     }
 
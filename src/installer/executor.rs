@@ -0,0 +1,408 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Command executors used by `CommandRunner`.
+//!
+//! `ProcessExecutor` spawns real processes, `RecordingExecutor` wraps another
+//! executor and writes every command with its result into a transcript file,
+//! and `ReplayExecutor` answers commands from such a transcript without
+//! spawning anything.
+
+use crate::{Result, PortableSourceError};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// Current transcript file format version
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+/// What happens with the output of a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    /// stdout/stderr are captured and returned
+    Captured,
+    /// stdout/stderr are not shown to the user; kept only when the request is captured
+    Silent,
+    /// stdout/stderr go to the user's console (a TTY keeps progress bars and colour);
+    /// a captured request tees them into the result as they arrive
+    Inherited,
+}
+
+/// Command to execute
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandRequest {
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Variables set on top of the parent process environment
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    pub mode: OutputMode,
+    /// Keep the output of `Silent` and `Inherited` commands too; set by `RecordingExecutor`
+    /// for its transcript, at the cost of piping the child's output
+    #[serde(skip)]
+    pub capture: bool,
+}
+
+/// Result of a finished command
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandOutput {
+    /// `None` when the process was terminated by a signal
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    pub fn status_text(&self) -> String {
+        match self.exit_code {
+            Some(code) => format!("exit code: {}", code),
+            None => "terminated by signal".to_string(),
+        }
+    }
+}
+
+/// Executes commands for `CommandRunner`
+pub trait CommandExecutor: Send + Sync {
+    /// Run a command; `Err` means it could not be started at all
+    fn execute(&self, request: &CommandRequest) -> Result<CommandOutput>;
+}
+
+/// Spawns real processes through `std::process::Command`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessExecutor;
+
+impl CommandExecutor for ProcessExecutor {
+    fn execute(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        let (program, rest) = request.args.split_first()
+            .ok_or_else(|| PortableSourceError::command("Empty command"))?;
+        let mut cmd = Command::new(program);
+        cmd.args(rest);
+        if let Some(dir) = &request.cwd {
+            cmd.current_dir(dir);
+        }
+        cmd.envs(&request.env);
+
+        // Hide console window on Windows unless the output is shown to the user
+        #[cfg(windows)]
+        {
+            if request.mode != OutputMode::Inherited {
+                use std::os::windows::process::CommandExt;
+                cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+            }
+        }
+
+        match request.mode {
+            OutputMode::Captured => {
+                let output = cmd.output().map_err(|e| PortableSourceError::command(e.to_string()))?;
+                Ok(CommandOutput {
+                    exit_code: output.status.code(),
+                    stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                })
+            }
+            OutputMode::Silent | OutputMode::Inherited if !request.capture => {
+                let stdio = || if request.mode == OutputMode::Silent { Stdio::null() } else { Stdio::inherit() };
+                cmd.stdout(stdio()).stderr(stdio());
+                let status = cmd.status().map_err(|e| PortableSourceError::command(e.to_string()))?;
+                Ok(CommandOutput { exit_code: status.code(), ..Default::default() })
+            }
+            OutputMode::Silent | OutputMode::Inherited => {
+                cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+                let mut child = cmd.spawn().map_err(|e| PortableSourceError::command(e.to_string()))?;
+                let forward = request.mode == OutputMode::Inherited;
+                let child_stdout = child.stdout.take();
+                let child_stderr = child.stderr.take();
+                // Both pipes are drained at once, otherwise a full stderr pipe blocks the child
+                let (stdout, stderr) = std::thread::scope(|scope| {
+                    let stdout = scope.spawn(move || tee(child_stdout, forward.then(io::stdout)));
+                    let stderr = tee(child_stderr, forward.then(io::stderr));
+                    (stdout.join().unwrap_or_default(), stderr)
+                });
+                let status = child.wait().map_err(|e| PortableSourceError::command(e.to_string()))?;
+                Ok(CommandOutput { exit_code: status.code(), stdout, stderr })
+            }
+        }
+    }
+}
+
+/// Read a child pipe to the end, copying each chunk to `console` as it arrives
+fn tee(pipe: Option<impl Read>, mut console: Option<impl Write>) -> String {
+    let Some(mut pipe) = pipe else { return String::new() };
+    let mut captured = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                captured.extend_from_slice(&buf[..n]);
+                if let Some(console) = console.as_mut() {
+                    let _ = console.write_all(&buf[..n]).and_then(|_| console.flush());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    String::from_utf8_lossy(&captured).to_string()
+}
+
+/// One executed command in a transcript
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    #[serde(flatten)]
+    pub request: CommandRequest,
+    /// Set when the command finished (successfully or not)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<CommandOutput>,
+    /// Set when the command could not be started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+impl TranscriptEntry {
+    /// Command that finished with exit code 0 and no output
+    pub(crate) fn finished<S: Into<String>>(args: impl IntoIterator<Item = S>, cwd: Option<&Path>, mode: OutputMode) -> Self {
+        Self {
+            request: CommandRequest {
+                args: args.into_iter().map(Into::into).collect(),
+                cwd: cwd.map(Path::to_path_buf),
+                env: Default::default(),
+                mode,
+                capture: false,
+            },
+            output: Some(CommandOutput { exit_code: Some(0), ..Default::default() }),
+            error: None,
+        }
+    }
+
+    /// Captured `git <args>` run in `cwd`
+    pub(crate) fn git(args: &[&str], cwd: &Path) -> Self {
+        Self::finished(std::iter::once("git").chain(args.iter().copied()), Some(cwd), OutputMode::Captured)
+    }
+
+    pub(crate) fn with_exit_code(mut self, exit_code: i32) -> Self {
+        self.output.get_or_insert_with(Default::default).exit_code = Some(exit_code);
+        self
+    }
+
+    pub(crate) fn with_stdout(mut self, stdout: &str) -> Self {
+        self.output.get_or_insert_with(Default::default).stdout = stdout.into();
+        self
+    }
+
+    pub(crate) fn with_stderr(mut self, stderr: &str) -> Self {
+        self.output.get_or_insert_with(Default::default).stderr = stderr.into();
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    pub version: u32,
+    #[serde(default)]
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let transcript: Transcript = serde_json::from_str(&content)?;
+        if transcript.version > TRANSCRIPT_VERSION {
+            return Err(PortableSourceError::config(format!(
+                "Transcript {:?} has version {}, this build supports up to {}",
+                path, transcript.version, TRANSCRIPT_VERSION
+            )));
+        }
+        Ok(transcript)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Runs commands through another executor and saves each one to a transcript file
+pub struct RecordingExecutor<E: CommandExecutor> {
+    inner: E,
+    path: PathBuf,
+    transcript: Mutex<Transcript>,
+}
+
+impl<E: CommandExecutor> RecordingExecutor<E> {
+    /// Start a new transcript at `path` (an existing file is replaced)
+    pub fn new(inner: E, path: PathBuf) -> Self {
+        let transcript = Transcript { version: TRANSCRIPT_VERSION, entries: Vec::new() };
+        Self { inner, path, transcript: Mutex::new(transcript) }
+    }
+}
+
+impl<E: CommandExecutor> CommandExecutor for RecordingExecutor<E> {
+    fn execute(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        let result = self.inner.execute(&CommandRequest { capture: true, ..request.clone() });
+        let entry = TranscriptEntry {
+            request: request.clone(),
+            output: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| match e {
+                PortableSourceError::Command { message } => message.clone(),
+                other => other.to_string(),
+            }),
+        };
+
+        // Save after every command so the transcript survives a crash or Ctrl+C
+        let mut transcript = self.transcript.lock().unwrap_or_else(|e| e.into_inner());
+        transcript.entries.push(entry);
+        if let Err(e) = transcript.save(&self.path) {
+            warn!("Failed to write transcript {:?}: {}", self.path, e);
+        }
+        result
+    }
+}
+
+/// Answers commands from a recorded transcript, in order
+pub struct ReplayExecutor {
+    entries: Vec<TranscriptEntry>,
+    position: Mutex<usize>,
+    strict: bool,
+}
+
+impl ReplayExecutor {
+    /// Replay a transcript file. Paths usually differ between machines, so a
+    /// command that does not match the recorded one only produces a warning.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let transcript = Transcript::load(path)?;
+        Ok(Self { entries: transcript.entries, position: Mutex::new(0), strict: false })
+    }
+
+    /// Replay entries and fail on any command that differs from the recorded one
+    pub fn strict(entries: Vec<TranscriptEntry>) -> Self {
+        Self { entries, position: Mutex::new(0), strict: true }
+    }
+
+    /// Number of recorded commands that were not requested yet
+    pub fn remaining(&self) -> usize {
+        let position = *self.position.lock().unwrap_or_else(|e| e.into_inner());
+        self.entries.len().saturating_sub(position)
+    }
+}
+
+impl CommandExecutor for ReplayExecutor {
+    fn execute(&self, request: &CommandRequest) -> Result<CommandOutput> {
+        let mut position = self.position.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = self.entries.get(*position).ok_or_else(|| {
+            PortableSourceError::command(format!(
+                "Transcript exhausted after {} commands; unexpected command: {}",
+                self.entries.len(),
+                request.args.join(" ")
+            ))
+        })?;
+        *position += 1;
+
+        if entry.request.args != request.args || entry.request.cwd != request.cwd {
+            let message = format!(
+                "Command #{} differs from transcript:\n  recorded: {}\n  actual:   {}",
+                *position,
+                entry.request.args.join(" "),
+                request.args.join(" ")
+            );
+            if self.strict {
+                return Err(PortableSourceError::command(message));
+            }
+            warn!("{}", message);
+        }
+
+        match (&entry.output, &entry.error) {
+            (Some(output), _) => Ok(output.clone()),
+            (None, Some(error)) => Err(PortableSourceError::command(error.clone())),
+            (None, None) => Err(PortableSourceError::command(format!("Transcript entry #{} has no result", *position))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingExecutor;
+
+    impl CommandExecutor for FailingExecutor {
+        fn execute(&self, _request: &CommandRequest) -> Result<CommandOutput> {
+            Ok(CommandOutput { exit_code: Some(2), stdout: "out".into(), stderr: "boom".into() })
+        }
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transcript.json");
+        let request = CommandRequest {
+            args: vec!["git".into(), "status".into()],
+            cwd: Some(dir.path().to_path_buf()),
+            env: BTreeMap::from([("GIT_TERMINAL_PROMPT".to_string(), "0".to_string())]),
+            mode: OutputMode::Captured,
+            capture: false,
+        };
+
+        let recorder = RecordingExecutor::new(FailingExecutor, path.clone());
+        let recorded = recorder.execute(&request).unwrap();
+
+        let replayer = ReplayExecutor::from_file(&path).unwrap();
+        assert_eq!(replayer.remaining(), 1);
+        assert_eq!(replayer.execute(&request).unwrap(), recorded);
+        assert!(replayer.execute(&request).unwrap_err().to_string().contains("Transcript exhausted"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_silent_and_inherited_output_is_captured_only_for_transcripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transcript.json");
+        for mode in [OutputMode::Silent, OutputMode::Inherited] {
+            let request = CommandRequest {
+                args: vec!["sh".into(), "-c".into(), "echo out >/dev/null; echo err >/dev/null; exit 3".into()],
+                cwd: None,
+                env: BTreeMap::new(),
+                mode,
+                capture: false,
+            };
+            // Not piped: the output stays with the console
+            let output = ProcessExecutor.execute(&request).unwrap();
+            assert_eq!(output, CommandOutput { exit_code: Some(3), ..Default::default() });
+
+            let request = CommandRequest { args: vec!["sh".into(), "-c".into(), "echo out; echo err >&2; exit 3".into()], ..request };
+            let output = RecordingExecutor::new(ProcessExecutor, path.clone()).execute(&request).unwrap();
+            assert_eq!(output, CommandOutput { exit_code: Some(3), stdout: "out\n".into(), stderr: "err\n".into() });
+            assert_eq!(Transcript::load(&path).unwrap().entries[0].output, Some(output));
+        }
+    }
+}
@@ -20,15 +20,57 @@ use crate::installer::command_runer::CommandRunner;
 use crate::envs_manager::PortableEnvironmentManager;
 use crate::PortableSourceError;
 use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::path::Path;
 use log::{info, warn};
 
+/// Git reference a repository is pinned to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "ref", rename_all = "lowercase")]
+pub enum RepoPin {
+    Branch(String),
+    Tag(String),
+    Commit(String),
+}
+
+impl RepoPin {
+    pub fn reference(&self) -> &str {
+        match self {
+            RepoPin::Branch(r) | RepoPin::Tag(r) | RepoPin::Commit(r) => r,
+        }
+    }
+}
+
+impl fmt::Display for RepoPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoPin::Branch(r) => write!(f, "branch {}", r),
+            RepoPin::Tag(r) => write!(f, "tag {}", r),
+            RepoPin::Commit(r) => write!(f, "commit {}", r),
+        }
+    }
+}
+
+/// What update-repo does with uncommitted changes to tracked files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LocalChanges {
+    /// Stash before updating and re-apply afterwards
+    #[default]
+    Stash,
+    /// Abort the update
+    Refuse,
+    /// Throw the changes away (--force)
+    Discard,
+}
+
 /// Repository information struct for git operations
 pub struct RepositoryInfo {
     pub url: Option<String>,
     pub main_file: Option<String>,
     pub program_args: Option<String>,
+    pub pin: Option<RepoPin>,
 }
 
 pub struct GitManager<'a> {
@@ -47,47 +89,53 @@ impl<'a> GitManager<'a> {
     }
 
     /// Clone or update repository using RepositoryInfo struct (main interface)
-    pub async fn clone_or_update_repository(&self, repo_info: &RepositoryInfo, repo_path: &Path) -> Result<()> {
+    pub async fn clone_or_update_repository(&self, repo_info: &RepositoryInfo, repo_path: &Path, local_changes: LocalChanges) -> Result<()> {
         let repo_url = repo_info.url.as_ref().ok_or_else(|| PortableSourceError::repository("Missing repository URL"))?;
-        self.clone_or_update_repository_from_url(repo_url, repo_path).await
+        self.clone_or_update_repository_from_url(repo_url, repo_path, repo_info.pin.as_ref(), local_changes).await
     }
 
-    /// Clone or update repository from URL (helper method)
-    pub async fn clone_or_update_repository_from_url(&self, repo_url: &str, repo_path: &Path) -> Result<()> {
+    /// Clone or update repository from URL (helper method).
+    /// An existing checkout is updated like update-repo, with the pin (if any) and the
+    /// given policy for local changes.
+    pub async fn clone_or_update_repository_from_url(&self, repo_url: &str, repo_path: &Path, pin: Option<&RepoPin>, local_changes: LocalChanges) -> Result<()> {
         let git_exe = self.get_git_executable();
         if repo_path.exists() {
-            if repo_path.join(".git").exists() {
-                match self.update_repository_with_fixes(&git_exe, repo_path) {
-                    Ok(_) => return Ok(()),
-                    Err(e) => {
-                        // If repository was removed due to corruption (exit code 128), proceed to clone
-                        if e.to_string().contains("Repository corrupted (exit code 128)") {
-                            warn!("Repository was corrupted and removed, proceeding to clone fresh copy");
-                            // Continue to cloning logic below
-                        } else {
-                            return Err(e);
-                        }
-                    }
-                }
-            } else {
+            if !repo_path.join(".git").exists() {
                 return Err(PortableSourceError::repository(format!("Directory exists but is not a git repository: {:?}", repo_path)));
             }
+            // Only a checkout git itself does not recognise is thrown away; network
+            // or auth failures during the update are reported as they are
+            let probe = self.command_runner.output(&self.git(&git_exe, &["rev-parse", "--git-dir"]), Some(repo_path))?;
+            if probe.success() {
+                return self.update_repository(repo_path, pin, local_changes);
+            }
+            warn!("{:?} is not a valid git repository ({}), removing it to clone a fresh copy", repo_path, probe.stderr.trim());
+            println!("[PortableSource] Repository is corrupted, cloning it again");
+            self.command_runner.remove_dir_all(repo_path)?;
         }
-        
+
         // Clone repository (either first time or after corruption removal)
         info!("Cloning repository from URL: {}", repo_url);
         
         let parent = repo_path.parent().ok_or_else(|| PortableSourceError::repository("Invalid repo path"))?;
-        fs::create_dir_all(parent)?;
+        if !self.command_runner.is_dry_run() {
+            fs::create_dir_all(parent)?;
+        }
         let mut args = vec![git_exe.clone(), "clone".to_string()];
-        if let Some(branch) = None::<String> { 
+        // Branches and tags can be cloned directly; commits are checked out after cloning
+        if let Some(RepoPin::Branch(r) | RepoPin::Tag(r)) = pin {
             args.push("-b".to_string());
-            args.push(branch);
+            args.push(r.clone());
         }
         args.push(repo_url.to_string());
         args.push(repo_path.file_name().unwrap().to_string_lossy().to_string());
         
-        match self.command_runner.run(&args, Some("Cloning repository"), Some(parent)) {
+        let result = self.command_runner.run(&args, Some("Cloning repository"), Some(parent)).and_then(|_| match pin {
+            Some(pin @ RepoPin::Commit(_)) => self.checkout_pin(&git_exe, repo_path, pin, false),
+            _ => Ok(()),
+        });
+        match result {
+            Ok(_) if self.command_runner.is_dry_run() => Ok(()),
             Ok(_) => {
                 info!("Repository cloned successfully to: {:?}", repo_path);
                 println!("[PortableSource] Repository cloned successfully");
@@ -101,69 +149,297 @@ impl<'a> GitManager<'a> {
         }
     }
 
-    fn update_repository_with_fixes(&self, git_exe: &str, repo_path: &Path) -> Result<()> {
-        let max_attempts = 3;
-        for attempt in 0..max_attempts {
-            let args = vec![git_exe.to_string(), "pull".to_string()];
-            match self.command_runner.run(&args, Some("Updating repository"), Some(repo_path)) {
-                Ok(_) => return Ok(()),
-                Err(e) => {
-                    warn!("git pull failed (attempt {}/{}): {}", attempt + 1, max_attempts, e);
-                    
-                    // Check for exit code 128 (not a git repository)
-                    if e.to_string().contains("exit code: 128") {
-                        warn!("Exit code 128 detected - repository is corrupted. Removing and will re-clone.");
-                        if let Err(remove_err) = std::fs::remove_dir_all(repo_path) {
-                            warn!("Failed to remove corrupted repository: {}", remove_err);
-                        }
-                        return Err(PortableSourceError::repository("Repository corrupted (exit code 128) - removed for re-cloning"));
-                    }
-                    
-                    if attempt < max_attempts - 1 {
-                        if self.fix_git_issues(git_exe, repo_path).is_ok() { continue; }
-                    }
-                    if attempt == max_attempts - 1 { return Err(PortableSourceError::repository("Failed to update repository")); }
-                }
+    /// Update a repository to the latest commit of the remote default branch.
+    /// A pinned repository only follows its pin: a branch pin is fast-forwarded,
+    /// tag and commit pins stay where they are.
+    pub fn update_repository(&self, repo_path: &Path, pin: Option<&RepoPin>, local_changes: LocalChanges) -> Result<()> {
+        let git_exe = self.get_git_executable();
+        let old_head = self.head(&git_exe, repo_path);
+        self.fetch(&git_exe, repo_path);
+
+        let pin = match pin {
+            Some(pin) => pin.clone(),
+            None => {
+                let branch = self.default_branch(&git_exe, repo_path)?;
+                info!("Remote default branch: {}", branch);
+                RepoPin::Branch(branch)
+            }
+        };
+        self.with_local_changes(&git_exe, repo_path, local_changes, || {
+            self.checkout_pin(&git_exe, repo_path, &pin, local_changes == LocalChanges::Discard)
+        })?;
+
+        self.print_update_summary(&git_exe, repo_path, old_head);
+        Ok(())
+    }
+
+    /// Move a repository to another branch, tag or commit; returns the new pin
+    pub fn move_pin(&self, repo_path: &Path, reference: &str, local_changes: LocalChanges) -> Result<RepoPin> {
+        let git_exe = self.get_git_executable();
+        let old_head = self.head(&git_exe, repo_path);
+        self.fetch(&git_exe, repo_path);
+        let pin = self.resolve_reference(&git_exe, repo_path, reference)?;
+        self.with_local_changes(&git_exe, repo_path, local_changes, || {
+            self.checkout_pin(&git_exe, repo_path, &pin, local_changes == LocalChanges::Discard)
+        })?;
+
+        self.print_update_summary(&git_exe, repo_path, old_head);
+        Ok(pin)
+    }
+
+    fn git(&self, git_exe: &str, args: &[&str]) -> Vec<String> {
+        std::iter::once(git_exe).chain(args.iter().copied()).map(String::from).collect()
+    }
+
+    /// Trimmed stdout of a successful git command
+    fn git_output(&self, git_exe: &str, repo_path: &Path, args: &[&str]) -> Option<String> {
+        let output = self.command_runner.output(&self.git(git_exe, args), Some(repo_path)).ok()?;
+        let stdout = output.stdout.trim();
+        (output.success() && !stdout.is_empty()).then(|| stdout.to_string())
+    }
+
+    fn head(&self, git_exe: &str, repo_path: &Path) -> Option<String> {
+        self.git_output(git_exe, repo_path, &["rev-parse", "HEAD"])
+    }
+
+    /// Commit checked out in the repository
+    pub fn current_commit(&self, repo_path: &Path) -> Option<String> {
+        self.head(&self.get_git_executable(), repo_path)
+    }
+
+    /// Checked out branch (`None` for a detached HEAD)
+    pub fn current_branch(&self, repo_path: &Path) -> Option<String> {
+        self.git_output(&self.get_git_executable(), repo_path, &["symbolic-ref", "--quiet", "--short", "HEAD"])
+    }
+
+    /// Return to a previously recorded branch and commit. `reset --keep` refuses
+    /// instead of overwriting local modifications.
+    pub fn restore_checkout(&self, repo_path: &Path, branch: Option<&str>, commit: &str) -> Result<()> {
+        let git_exe = self.get_git_executable();
+        let label = format!("Restoring commit {}", commit.chars().take(8).collect::<String>());
+        match branch {
+            Some(branch) => {
+                self.command_runner.run(&self.git(&git_exe, &["checkout", branch]), None, Some(repo_path))?;
+                self.command_runner.run(&self.git(&git_exe, &["reset", "--keep", commit]), Some(&label), Some(repo_path))
             }
+            None => self.command_runner.run(&self.git(&git_exe, &["checkout", "--quiet", "--detach", commit]), Some(&label), Some(repo_path)),
         }
-        Err(PortableSourceError::repository("Failed to update repository"))
     }
 
-    fn fix_git_issues(&self, git_exe: &str, repo_path: &Path) -> Result<()> {
-        // Try a sequence of common fixes
-        let fixes: Vec<Vec<&str>> = vec![
-            vec!["fetch", "origin"],
-            vec!["reset", "--hard", "origin/main"],
-        ];
-        for fix_args in fixes {
-            let mut args = vec![git_exe.to_string()];
-            args.extend(fix_args.into_iter().map(|s| s.to_string()));
-            let _ = self.command_runner.run(&args, None, Some(repo_path));
+    /// URL of the `origin` remote
+    pub fn remote_url(&self, repo_path: &Path) -> Option<String> {
+        self.git_output(&self.get_git_executable(), repo_path, &["remote", "get-url", "origin"])
+    }
+
+    fn fetch(&self, git_exe: &str, repo_path: &Path) {
+        let args = self.git(git_exe, &["fetch", "--tags", "origin"]);
+        if let Err(e) = self.command_runner.run(&args, Some("Fetching from remote"), Some(repo_path)) {
+            warn!("Failed to fetch from remote: {}", e);
         }
-        Ok(())
     }
 
-    pub fn update_repository(&self, repo_path: &Path) -> Result<()> {
-        let git_exe = self.get_git_executable();
-        {
-            let args = vec![git_exe.clone(), "fetch".to_string(), "--all".to_string()];
-            if let Err(e) = self.command_runner.run(&args, Some("Fetching from remote"), Some(repo_path)) {
-                warn!("Failed to fetch from remote: {}", e);
+    /// Branch that `origin/HEAD` points to (main, master, dev, ...)
+    fn default_branch(&self, git_exe: &str, repo_path: &Path) -> Result<String> {
+        let symbolic_ref = || self.git_output(git_exe, repo_path, &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"]);
+        let remote_head = symbolic_ref().or_else(|| {
+            // origin/HEAD is not set for some clones; ask the remote
+            let _ = self.command_runner.output(&self.git(git_exe, &["remote", "set-head", "origin", "--auto"]), Some(repo_path));
+            symbolic_ref()
+        });
+        if let Some(branch) = remote_head.as_deref().and_then(|r| r.strip_prefix("origin/")) {
+            return Ok(branch.to_string());
+        }
+        if self.command_runner.is_dry_run() {
+            return Ok("<default branch>".to_string());
+        }
+        // Fall back to the branch that is checked out
+        match self.git_output(git_exe, repo_path, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+            Some(branch) if branch != "HEAD" => {
+                warn!("Could not detect the remote default branch, updating current branch '{}'", branch);
+                Ok(branch)
             }
+            _ => Err(PortableSourceError::repository(format!(
+                "Could not detect the default branch of {:?}; use update-repo --to <branch>", repo_path
+            ))),
         }
-        {
-            let args = vec![git_exe.clone(), "reset".to_string(), "--hard".to_string(), "origin/main".to_string()];
-            if self.command_runner.run(&args, Some("Reset to origin/main"), Some(repo_path)).is_err() {
-                let args = vec![git_exe.clone(), "reset".to_string(), "--hard".to_string(), "origin/master".to_string()];
-                let _ = self.command_runner.run(&args, Some("Reset to origin/master"), Some(repo_path));
+    }
+
+    /// Tracked files with local modifications (untracked files such as link.txt are ignored)
+    fn local_changes(&self, git_exe: &str, repo_path: &Path) -> Vec<String> {
+        self.git_output(git_exe, repo_path, &["status", "--porcelain", "--untracked-files=no"])
+            .map(|out| out.lines().map(|l| l.trim().to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Run `switch` with local modifications stashed, refused or discarded according to the policy
+    fn with_local_changes(&self, git_exe: &str, repo_path: &Path, policy: LocalChanges, switch: impl FnOnce() -> Result<()>) -> Result<()> {
+        let changes = self.local_changes(git_exe, repo_path);
+        if changes.is_empty() {
+            return switch();
+        }
+
+        match policy {
+            LocalChanges::Refuse => Err(PortableSourceError::repository(format!(
+                "Repository {:?} has local changes:\n  {}\nCommit or stash them, or run update-repo with --force to discard them",
+                repo_path, changes.join("\n  ")
+            ))),
+            LocalChanges::Discard => {
+                println!("[PortableSource] Discarding local changes in {} file(s)", changes.len());
+                self.command_runner.run(&self.git(git_exe, &["reset", "--hard", "HEAD"]), Some("Discarding local changes"), Some(repo_path))?;
+                switch()
+            }
+            LocalChanges::Stash => {
+                println!("[PortableSource] Stashing local changes in {} file(s)", changes.len());
+                let stash = self.git(git_exe, &["stash", "push", "-m", "portablesource: local changes before update"]);
+                self.command_runner.run(&stash, Some("Stashing local changes"), Some(repo_path))?;
+                let result = switch();
+                let pop = self.git(git_exe, &["stash", "pop"]);
+                match self.command_runner.run(&pop, Some("Restoring local changes"), Some(repo_path)) {
+                    Ok(_) => println!("[PortableSource] Local changes restored"),
+                    Err(e) => {
+                        warn!("Failed to restore stashed changes: {}", e);
+                        println!("[PortableSource] Local changes conflict with the update and were kept in 'git stash list' of {:?}", repo_path);
+                    }
+                }
+                result
             }
         }
-        {
-            let args = vec![git_exe.clone(), "pull".to_string()];
-            if let Err(e) = self.command_runner.run(&args, Some("Pulling latest changes"), Some(repo_path)) {
-                warn!("Failed to pull latest changes: {}", e);
+    }
+
+    /// Print the commit range and a short log between the old and the new HEAD
+    fn print_update_summary(&self, git_exe: &str, repo_path: &Path, old_head: Option<String>) {
+        let (Some(old), Some(new)) = (old_head, self.head(git_exe, repo_path)) else { return };
+        let short = |sha: &str| sha.chars().take(8).collect::<String>();
+        if old == new {
+            println!("[PortableSource] Already up to date ({})", short(&new));
+            return;
+        }
+
+        let range = format!("{}..{}", old, new);
+        println!("[PortableSource] Updated {}..{}", short(&old), short(&new));
+        let log = self.git_output(git_exe, repo_path, &["log", "--oneline", "--no-decorate", "-n", "20", &range]);
+        match log {
+            Some(log) => {
+                for line in log.lines() {
+                    println!("  {}", line);
+                }
             }
+            // The new HEAD is behind the old one (e.g. pinned to an older tag)
+            None => println!("  (no new commits; HEAD moved to an older or unrelated commit)"),
         }
-        Ok(())
+    }
+
+    /// Decide whether a reference names a remote branch, a tag or a commit
+    fn resolve_reference(&self, git_exe: &str, repo_path: &Path, reference: &str) -> Result<RepoPin> {
+        let exists = |rev: String| -> Result<bool> {
+            let args = self.git(git_exe, &["rev-parse", "--verify", "--quiet", &rev]);
+            Ok(self.command_runner.output(&args, Some(repo_path))?.success())
+        };
+        if exists(format!("refs/remotes/origin/{}", reference))? {
+            Ok(RepoPin::Branch(reference.to_string()))
+        } else if exists(format!("refs/tags/{}", reference))? {
+            Ok(RepoPin::Tag(reference.to_string()))
+        } else if exists(format!("{}^{{commit}}", reference))? {
+            Ok(RepoPin::Commit(reference.to_string()))
+        } else {
+            Err(PortableSourceError::repository(format!("'{}' is not a branch, tag or commit of {:?}", reference, repo_path)))
+        }
+    }
+
+    /// Check out a pinned reference. A branch is fast-forwarded to its upstream;
+    /// if it has diverged, it is reset only when `force` is set.
+    fn checkout_pin(&self, git_exe: &str, repo_path: &Path, pin: &RepoPin, force: bool) -> Result<()> {
+        let label = format!("Checking out {}", pin);
+        match pin {
+            RepoPin::Branch(r) => {
+                self.command_runner.run(&self.git(git_exe, &["checkout", r]), Some(&label), Some(repo_path))?;
+                let upstream = format!("origin/{}", r);
+                let merged = self.command_runner.run(&self.git(git_exe, &["merge", "--ff-only", &upstream]), Some("Fast-forwarding branch"), Some(repo_path));
+                match merged {
+                    Err(_) if force => self.command_runner.run(&self.git(git_exe, &["reset", "--hard", &upstream]), Some("Resetting to upstream"), Some(repo_path)),
+                    Err(e) => Err(PortableSourceError::repository(format!(
+                        "Branch '{}' has local commits that are not on {} ({}); use --force to reset it", r, upstream, e
+                    ))),
+                    Ok(_) => Ok(()),
+                }
+            }
+            RepoPin::Tag(r) => {
+                let tag = format!("refs/tags/{}", r);
+                self.command_runner.run(&self.git(git_exe, &["checkout", "--quiet", &tag]), Some(&label), Some(repo_path))
+            }
+            RepoPin::Commit(r) => self.command_runner.run(&self.git(git_exe, &["checkout", "--quiet", r]), Some(&label), Some(repo_path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigManager;
+    use crate::installer::executor::{ReplayExecutor, TranscriptEntry};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_corrupted_repository_is_recloned() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ConfigManager::new(Some(dir.path().join("config.json"))).unwrap();
+        let env_manager = PortableEnvironmentManager::with_config(dir.path().to_path_buf(), config);
+        let repos = dir.path().join("repos");
+        let repo_path = repos.join("demo");
+        fs::create_dir_all(repo_path.join(".git")).unwrap();
+
+        let url = "https://github.com/example/demo.git";
+        let executor = Arc::new(ReplayExecutor::strict(vec![
+            TranscriptEntry::git(&["rev-parse", "--git-dir"], &repo_path).with_exit_code(128).with_stderr("fatal: not a git repository"),
+            TranscriptEntry::git(&["clone", url, "demo"], &repos),
+        ]));
+        let runner = CommandRunner::with_executor(&env_manager, executor.clone());
+        let git_manager = GitManager::new(&runner, &env_manager);
+
+        git_manager.clone_or_update_repository_from_url(url, &repo_path, None, LocalChanges::Refuse).await.unwrap();
+        assert_eq!(executor.remaining(), 0);
+        assert!(!repo_path.exists(), "corrupted checkout should be removed before cloning");
+    }
+
+    #[tokio::test]
+    async fn test_commit_pin_is_checked_out_after_clone() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ConfigManager::new(Some(dir.path().join("config.json"))).unwrap();
+        let env_manager = PortableEnvironmentManager::with_config(dir.path().to_path_buf(), config);
+        let repos = dir.path().join("repos");
+        let repo_path = repos.join("demo");
+
+        let url = "https://github.com/example/demo.git";
+        let pin = RepoPin::Commit("1a2b3c4".into());
+        let executor = Arc::new(ReplayExecutor::strict(vec![
+            TranscriptEntry::git(&["clone", url, "demo"], &repos),
+            TranscriptEntry::git(&["checkout", "--quiet", "1a2b3c4"], &repo_path),
+        ]));
+        let runner = CommandRunner::with_executor(&env_manager, executor.clone());
+        let git_manager = GitManager::new(&runner, &env_manager);
+
+        git_manager.clone_or_update_repository_from_url(url, &repo_path, Some(&pin), LocalChanges::Refuse).await.unwrap();
+        assert_eq!(executor.remaining(), 0);
+    }
+
+    #[test]
+    fn test_update_detects_default_branch_and_refuses_local_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ConfigManager::new(Some(dir.path().join("config.json"))).unwrap();
+        let env_manager = PortableEnvironmentManager::with_config(dir.path().to_path_buf(), config);
+        let repo_path = dir.path().join("repos").join("demo");
+
+        let executor = Arc::new(ReplayExecutor::strict(vec![
+            TranscriptEntry::git(&["rev-parse", "HEAD"], &repo_path).with_stdout("386aa945\n"),
+            TranscriptEntry::git(&["fetch", "--tags", "origin"], &repo_path),
+            TranscriptEntry::git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"], &repo_path).with_stdout("origin/develop\n"),
+            TranscriptEntry::git(&["status", "--porcelain", "--untracked-files=no"], &repo_path).with_stdout(" M main.py\n"),
+        ]));
+        let runner = CommandRunner::with_executor(&env_manager, executor.clone());
+        let git_manager = GitManager::new(&runner, &env_manager);
+
+        let err = git_manager.update_repository(&repo_path, None, LocalChanges::Refuse).unwrap_err();
+        assert!(err.to_string().contains("M main.py"));
+        assert_eq!(executor.remaining(), 0);
     }
 }
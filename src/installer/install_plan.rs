@@ -0,0 +1,192 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Typed schema for installation plans served by the PortableSource server.
+
+use crate::{Result, PortableSourceError};
+use crate::installer::requirements::RequirementLine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::path::{Component, Path};
+
+/// Step types understood by this version of PortableSource
+pub const KNOWN_STEP_TYPES: &[&str] = &["requirements", "pip_install", "regular", "regular_only"];
+
+/// Validated installation plan
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ServerInstallPlan {
+    #[serde(default)]
+    pub steps: Vec<PlanStep>,
+}
+
+/// Single plan step, tagged by its `type` field
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlanStep {
+    /// Install a requirements file from the repository
+    Requirements { path: String },
+    /// Install a list of packages (torch/onnx/etc. are routed to their own indexes)
+    PipInstall(PackagesStep),
+    Regular(PackagesStep),
+    RegularOnly(PackagesStep),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PackagesStep {
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torch_index_url: Option<String>,
+}
+
+impl PlanStep {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PlanStep::Requirements { .. } => "requirements",
+            PlanStep::PipInstall(_) => "pip_install",
+            PlanStep::Regular(_) => "regular",
+            PlanStep::RegularOnly(_) => "regular_only",
+        }
+    }
+
+    /// Package list for package steps, `None` for requirements steps
+    pub fn packages(&self) -> Option<&PackagesStep> {
+        match self {
+            PlanStep::Requirements { .. } => None,
+            PlanStep::PipInstall(p) | PlanStep::Regular(p) | PlanStep::RegularOnly(p) => Some(p),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        match self {
+            PlanStep::Requirements { path } => {
+                if path.trim().is_empty() {
+                    return Err("requirements path is empty".into());
+                }
+                let p = Path::new(path);
+                if p.is_absolute() || p.components().any(|c| matches!(c, Component::ParentDir | Component::Prefix(_))) {
+                    return Err(format!("requirements path '{}' must stay inside the repository", path));
+                }
+            }
+            PlanStep::PipInstall(step) | PlanStep::Regular(step) | PlanStep::RegularOnly(step) => {
+                if step.packages.is_empty() {
+                    return Err("package list is empty".into());
+                }
+                if let Some(i) = step.packages.iter().position(|p| p.trim().is_empty()) {
+                    return Err(format!("package #{} is empty", i + 1));
+                }
+                for (i, package) in step.packages.iter().enumerate() {
+                    RequirementLine::parse(package).map_err(|e| format!("package #{}: {}", i + 1, e))?;
+                }
+                if let Some(url) = &step.torch_index_url {
+                    url::Url::parse(url).map_err(|e| format!("invalid torch_index_url '{}': {}", url, e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ServerInstallPlan {
+    /// Parse and validate the raw `installation_plan` object returned by the server
+    pub fn from_json(value: &JsonValue) -> Result<Self> {
+        let raw_steps = match value.get("steps") {
+            None | Some(JsonValue::Null) => return Ok(Self::default()),
+            Some(JsonValue::Array(steps)) => steps,
+            Some(_) => return Err(PortableSourceError::plan("'steps' must be an array")),
+        };
+
+        let mut steps = Vec::with_capacity(raw_steps.len());
+        for (i, raw) in raw_steps.iter().enumerate() {
+            let step_no = i + 1;
+            let step_type = raw.get("type").and_then(|t| t.as_str()).ok_or_else(|| {
+                PortableSourceError::plan(format!("step #{} has no 'type' field", step_no))
+            })?;
+            if !KNOWN_STEP_TYPES.contains(&step_type) {
+                return Err(PortableSourceError::plan(format!(
+                    "step #{} has unknown type '{}' (supported: {}). Update PortableSource to install this repository",
+                    step_no, step_type, KNOWN_STEP_TYPES.join(", ")
+                )));
+            }
+            let step: PlanStep = serde_json::from_value(raw.clone()).map_err(|e| {
+                PortableSourceError::plan(format!("step #{} ({}) is malformed: {}", step_no, step_type, e))
+            })?;
+            step.validate().map_err(|e| {
+                PortableSourceError::plan(format!("step #{} ({}): {}", step_no, step_type, e))
+            })?;
+            steps.push(step);
+        }
+
+        Ok(Self { steps })
+    }
+}
+
+impl fmt::Display for ServerInstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            return writeln!(f, "(no steps)");
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            match step {
+                PlanStep::Requirements { path } => writeln!(f, "{}. requirements: {}", i + 1, path)?,
+                PlanStep::PipInstall(p) | PlanStep::Regular(p) | PlanStep::RegularOnly(p) => {
+                    writeln!(f, "{}. {} ({} packages)", i + 1, step.type_name(), p.packages.len())?;
+                    if let Some(url) = &p.torch_index_url {
+                        writeln!(f, "     torch index: {}", url)?;
+                    }
+                    for pkg in &p.packages {
+                        writeln!(f, "     - {}", pkg)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_valid_plan() {
+        let plan = ServerInstallPlan::from_json(&json!({
+            "steps": [
+                {"type": "requirements", "path": "requirements.txt"},
+                {"type": "pip_install", "packages": ["torch==2.3.1", "numpy"], "torch_index_url": "https://download.pytorch.org/whl/cu121"}
+            ]
+        })).unwrap();
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[1].packages().unwrap().packages.len(), 2);
+    }
+
+    #[test]
+    fn test_reject_invalid_steps() {
+        let unknown = ServerInstallPlan::from_json(&json!({"steps": [{"type": "conda"}]}));
+        assert!(unknown.unwrap_err().to_string().contains("unknown type 'conda'"));
+
+        let missing_path = ServerInstallPlan::from_json(&json!({"steps": [{"type": "requirements"}]}));
+        assert!(missing_path.unwrap_err().to_string().contains("step #1 (requirements) is malformed"));
+
+        let escaping = ServerInstallPlan::from_json(&json!({"steps": [{"type": "requirements", "path": "../x.txt"}]}));
+        assert!(escaping.is_err());
+
+        let empty = ServerInstallPlan::from_json(&json!({"steps": [{"type": "regular", "packages": []}]}));
+        assert!(empty.is_err());
+    }
+}
@@ -0,0 +1,152 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Lockfile with the exact package set of a repository environment.
+//!
+//! Every successful install/update writes `.portablesource.lock.json` next to the
+//! manifest. `lock export` writes `portablesource.lock.json`, which can be committed
+//! to the repository; `--locked` prefers that shared file over the local one.
+
+use crate::{Result, PortableSourceError};
+use crate::config::PythonVersion;
+use crate::installer::command_runer::CommandRunner;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lockfile written after every install/update
+pub const LOCK_FILE: &str = ".portablesource.lock.json";
+/// Lockfile written by `lock export`, meant to be committed and shared
+pub const SHARED_LOCK_FILE: &str = "portablesource.lock.json";
+pub const LOCK_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RepoLock {
+    pub version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Full interpreter version, e.g. "3.11.9"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torch_index_url: Option<String>,
+    /// Extra indexes added by override rules (see overrides.rs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_index_urls: Vec<String>,
+    /// The repository itself was installed as a package (pyproject.toml)
+    #[serde(default)]
+    pub install_repository: bool,
+    /// Pinned requirements in `pip freeze` format
+    pub packages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<String>,
+}
+
+impl RepoLock {
+    /// Build a lock from `pip freeze --all` / `uv pip freeze` output. Packages installed from
+    /// local paths (`-e <dir>`, `<name> @ file:...`) cannot be reproduced elsewhere and are
+    /// skipped, VCS editables (`-e git+https://...`) are kept; the repository itself
+    /// (`<name> @ file:///.../repos/<name>`) is recorded as `install_repository` instead.
+    pub fn from_freeze(name: impl Into<String>, freeze_output: &str) -> Self {
+        let name = name.into();
+        let repo_suffix = format!("/{}", name.to_lowercase());
+        let install_repository = freeze_output.lines().any(|line| {
+            let line = line.trim().trim_end_matches('/').to_lowercase();
+            line.contains(" @ file:") && line.ends_with(&repo_suffix)
+        });
+        let packages = freeze_output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter(|line| match line.strip_prefix("-e ") {
+                Some(editable) => is_vcs_url(editable.trim()),
+                None => !line.contains(" @ file:"),
+            })
+            .map(String::from)
+            .collect();
+        Self {
+            version: LOCK_VERSION,
+            name,
+            commit: None,
+            python_version: None,
+            torch_index_url: None,
+            extra_index_urls: Vec::new(),
+            install_repository,
+            packages,
+            generated_at: None,
+        }
+    }
+
+    /// Lockfile used by `--locked`: the shared one if committed, otherwise the local one
+    pub fn find(repo_path: &Path) -> Option<PathBuf> {
+        [SHARED_LOCK_FILE, LOCK_FILE]
+            .iter()
+            .map(|file| repo_path.join(file))
+            .find(|path| path.exists())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| PortableSourceError::config(format!("Failed to read lockfile {:?}: {}", path, e)))?;
+        let lock: RepoLock = serde_json::from_str(&content)
+            .map_err(|e| PortableSourceError::config(format!("Invalid lockfile {:?}: {}", path, e)))?;
+        if lock.version > LOCK_VERSION {
+            return Err(PortableSourceError::config(format!(
+                "Lockfile {:?} has version {}, this build supports up to {}", path, lock.version, LOCK_VERSION
+            )));
+        }
+        Ok(lock)
+    }
+
+    pub fn save(&self, command_runner: &CommandRunner, path: &Path) -> Result<()> {
+        command_runner.write_file(path, &serde_json::to_string_pretty(self)?)
+    }
+
+    /// Packages as a requirements file
+    pub fn requirements(&self) -> String {
+        let mut content = self.packages.join("\n");
+        content.push('\n');
+        content
+    }
+
+    /// Portable Python matching the locked interpreter (3.10.x -> 310)
+    pub fn portable_python(&self) -> Option<PythonVersion> {
+        PythonVersion::from_interpreter_version(self.python_version.as_deref()?)
+    }
+}
+
+/// `git+https://...`, `hg+...`, `svn+...`, `bzr+...`
+fn is_vcs_url(url: &str) -> bool {
+    ["git+", "hg+", "svn+", "bzr+"].iter().any(|scheme| url.starts_with(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_from_freeze() {
+        let freeze = "numpy==1.26.4\n# comment\ntorch==2.7.0+cu128\n-e git+https://example.com/x@1a2b3c4#egg=x\n-e /opt/ps/repos/demo/libs/y\ndemo @ file:///opt/ps/repos/demo\n\n";
+        let mut lock = RepoLock::from_freeze("demo", freeze);
+        assert_eq!(lock.packages, vec!["numpy==1.26.4", "torch==2.7.0+cu128", "-e git+https://example.com/x@1a2b3c4#egg=x"]);
+        assert_eq!(lock.requirements(), "numpy==1.26.4\ntorch==2.7.0+cu128\n-e git+https://example.com/x@1a2b3c4#egg=x\n");
+        assert!(lock.install_repository);
+
+        lock.python_version = Some("3.11.9".into());
+        assert_eq!(lock.portable_python(), Some(PythonVersion::Python311));
+    }
+}
@@ -0,0 +1,292 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Per-repository metadata manifest (`.portablesource.json` in the repository folder).
+
+use crate::Result;
+use crate::config::PythonVersion;
+use crate::installer::command_runer::CommandRunner;
+use crate::installer::git_manager::RepoPin;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Manifest file name inside the repository folder
+pub const MANIFEST_FILE: &str = ".portablesource.json";
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Files written by older versions; migrated into the manifest and removed
+const LEGACY_LINK_FILE: &str = "link.txt";
+const LEGACY_URL_MARKER: &str = ".portablesource_url";
+
+/// Where the repository was installed from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoSource {
+    /// Resolved by name on the PortableSource server
+    Server,
+    /// Installed from a git URL
+    Url,
+    /// Resolved by name from a local, shared or built-in catalog
+    Catalog,
+}
+
+impl fmt::Display for RepoSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoSource::Server => write!(f, "server"),
+            RepoSource::Url => write!(f, "url"),
+            RepoSource::Catalog => write!(f, "catalog"),
+        }
+    }
+}
+
+/// What the dependencies were installed from
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum PlanSource {
+    /// Installation plan from the PortableSource server
+    Server,
+    /// Dependencies extracted from pyproject.toml
+    Pyproject,
+    /// Dependencies extracted from setup.cfg
+    SetupCfg,
+    /// A requirements file (path relative to the repository)
+    Requirements(String),
+    /// A lockfile (`--locked`)
+    Lockfile(String),
+    /// Nothing to install
+    Empty,
+}
+
+impl fmt::Display for PlanSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanSource::Server => write!(f, "server installation plan"),
+            PlanSource::Pyproject => write!(f, "pyproject.toml"),
+            PlanSource::SetupCfg => write!(f, "setup.cfg"),
+            PlanSource::Requirements(path) => write!(f, "{}", path),
+            PlanSource::Lockfile(path) => write!(f, "lockfile {}", path),
+            PlanSource::Empty => write!(f, "no dependencies"),
+        }
+    }
+}
+
+/// Torch/ONNX builds chosen for the hardware during installation
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstalledVariants {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torch_index_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub onnx_package: Option<String>,
+    /// Extra indexes added by override rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_index_urls: Vec<String>,
+}
+
+impl InstalledVariants {
+    /// Short torch build name taken from the index URL (cu128, cpu, rocm6.2, ...)
+    pub fn torch_variant(&self) -> Option<&str> {
+        self.torch_index_url.as_deref()
+            .map(|url| url.trim_end_matches('/'))
+            .and_then(|url| url.rsplit('/').next())
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Keep previously recorded values for anything not installed this time
+    pub fn merge_from(&mut self, older: &InstalledVariants) {
+        if self.torch_index_url.is_none() { self.torch_index_url = older.torch_index_url.clone(); }
+        if self.onnx_package.is_none() { self.onnx_package = older.onnx_package.clone(); }
+        if self.extra_index_urls.is_empty() { self.extra_index_urls = older.extra_index_urls.clone(); }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RepoManifest {
+    pub version: u32,
+    pub name: String,
+    pub source: RepoSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<RepoPin>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_version: Option<String>,
+    /// PyTorch backend given with `--torch-backend` (absent in auto mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torch_backend: Option<String>,
+    #[serde(flatten)]
+    pub variants: InstalledVariants,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_args: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan_source: Option<PlanSource>,
+    /// Requirements files given with `--requirements`, reused by update-repo
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<String>,
+    /// Extras and dependency groups given with `--extras`, reused by update-repo
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extras: Vec<String>,
+    /// RFC 3339 UTC timestamps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+impl RepoManifest {
+    pub fn new(name: impl Into<String>, source: RepoSource, url: Option<String>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            name: name.into(),
+            source,
+            url,
+            commit: None,
+            pin: None,
+            python_version: None,
+            torch_backend: None,
+            variants: InstalledVariants::default(),
+            main_file: None,
+            program_args: None,
+            plan_source: None,
+            requirements: Vec::new(),
+            extras: Vec::new(),
+            installed_at: None,
+            updated_at: None,
+        }
+    }
+
+    /// Portable Python of the interpreter the repository was installed with, reused by update-repo
+    pub fn portable_python(&self) -> Option<PythonVersion> {
+        PythonVersion::from_interpreter_version(self.python_version.as_deref()?)
+    }
+
+    /// Read the manifest of an installed repository. Repositories installed by
+    /// older versions get a manifest built from link.txt / .portablesource_url.
+    pub fn load(repo_path: &Path) -> Option<Self> {
+        let path = repo_path.join(MANIFEST_FILE);
+        if let Ok(content) = fs::read_to_string(&path) {
+            match serde_json::from_str(&content) {
+                Ok(manifest) => return Some(manifest),
+                Err(e) => warn!("Ignoring invalid manifest {:?}: {}", path, e),
+            }
+        }
+        Self::from_legacy_files(repo_path)
+    }
+
+    fn from_legacy_files(repo_path: &Path) -> Option<Self> {
+        let name = repo_path.file_name()?.to_string_lossy().to_string();
+        let link = fs::read_to_string(repo_path.join(LEGACY_LINK_FILE)).ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        let marker_url = fs::read_to_string(repo_path.join(LEGACY_URL_MARKER)).ok()
+            .and_then(|s| s.split_once('=').map(|(_, url)| url.trim().to_string()));
+
+        // Repositories without link.txt were installed by name from the server
+        match link.or(marker_url) {
+            Some(url) => Some(Self::new(name, RepoSource::Url, Some(url))),
+            None if repo_path.join(".git").exists() => Some(Self::new(name, RepoSource::Server, None)),
+            None => None,
+        }
+    }
+
+    /// Write the manifest and remove the legacy files it replaces
+    pub fn save(&self, command_runner: &CommandRunner, repo_path: &Path) -> Result<()> {
+        command_runner.write_file(&repo_path.join(MANIFEST_FILE), &serde_json::to_string_pretty(self)?)?;
+        if !command_runner.is_dry_run() {
+            for legacy in [LEGACY_LINK_FILE, LEGACY_URL_MARKER] {
+                let _ = fs::remove_file(repo_path.join(legacy));
+            }
+        }
+        Ok(())
+    }
+
+    /// Label used by list-repos
+    pub fn source_label(&self) -> &'static str {
+        match self.source {
+            RepoSource::Server => "From server",
+            RepoSource::Catalog => "From catalog",
+            RepoSource::Url => match &self.url {
+                Some(url) if url.to_lowercase().contains("://github.com/") || url.to_lowercase().starts_with("git@github.com:") => "From github",
+                _ => "From git",
+            },
+        }
+    }
+}
+
+/// Current time as an RFC 3339 UTC timestamp
+pub fn now_rfc3339() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_legacy_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("demo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        assert_eq!(RepoManifest::load(&repo).unwrap().source, RepoSource::Server);
+
+        fs::write(repo.join(LEGACY_LINK_FILE), "https://github.com/example/demo\n").unwrap();
+        let manifest = RepoManifest::load(&repo).unwrap();
+        assert_eq!(manifest.url.as_deref(), Some("https://github.com/example/demo"));
+        assert_eq!(manifest.source_label(), "From github");
+
+        assert_eq!(manifest.portable_python(), None);
+        let pinned = RepoManifest { pin: Some(RepoPin::Tag("v1".into())), python_version: Some("3.10.11".into()), ..manifest };
+        assert_eq!(pinned.portable_python(), Some(PythonVersion::Python310));
+        let json = serde_json::to_string(&pinned).unwrap();
+        assert!(json.contains(r#""pin":{"kind":"tag","ref":"v1"}"#));
+        assert_eq!(serde_json::from_str::<RepoManifest>(&json).unwrap(), pinned);
+    }
+
+    #[test]
+    fn test_torch_variant() {
+        let variants = InstalledVariants {
+            torch_index_url: Some("https://download.pytorch.org/whl/cu128/".into()),
+            ..Default::default()
+        };
+        assert_eq!(variants.torch_variant(), Some("cu128"));
+        assert_eq!(now_rfc3339().len(), "2026-01-01T00:00:00Z".len());
+    }
+}
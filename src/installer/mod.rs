@@ -15,18 +15,38 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod command_runer;
+pub mod executor;
 pub mod git_manager;
 pub mod pip_manager;
 pub mod dependency_installer;
 pub mod script_generator;
 pub mod server_client;
+pub mod install_plan;
+pub mod requirements;
+pub mod pyproject;
+pub mod overrides;
+pub mod conflicts;
+pub mod manifest;
+pub mod lockfile;
+pub mod rollback;
+pub mod verification;
 pub mod main_file_finder;
 pub mod templates; // <-- Добавлено
 
-pub use command_runer::CommandRunner;
-pub use git_manager::{GitManager, RepositoryInfo};
-pub use pip_manager::PipManager;
+pub use command_runer::{CommandRunner, PlannedAction};
+pub use executor::{CommandExecutor, CommandOutput, CommandRequest, OutputMode, ProcessExecutor, RecordingExecutor, ReplayExecutor, Transcript, TranscriptEntry};
+pub use git_manager::{GitManager, LocalChanges, RepoPin, RepositoryInfo};
+pub use pip_manager::{PipManager, Wheelhouse};
 pub use dependency_installer::DependencyInstaller;
 pub use script_generator::{ScriptGenerator, RepositoryInfo as ScriptRepositoryInfo};
-pub use server_client::{ServerClient, RepositoryInfo as ServerRepositoryInfo};
-pub use main_file_finder::MainFileFinder;
\ No newline at end of file
+pub use server_client::{ServerClient, RepositoryInfo as ServerRepositoryInfo, RepositorySummary};
+pub use main_file_finder::MainFileFinder;
+pub use install_plan::{ServerInstallPlan, PlanStep, PackagesStep};
+pub use requirements::{Requirement, RequirementLine, RequirementOption, SpecifierSet};
+pub use conflicts::{DependencyCheck, DependencyConflict};
+pub use overrides::{AppliedOverride, OverrideRule, OverrideRules, OverrideTarget};
+pub use pyproject::{ProjectDependencies, ProjectFile};
+pub use manifest::{InstalledVariants, PlanSource, RepoManifest, RepoSource};
+pub use lockfile::RepoLock;
+pub use rollback::UpdateSnapshot;
+pub use verification::{Verification, VerificationProblem};
\ No newline at end of file
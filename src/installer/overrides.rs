@@ -0,0 +1,353 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Package override rules.
+//!
+//! Rules substitute packages, pin versions, add companion packages or extra index URLs,
+//! optionally only on some platforms or GPU vendors. They are read from the built-in set,
+//! then `<install_path>/overrides.toml`, then `<install_path>/overrides/<repo>.toml`;
+//! when several rules match a package, the last one wins.
+//!
+//! ```toml
+//! [[rule]]
+//! package = "tensorflow"
+//! pinned = false                      # only when no version/URL is requested
+//! platform = ["linux", "macos"]       # windows, linux, macos
+//! gpu = ["amd", "intel", "cpu"]       # nvidia, amd, intel, cpu
+//! replace = "tensorflow-cpu==2.15.0"  # or: version = ">=2.15,<2.16"
+//! with = ["numpy<2"]                  # installed together with the package
+//! extra_index_url = "https://example.com/simple"
+//! reason = "2.16 breaks our models"
+//! ```
+
+use crate::{Result, PortableSourceError};
+use crate::installer::requirements::{canonicalize_name, HardwareProfile, Requirement, SpecifierSet};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Global rules file in the installation folder
+pub const OVERRIDES_FILE: &str = "overrides.toml";
+/// Folder with per-repository rules files (`<repo>.toml`)
+pub const REPO_OVERRIDES_DIR: &str = "overrides";
+
+const PLATFORMS: &[&str] = &["windows", "linux", "macos"];
+const GPU_VENDORS: &[&str] = &["nvidia", "amd", "intel", "cpu"];
+
+/// Rules that replaced the special cases previously hardcoded in `PipManager`
+const BUILTIN_RULES: &str = r#"
+[[rule]]
+package = "tensorflow"
+pinned = false
+replace = "tensorflow==2.15.0"
+reason = "works with typing-extensions>=4.7.1"
+
+[[rule]]
+package = "tensorflow"
+pinned = false
+platform = ["linux", "macos"]
+gpu = ["amd", "intel", "cpu"]
+replace = "tensorflow-cpu==2.15.0"
+reason = "no CUDA on this machine"
+
+[[rule]]
+package = "typing-extensions"
+pinned = true
+version = ">=4.7.1"
+reason = "onnx>=1.18 needs typing-extensions>=4.7.1"
+
+[[rule]]
+package = "insightface"
+with = ["numpy==1.26.4"]
+reason = "insightface 0.7.3 is built against numpy 1.x"
+
+[[rule]]
+package = "insightface"
+platform = "windows"
+replace = "insightface @ https://huggingface.co/hanamizuki-ai/pypi-wheels/resolve/main/insightface/insightface-0.7.3-cp311-cp311-win_amd64.whl"
+with = ["numpy==1.26.4"]
+reason = "prebuilt wheel, no compiler needed"
+"#;
+
+/// Rule as written in a rules file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    package: String,
+    #[serde(default, deserialize_with = "one_or_many")]
+    platform: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    gpu: Vec<String>,
+    pinned: Option<bool>,
+    replace: Option<String>,
+    version: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    with: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    extra_index_url: Vec<String>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RawRule>,
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverrideRule {
+    /// Canonical name of the package the rule applies to
+    pub package: String,
+    pub platforms: Vec<String>,
+    pub gpus: Vec<String>,
+    /// Only when the requirement has (true) or lacks (false) a version or URL
+    pub pinned: Option<bool>,
+    pub replace: Option<Requirement>,
+    pub version: Option<SpecifierSet>,
+    pub with: Vec<Requirement>,
+    pub extra_index_urls: Vec<String>,
+    pub reason: Option<String>,
+    /// File the rule comes from, for messages
+    pub origin: String,
+}
+
+/// Where the packages are installed, for rule conditions
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverrideTarget {
+    /// `windows`, `linux` or `macos`
+    pub platform: String,
+    pub hardware: HardwareProfile,
+}
+
+impl OverrideTarget {
+    pub fn current(hardware: HardwareProfile) -> Self {
+        Self { platform: std::env::consts::OS.to_string(), hardware }
+    }
+}
+
+/// Result of applying a rule to a requirement
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedOverride {
+    pub original: Requirement,
+    pub requirement: Requirement,
+    pub with: Vec<Requirement>,
+    pub extra_index_urls: Vec<String>,
+    pub reason: Option<String>,
+    pub origin: String,
+}
+
+impl fmt::Display for AppliedOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.original, self.requirement)?;
+        for companion in &self.with {
+            write!(f, " + {}", companion)?;
+        }
+        for url in &self.extra_index_urls {
+            write!(f, " (index {})", url)?;
+        }
+        match &self.reason {
+            Some(reason) => write!(f, " [{}: {}]", self.origin, reason),
+            None => write!(f, " [{}]", self.origin),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverrideRules {
+    /// In precedence order: later rules win
+    pub rules: Vec<OverrideRule>,
+}
+
+impl OverrideRules {
+    pub fn builtin() -> Self {
+        Self { rules: Self::parse(BUILTIN_RULES, "built-in").expect("built-in override rules are valid") }
+    }
+
+    /// Built-in rules, then the global rules file, then the rules file of the repository
+    pub fn load(install_path: &Path, repo_name: &str) -> Result<Self> {
+        let mut rules = Self::builtin();
+        let files = [
+            install_path.join(OVERRIDES_FILE),
+            install_path.join(REPO_OVERRIDES_DIR).join(format!("{}.toml", repo_name.to_lowercase())),
+        ];
+        for file in files.iter().filter(|file| file.exists()) {
+            let content = fs::read_to_string(file)
+                .map_err(|e| PortableSourceError::config(format!("Failed to read override rules {:?}: {}", file, e)))?;
+            rules.rules.extend(Self::parse(&content, &file.to_string_lossy())?);
+        }
+        Ok(rules)
+    }
+
+    /// Parse and validate a rules file
+    pub fn parse(content: &str, origin: &str) -> Result<Vec<OverrideRule>> {
+        let file: RulesFile = toml::from_str(content)
+            .map_err(|e| PortableSourceError::config(format!("Invalid override rules in {}: {}", origin, e)))?;
+        let invalid = |i: usize, message: String| {
+            PortableSourceError::config(format!("Invalid override rule #{} in {}: {}", i + 1, origin, message))
+        };
+
+        let mut rules = Vec::new();
+        for (i, raw) in file.rule.into_iter().enumerate() {
+            if raw.replace.is_none() && raw.version.is_none() && raw.with.is_empty() && raw.extra_index_url.is_empty() {
+                return Err(invalid(i, "needs replace, version, with or extra_index_url".into()));
+            }
+            if raw.replace.is_some() && raw.version.is_some() {
+                return Err(invalid(i, "replace and version cannot be combined".into()));
+            }
+            let mut platforms = Vec::new();
+            for platform in &raw.platform {
+                let platform = platform.to_lowercase();
+                if !PLATFORMS.contains(&platform.as_str()) {
+                    return Err(invalid(i, format!("unknown platform '{}' (expected {})", platform, PLATFORMS.join(", "))));
+                }
+                platforms.push(platform);
+            }
+            let mut gpus = Vec::new();
+            for gpu in &raw.gpu {
+                let gpu = gpu.to_lowercase();
+                if !GPU_VENDORS.contains(&gpu.as_str()) {
+                    return Err(invalid(i, format!("unknown gpu '{}' (expected {})", gpu, GPU_VENDORS.join(", "))));
+                }
+                gpus.push(gpu);
+            }
+            let replace = raw.replace.as_deref().map(Requirement::parse).transpose().map_err(|e| invalid(i, e.to_string()))?;
+            let version = raw.version.as_deref().map(SpecifierSet::parse).transpose().map_err(|e| invalid(i, e.to_string()))?;
+            let with = raw.with.iter().map(|w| Requirement::parse(w)).collect::<Result<Vec<_>>>().map_err(|e| invalid(i, e.to_string()))?;
+
+            rules.push(OverrideRule {
+                package: canonicalize_name(&raw.package),
+                platforms,
+                gpus,
+                pinned: raw.pinned,
+                replace,
+                version,
+                with,
+                extra_index_urls: raw.extra_index_url,
+                reason: raw.reason,
+                origin: origin.to_string(),
+            });
+        }
+        Ok(rules)
+    }
+
+    /// Last rule matching the requirement on this target
+    pub fn find(&self, requirement: &Requirement, target: &OverrideTarget) -> Option<&OverrideRule> {
+        let name = requirement.canonical_name();
+        let pinned = !requirement.specifier.is_empty() || requirement.url.is_some();
+        let vendor = target.hardware.vendor();
+        self.rules.iter().rev().find(|rule| {
+            rule.package == name
+                && (rule.platforms.is_empty() || rule.platforms.contains(&target.platform))
+                && (rule.gpus.is_empty() || rule.gpus.iter().any(|gpu| gpu == vendor))
+                && rule.pinned.is_none_or(|p| p == pinned)
+        })
+    }
+
+    /// Requirement after the matching rule, or `None` if no rule applies
+    pub fn apply(&self, requirement: &Requirement, target: &OverrideTarget) -> Option<AppliedOverride> {
+        let rule = self.find(requirement, target)?;
+        let mut replaced = match (&rule.replace, &rule.version) {
+            (Some(replacement), _) => {
+                let mut replacement = replacement.clone();
+                // Keep the environment marker of the original line
+                if replacement.marker.is_none() {
+                    replacement.marker = requirement.marker.clone();
+                }
+                replacement
+            }
+            (None, Some(version)) => {
+                let mut pinned = requirement.clone();
+                pinned.specifier = version.clone();
+                pinned.url = None;
+                pinned
+            }
+            (None, None) => requirement.clone(),
+        };
+        replaced.options = requirement.options.clone();
+        Some(AppliedOverride {
+            original: requirement.clone(),
+            requirement: replaced,
+            with: rule.with.clone(),
+            extra_index_urls: rule.extra_index_urls.clone(),
+            reason: rule.reason.clone(),
+            origin: rule.origin.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(platform: &str, hardware: HardwareProfile) -> OverrideTarget {
+        OverrideTarget { platform: platform.into(), hardware }
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let rules = OverrideRules::builtin();
+        let linux_cpu = target("linux", HardwareProfile::Cpu);
+        let linux_cuda = target("linux", HardwareProfile::Nvidia { cuda: Some(128) });
+
+        let tensorflow = Requirement::parse("tensorflow").unwrap();
+        assert_eq!(rules.apply(&tensorflow, &linux_cpu).unwrap().requirement.to_string(), "tensorflow-cpu==2.15.0");
+        assert_eq!(rules.apply(&tensorflow, &linux_cuda).unwrap().requirement.to_string(), "tensorflow==2.15.0");
+        assert!(rules.apply(&Requirement::parse("tensorflow==2.16.1").unwrap(), &linux_cpu).is_none());
+
+        let typing = Requirement::parse("typing_extensions==4.5.0; python_version < '3.12'").unwrap();
+        let applied = rules.apply(&typing, &linux_cpu).unwrap();
+        assert_eq!(applied.requirement.to_string(), "typing_extensions>=4.7.1 ; python_version < '3.12'");
+
+        let insightface = Requirement::parse("insightface").unwrap();
+        let applied = rules.apply(&insightface, &target("windows", HardwareProfile::Cpu)).unwrap();
+        assert!(applied.requirement.url.is_some());
+        assert_eq!(applied.with, vec![Requirement::parse("numpy==1.26.4").unwrap()]);
+    }
+
+    #[test]
+    fn test_later_rules_win() {
+        let mut rules = OverrideRules::builtin();
+        rules.rules.extend(OverrideRules::parse(r#"
+[[rule]]
+package = "TensorFlow"
+gpu = "cpu"
+replace = "tensorflow-cpu==2.16.1"
+extra_index_url = "https://mirror.example/simple"
+"#, "overrides.toml").unwrap());
+        let applied = rules.apply(&Requirement::parse("tensorflow").unwrap(), &target("linux", HardwareProfile::Cpu)).unwrap();
+        assert_eq!(applied.requirement.to_string(), "tensorflow-cpu==2.16.1");
+        assert_eq!(applied.extra_index_urls, vec!["https://mirror.example/simple"]);
+        assert_eq!(applied.origin, "overrides.toml");
+
+        assert!(OverrideRules::parse("[[rule]]\npackage = \"x\"\n", "bad.toml").is_err());
+        assert!(OverrideRules::parse("[[rule]]\npackage = \"x\"\nversion = \"==1\"\nplatform = \"beos\"\n", "bad.toml").is_err());
+    }
+}
@@ -17,15 +17,20 @@
 //! Pip manager for handling Python package installations with pip/uv support.
 //! PySM
 
-use crate::installer::command_runer::CommandRunner;
-use crate::config::ConfigManager;
+use crate::installer::command_runer::{CommandRunner, PlannedAction};
+use crate::config::{ConfigManager, TorchBackend};
 use crate::PortableSourceError;
 use crate::Result;
-use log::{info, debug};
+use log::{info, debug, warn};
+use std::cell::{Cell, RefCell};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::io::Write;
-use serde_json::Value as JsonValue;
+use crate::installer::install_plan::{PackagesStep, PlanStep, ServerInstallPlan};
+use crate::installer::conflicts::DependencyCheck;
+use crate::installer::manifest::InstalledVariants;
+use crate::installer::overrides::{AppliedOverride, OverrideRules, OverrideTarget};
+use crate::installer::pyproject::build_requirements;
+use crate::installer::requirements::{discover_requirements_files, logical_lines, HardwareProfile, Requirement, RequirementLine};
 use toml::Value as TomlValue;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -37,159 +42,68 @@ enum PackageType {
     Triton,
 }
 
-#[derive(Clone, Debug)]
-struct PackageInfo {
-    name: String,
-    version: Option<String>,
-    package_type: PackageType,
-}
-
-impl ToString for PackageInfo {
-    fn to_string(&self) -> String {
-        if let Some(v) = &self.version {
-            format!("{}=={}", self.name, v)
-        } else {
-            self.name.clone()
+impl PackageType {
+    /// Classify by the exact normalized name (`torchmetrics` or `open-clip-torch` are regular packages)
+    fn of(requirement: &Requirement) -> Self {
+        match requirement.canonical_name().as_str() {
+            "torch" | "torchvision" | "torchaudio" | "torchtext" | "torchdata" => PackageType::Torch,
+            "onnxruntime" | "onnxruntime-gpu" | "onnxruntime-directml" | "onnxruntime-openvino"
+            | "onnxruntime-rocm" | "onnxruntime-silicon" => PackageType::Onnxruntime,
+            "insightface" => PackageType::Insightface,
+            "triton" | "triton-windows" => PackageType::Triton,
+            _ => PackageType::Regular,
         }
     }
 }
 
+/// Requirements file split by `PackageType`
 #[derive(Clone, Debug, Default)]
-struct InstallationPlan {
-    torch_packages: Vec<PackageInfo>,
-    onnx_packages: Vec<PackageInfo>,
-    insightface_packages: Vec<PackageInfo>,
-    triton_packages: Vec<PackageInfo>,
-    regular_packages: Vec<PackageInfo>,
-    torch_index_url: Option<String>,
-    onnx_package_name: Option<String>,
+struct SplitRequirements {
+    /// Lines installed straight from the (filtered) requirements file
+    base: Vec<String>,
+    torch: Vec<Requirement>,
+    onnx: Vec<Requirement>,
+    triton: Vec<Requirement>,
+    insightface: Vec<Requirement>,
+    /// Packages that override rules install together with InsightFace
+    insightface_with: Vec<Requirement>,
+    /// Extra index URLs added by override rules
+    extra_index_urls: Vec<String>,
+    overridden: Vec<AppliedOverride>,
 }
 
-struct RequirementsAnalyzer<'a> {
-    config_manager: &'a ConfigManager,
+/// Where the requirement lines of one install come from
+enum RequirementsSource<'s> {
+    /// Requirements files, named relative to `dir` (where the filtered copy is written)
+    Files { names: String, dir: &'s Path },
+    /// Package list of a server plan step, with the torch index it asks for
+    ServerStep { torch_index_url: Option<&'s str> },
 }
 
-impl<'a> RequirementsAnalyzer<'a> {
-    fn new(config_manager: &'a ConfigManager) -> Self {
-        Self { config_manager }
-    }
-
-    fn parse_requirement_line(&self, line_in: &str) -> Option<PackageInfo> {
-        let line = line_in.split('#').next().unwrap_or("").trim().to_string();
-        if line.is_empty() || line.starts_with('-') || line.contains("--index-url") || line.contains("--extra-index-url") {
-            return None;
-        }
-        
-        // Basic parse: name[extras]==version
-        let (name_part, version) = if let Some(idx) = line.find(|c: char| "=><!~".contains(c)) {
-            let (n, v) = line.split_at(idx);
-            (n.trim().to_string(), Some(v.trim_matches(|c| c == '=' || c == '>' || c == '<' || c == '!' || c == '~').to_string()))
-        } else {
-            (line.clone(), None)
-        };
-        
-        let name = if let Some(start) = name_part.find('[') {
-            name_part[..start].to_string()
-        } else {
-            name_part
-        };
-        
-        let lname = name.to_lowercase();
-        let package_type = if ["torch", "torchvision", "torchaudio", "torchtext", "torchdata"].contains(&lname.as_str()) {
-            PackageType::Torch
-        } else if lname.starts_with("onnxruntime") {
-            PackageType::Onnxruntime
-        } else if lname.starts_with("insightface") {
-            PackageType::Insightface
-        } else if lname.starts_with("triton") {
-            PackageType::Triton
-        } else {
-            PackageType::Regular
-        };
-        
-        Some(PackageInfo {
-            name: lname,
-            version,
-            package_type,
-        })
-    }
+/// Local wheel folder used instead of the package indexes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Wheelhouse {
+    /// Install from the folder only (`--no-index --find-links`)
+    Offline(PathBuf),
+    /// Download everything that would be installed into the folder (`pip download`)
+    Download(PathBuf),
+}
 
-    fn create_installation_plan(&self, packages: &Vec<PackageInfo>) -> InstallationPlan {
-        let mut plan = InstallationPlan::default();
-        for p in packages {
-            match p.package_type {
-                PackageType::Torch => plan.torch_packages.push(p.clone()),
-                PackageType::Onnxruntime => plan.onnx_packages.push(p.clone()),
-                PackageType::Insightface => plan.insightface_packages.push(p.clone()),
-                PackageType::Triton => plan.triton_packages.push(p.clone()),
-                PackageType::Regular => plan.regular_packages.push(p.clone()),
-            }
+impl Wheelhouse {
+    pub fn dir(&self) -> &Path {
+        match self {
+            Wheelhouse::Offline(dir) | Wheelhouse::Download(dir) => dir,
         }
-        // torch index url
-        plan.torch_index_url = Some(self.get_torch_index_url());
-        // onnx package name by GPU vendor
-        plan.onnx_package_name = Some(self.get_onnx_package_name());
-        plan
-    }
-
-    fn get_torch_index_url(&self) -> String {
-        if self.config_manager.has_cuda() {
-            let gpu_name = self.config_manager.get_gpu_name();
-            let gpu_generation = self.config_manager.detect_current_gpu_generation();
-            let name_up = gpu_name.to_uppercase();
-            let is_blackwell = name_up.contains("RTX 50") || format!("{:?}", gpu_generation).to_lowercase().contains("blackwell");
-            if is_blackwell {
-                return "https://download.pytorch.org/whl/nightly/cu128".into();
-            }
-        }
-        
-        #[cfg(unix)]
-        {
-            if let Some(cv) = crate::utils::detect_cuda_version_from_system() {
-                return match cv {
-                    crate::config::CudaVersionLinux::Cuda128 => "https://download.pytorch.org/whl/nightly/cu128".into(),
-                    crate::config::CudaVersionLinux::Cuda126 => "https://download.pytorch.org/whl/cu126".into(),
-                    crate::config::CudaVersionLinux::Cuda124 => "https://download.pytorch.org/whl/cu124".into(),
-                    crate::config::CudaVersionLinux::Cuda121 => "https://download.pytorch.org/whl/cu121".into(),
-                    crate::config::CudaVersionLinux::Cuda118 => "https://download.pytorch.org/whl/cu118".into(),
-                };
-            }
-        }
-        
-        #[cfg(windows)]
-        {
-            if self.config_manager.has_cuda() {
-                if let Some(cuda_version) = self.config_manager.get_cuda_version() {
-                    return match cuda_version {
-                        crate::config::CudaVersion::Cuda128 => "https://download.pytorch.org/whl/nightly/cu128".into(),
-                        crate::config::CudaVersion::Cuda124 => "https://download.pytorch.org/whl/cu124".into(),
-                        crate::config::CudaVersion::Cuda118 => "https://download.pytorch.org/whl/cu118".into(),
-                    };
-                }
-            }
-        }
-        
-        "https://download.pytorch.org/whl/cpu".into()
-    }
-
-    fn get_onnx_package_name(&self) -> String {
-        if self.config_manager.has_cuda() {
-            let gpu_name = self.config_manager.get_gpu_name();
-            let up = gpu_name.to_uppercase();
-            if up.contains("NVIDIA") {
-                return "onnxruntime-gpu".into();
-            }
-            if (up.contains("AMD") || up.contains("INTEL")) && cfg!(windows) {
-                return "onnxruntime-directml".into();
-            }
-        }
-        "onnxruntime".into()
     }
 }
 
 pub struct PipManager<'a> {
     command_runner: &'a CommandRunner<'a>,
     config_manager: &'a ConfigManager,
+    dry_run_uv_planned: Cell<bool>,
+    installed_variants: RefCell<InstalledVariants>,
+    torch_backend: TorchBackend,
+    wheelhouse: Option<Wheelhouse>,
 }
 
 impl<'a> PipManager<'a> {
@@ -200,13 +114,123 @@ impl<'a> PipManager<'a> {
         Self {
             command_runner,
             config_manager,
+            dry_run_uv_planned: Cell::new(false),
+            installed_variants: RefCell::new(InstalledVariants::default()),
+            torch_backend: TorchBackend::Auto,
+            wheelhouse: None,
+        }
+    }
+
+    /// Install this PyTorch build instead of the detected one; ONNX Runtime follows it
+    pub fn with_torch_backend(mut self, torch_backend: TorchBackend) -> Self {
+        self.torch_backend = torch_backend;
+        self
+    }
+
+    /// Install from a local wheel folder, or only download into it
+    pub fn with_wheelhouse(mut self, wheelhouse: Wheelhouse) -> Self {
+        self.wheelhouse = Some(wheelhouse);
+        self
+    }
+
+    pub fn wheelhouse(&self) -> Option<&Wheelhouse> {
+        self.wheelhouse.as_ref()
+    }
+
+    /// Packages are only downloaded into the wheelhouse, the environment is left as is
+    pub fn is_download_only(&self) -> bool {
+        matches!(self.wheelhouse, Some(Wheelhouse::Download(_)))
+    }
+
+    /// Adapt a `pip install` / `uv pip install` command to the wheelhouse mode.
+    /// Offline, index options are dropped and packages come only from the wheelhouse;
+    /// in download mode the command becomes `pip download -d <wheelhouse>` with the same
+    /// indexes, so the torch, ONNX and InsightFace builds for this machine are downloaded.
+    fn package_command(&self, cmd: Vec<String>) -> Vec<String> {
+        let Some(wheelhouse) = &self.wheelhouse else { return cmd };
+        let dir = wheelhouse.dir().to_string_lossy().to_string();
+        let mut result = Vec::with_capacity(cmd.len() + 3);
+        let mut args = cmd.into_iter();
+        while let Some(arg) = args.next() {
+            match (wheelhouse, arg.as_str()) {
+                (Wheelhouse::Offline(_), "--index-url" | "--extra-index-url") => { args.next(); }
+                (Wheelhouse::Download(_), "--index-strategy" | "--resolution") => { args.next(); }
+                (Wheelhouse::Download(_), "--force-reinstall" | "-U" | "--upgrade") => {}
+                (Wheelhouse::Download(_), "install") if !result.iter().any(|a| a == "download") => {
+                    result.extend(["download".to_string(), "-d".to_string(), dir.clone()]);
+                }
+                _ => result.push(arg),
+            }
+        }
+        if let Wheelhouse::Offline(_) = wheelhouse {
+            result.extend(["--no-index".to_string(), "--find-links".to_string(), dir]);
+        }
+        result
+    }
+
+    /// Requirement as it can be installed from the wheelhouse: a direct wheel URL
+    /// (e.g. the prebuilt InsightFace wheel) becomes a pin of the wheel's version
+    fn offline_requirement(&self, requirement: &Requirement) -> Requirement {
+        let Some(Wheelhouse::Offline(_)) = &self.wheelhouse else { return requirement.clone() };
+        let Some(url) = &requirement.url else { return requirement.clone() };
+        let version = url.rsplit('/').next()
+            .and_then(|file| file.strip_suffix(".whl"))
+            .and_then(|stem| stem.split('-').nth(1));
+        let mut offline = match version {
+            Some(version) => Requirement::parse(&format!("{}=={}", requirement.name, version))
+                .unwrap_or_else(|_| Requirement::new(&requirement.name)),
+            None => Requirement::new(&requirement.name),
+        };
+        offline.extras = requirement.extras.clone();
+        offline.marker = requirement.marker.clone();
+        offline
+    }
+
+    /// Whether torch is installed (in download mode: whether it was downloaded)
+    fn has_torch(&self, repo_name: &str, repo_path: Option<&Path>) -> bool {
+        if let Some(Wheelhouse::Download(dir)) = &self.wheelhouse {
+            if self.command_runner.is_dry_run() {
+                return true;
+            }
+            return fs::read_dir(dir).into_iter().flatten().flatten().any(|entry| {
+                let name = entry.file_name().to_string_lossy().to_lowercase();
+                name.starts_with("torch-") && name.ends_with(".whl")
+            });
         }
+        let mut check_cmd = self.get_pip_executable(repo_name);
+        check_cmd.extend(["show".into(), "torch".into()]);
+        // In dry-run this is recorded and assumed to succeed, so the reinstall is shown too
+        self.command_runner.run_silent(&check_cmd, Some("Checking for torch"), repo_path).is_ok()
+    }
+
+    /// ONNX Runtime package for an explicitly selected torch backend
+    fn backend_onnx_package(&self) -> Option<&'static str> {
+        match self.torch_backend {
+            TorchBackend::Auto => None,
+            TorchBackend::Cuda(_) => Some("onnxruntime-gpu"),
+            // There is no ROCm build of ONNX Runtime on PyPI
+            TorchBackend::Cpu | TorchBackend::Rocm(_) => Some("onnxruntime"),
+            TorchBackend::Xpu => Some("onnxruntime-openvino"),
+        }
+    }
+
+    /// Torch/ONNX builds installed by this manager so far
+    pub fn installed_variants(&self) -> InstalledVariants {
+        self.installed_variants.borrow().clone()
+    }
+
+    /// Command runner used for all pip/uv calls (also records actions in dry-run)
+    pub fn command_runner(&self) -> &CommandRunner<'a> {
+        self.command_runner
     }
 
     /// Get python executable path in virtual environment
     pub fn get_python_in_env(&self, repo_name: &str) -> PathBuf {
-        let cfg = self.config_manager.get_config();
-        let venv_path = cfg.install_path.join("envs").join(repo_name);
+        Self::venv_python(&self.config_manager.get_config().install_path, repo_name)
+    }
+
+    fn venv_python(install_path: &Path, repo_name: &str) -> PathBuf {
+        let venv_path = install_path.join("envs").join(repo_name);
         if cfg!(windows) {
             venv_path.join("python.exe")
         } else {
@@ -217,7 +241,8 @@ impl<'a> PipManager<'a> {
     /// Get pip executable command for virtual environment
     pub fn get_pip_executable(&self, repo_name: &str) -> Vec<String> {
         let py = self.get_python_in_env(repo_name);
-        if py.exists() {
+        // In dry-run the venv is not created, but commands should show its interpreter
+        if py.exists() || self.command_runner.is_dry_run() {
             vec![py.to_string_lossy().to_string(), "-m".into(), "pip".into()]
         } else {
             vec!["python".into(), "-m".into(), "pip".into()]
@@ -227,7 +252,7 @@ impl<'a> PipManager<'a> {
     /// Get uv executable command for virtual environment
     pub fn get_uv_executable(&self, repo_name: &str) -> Vec<String> {
         let mut py_path = self.get_python_in_env(repo_name);
-        if !py_path.exists() {
+        if !py_path.exists() && !self.command_runner.is_dry_run() {
             py_path = if cfg!(windows) { 
                 PathBuf::from("python.exe") 
             } else { 
@@ -239,6 +264,26 @@ impl<'a> PipManager<'a> {
 
     /// Install uv in virtual environment and check if it's available
     pub fn install_uv_in_venv(&self, repo_name: &str) -> Result<bool> {
+        // uv has no download command: pip downloads everything, including uv for the offline install
+        if self.is_download_only() {
+            if !self.dry_run_uv_planned.replace(true) {
+                let mut pip_cmd = self.get_pip_executable(repo_name);
+                pip_cmd.extend(["install".into(), "uv".into()]);
+                self.command_runner.run(&self.package_command(pip_cmd), Some("Downloading uv"), None)?;
+            }
+            return Ok(false);
+        }
+
+        // Dry-run: show the uv bootstrap once and assume uv is available afterwards
+        if self.command_runner.is_dry_run() {
+            if !self.dry_run_uv_planned.replace(true) {
+                let mut pip_cmd = self.get_pip_executable(repo_name);
+                pip_cmd.extend(["install".into(), "uv".into()]);
+                self.command_runner.run(&self.package_command(pip_cmd), Some("Installing uv (if missing)"), None)?;
+            }
+            return Ok(true);
+        }
+
         let uv_cmd = self.get_uv_executable(repo_name);
         // Try uv --version
         if self.command_runner.run_silent(
@@ -253,7 +298,7 @@ impl<'a> PipManager<'a> {
         let mut pip_cmd = self.get_pip_executable(repo_name);
         pip_cmd.extend(["install".into(), "uv".into()]);
         
-        if let Err(e) = self.command_runner.run(&pip_cmd, Some("Installing uv"), None) {
+        if let Err(e) = self.command_runner.run(&self.package_command(pip_cmd), Some("Installing uv"), None) {
             debug!("Failed to install uv: {}", e);
             return Ok(false);
         }
@@ -274,116 +319,77 @@ impl<'a> PipManager<'a> {
         Ok(uv_works)
     }
 
-    /// Find requirements files in repository, checking specific files first, then using glob patterns
-    pub fn find_requirements_files(&self, repo_path: &Path) -> Option<PathBuf> {
-        // First, check specific known files
-        let specific_candidates = [
-            repo_path.join("requirements.txt"),
-            repo_path.join("requirements_pyp.txt"),
-            repo_path.join("requirements").join("requirements_nvidia.txt"),
-            repo_path.join("requirements").join("requirements.txt"),
-            repo_path.join("install").join("requirements.txt"),
-        ];
-        
-        // Check specific files first
-        for candidate in &specific_candidates {
-            if candidate.exists() {
-                return Some(candidate.clone());
-            }
-        }
-        
-        // Then search for requirements_* patterns in root directory
-        if let Ok(entries) = fs::read_dir(repo_path) {
-            for entry in entries.flatten() {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_file() {
-                        let file_name = entry.file_name();
-                        let name_str = file_name.to_string_lossy();
-                        if name_str.starts_with("requirements_") && name_str.ends_with(".txt") {
-                            return Some(entry.path());
-                        }
-                    }
-                }
-            }
+    /// GPU vendor and CUDA build used to pick the requirements variant
+    pub fn hardware_profile(&self) -> HardwareProfile {
+        match &self.torch_backend {
+            TorchBackend::Auto => {}
+            TorchBackend::Cuda(version) => return HardwareProfile::Nvidia { cuda: Some(*version) },
+            TorchBackend::Rocm(_) => return HardwareProfile::Amd,
+            TorchBackend::Xpu => return HardwareProfile::Intel,
+            TorchBackend::Cpu => return HardwareProfile::Cpu,
         }
-        
-        // Then search for requirements* patterns in root directory
-        if let Ok(entries) = fs::read_dir(repo_path) {
-            for entry in entries.flatten() {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_file() {
-                        let file_name = entry.file_name();
-                        let name_str = file_name.to_string_lossy();
-                        if name_str.starts_with("requirements") && name_str.ends_with(".txt") && name_str != "requirements.txt" {
-                            return Some(entry.path());
-                        }
-                    }
-                }
-            }
+        if self.config_manager.has_cuda() {
+            let index_url = self.get_default_torch_index_url();
+            let cuda = index_url.trim_end_matches('/').rsplit('/').next()
+                .and_then(|tag| tag.strip_prefix("cu"))
+                .and_then(|v| v.parse().ok());
+            return HardwareProfile::Nvidia { cuda };
         }
-        
-        // Finally, search in requirements/ subdirectory for requirements\* patterns
-        let requirements_dir = repo_path.join("requirements");
-        if requirements_dir.exists() {
-            if let Ok(entries) = fs::read_dir(&requirements_dir) {
-                for entry in entries.flatten() {
-                    if let Ok(file_type) = entry.file_type() {
-                        if file_type.is_file() {
-                            let file_name = entry.file_name();
-                            let name_str = file_name.to_string_lossy();
-                            if name_str.starts_with("requirements") && name_str.ends_with(".txt") {
-                                return Some(entry.path());
-                            }
-                        }
-                    }
-                }
-            }
+        let gpu_name = self.config_manager.get_gpu_name().to_uppercase();
+        if gpu_name.contains("AMD") || gpu_name.contains("RADEON") {
+            HardwareProfile::Amd
+        } else if gpu_name.contains("INTEL") {
+            HardwareProfile::Intel
+        } else {
+            HardwareProfile::Cpu
         }
-        
-        None
     }
 
-    /// Extract dependencies from pyproject.toml and create requirements_pyp.txt
-    pub fn extract_dependencies_from_pyproject(&self, pyproject_path: &Path, repo_path: &Path) -> Result<PathBuf> {
-        info!("Parsing pyproject.toml: {:?}", pyproject_path);
-        
-        // Read and parse TOML file
-        let content = fs::read_to_string(pyproject_path)
-            .map_err(|e| PortableSourceError::repository(format!("Failed to read pyproject.toml: {}", e)))?;
-        
-        let toml: TomlValue = content.parse()
-            .map_err(|e| PortableSourceError::repository(format!("Failed to parse pyproject.toml: {}", e)))?;
-        
-        // Extract dependencies from [project.dependencies]
-        let mut dependencies = Vec::new();
-        
-        if let Some(project) = toml.get("project") {
-            if let Some(deps) = project.get("dependencies") {
-                if let Some(deps_array) = deps.as_array() {
-                    for dep in deps_array {
-                        if let Some(dep_str) = dep.as_str() {
-                            dependencies.push(dep_str.to_string());
-                        }
-                    }
-                }
+    /// Override rules for a repository: built-in, then global, then per-repository
+    pub fn override_rules(&self, repo_name: &str) -> Result<OverrideRules> {
+        OverrideRules::load(&self.config_manager.get_config().install_path, repo_name)
+    }
+
+    /// Platform and hardware the override rule conditions are checked against
+    pub fn override_target(&self) -> OverrideTarget {
+        OverrideTarget::current(self.hardware_profile())
+    }
+
+    /// Print the overrides that were applied (and record them in dry-run);
+    /// the indexes they add are remembered for the lockfile
+    fn report_overrides(&self, overridden: &[AppliedOverride]) {
+        if overridden.is_empty() {
+            return;
+        }
+        let mut variants = self.installed_variants.borrow_mut();
+        for url in overridden.iter().flat_map(|applied| &applied.extra_index_urls) {
+            if !variants.extra_index_urls.contains(url) {
+                variants.extra_index_urls.push(url.clone());
             }
         }
-        
-        if dependencies.is_empty() {
-            return Err(PortableSourceError::repository("No dependencies found in pyproject.toml [project.dependencies]".to_string()));
+        println!("[PortableSource] Package overrides:");
+        for applied in overridden {
+            println!("  - {}", applied);
+            if self.command_runner.is_dry_run() {
+                self.command_runner.record(PlannedAction::Note(format!("Override: {}", applied)));
+            }
         }
-        
-        // Create requirements_pyp.txt file
+    }
+
+    /// Find the requirements files to install: the base file, the variant for this
+    /// hardware (requirements-cuda.txt, requirements-rocm.txt, ...) and optional extras
+    pub fn find_requirements_files(&self, repo_path: &Path) -> Vec<PathBuf> {
+        discover_requirements_files(repo_path, &self.hardware_profile())
+    }
+
+    /// Write dependencies declared by the project (pyproject.toml, setup.cfg) to requirements_pyp.txt
+    pub fn write_project_requirements(&self, dependencies: &[String], repo_path: &Path) -> Result<PathBuf> {
         let requirements_path = repo_path.join("requirements_pyp.txt");
-        let mut file = fs::File::create(&requirements_path)
-            .map_err(|e| PortableSourceError::repository(format!("Failed to create requirements_pyp.txt: {}", e)))?;
-        
-        for dep in &dependencies {
-            writeln!(file, "{}", dep)
-                .map_err(|e| PortableSourceError::repository(format!("Failed to write to requirements_pyp.txt: {}", e)))?;
-        }
-        
-        info!("Extracted {} dependencies from pyproject.toml to requirements_pyp.txt", dependencies.len());
+        let content: String = dependencies.iter().map(|dep| format!("{}\n", dep)).collect();
+        self.command_runner.write_file(&requirements_path, &content)
+            .map_err(|e| PortableSourceError::repository(format!("Failed to write requirements_pyp.txt: {}", e)))?;
+
+        info!("Wrote {} project dependencies to requirements_pyp.txt", dependencies.len());
         Ok(requirements_path)
     }
 
@@ -402,49 +408,49 @@ impl<'a> PipManager<'a> {
         let toml: TomlValue = content.parse()
             .map_err(|e| PortableSourceError::repository(format!("Failed to parse pyproject.toml: {}", e)))?;
         
-        // Check for [project.scripts] section
-        if let Some(project) = toml.get("project") {
-            if let Some(scripts) = project.get("scripts") {
-                if let Some(scripts_table) = scripts.as_table() {
-                    // Priority order: gradio+infer scripts first, then any other script
-                    let mut fallback_script: Option<(String, String)> = None;
-                    
-                    for (script_name, script_value) in scripts_table {
-                        if let Some(script_str) = script_value.as_str() {
-                            let name_lower = script_name.to_lowercase();
-                            let value_lower = script_str.to_lowercase();
-                            
-                            // Check if script name or value contains both 'gradio' and 'infer'
-                            if (name_lower.contains("gradio") && name_lower.contains("infer")) ||
-                               (value_lower.contains("gradio") && value_lower.contains("infer")) {
-                                info!("Found gradio+infer script: {} = {}", script_name, script_str);
-                                // Extract module path (before ':') for python -m usage
-                                let module_path = if let Some(colon_pos) = script_str.find(':') {
-                                    script_str[..colon_pos].to_string()
-                                } else {
-                                    script_str.to_string()
-                                };
-                                return Ok((true, Some(module_path)));
-                            }
-                            
-                            // Store first script as fallback
-                            if fallback_script.is_none() {
-                                // Extract module path (before ':') for python -m usage
-                                let module_path = if let Some(colon_pos) = script_str.find(':') {
-                                    script_str[..colon_pos].to_string()
-                                } else {
-                                    script_str.to_string()
-                                };
-                                fallback_script = Some((script_name.clone(), module_path));
-                            }
+        // Check for [project.scripts], then Poetry's [tool.poetry.scripts]
+        let scripts = toml.get("project").and_then(|p| p.get("scripts"))
+            .or_else(|| toml.get("tool").and_then(|t| t.get("poetry")).and_then(|p| p.get("scripts")));
+        if let Some(scripts) = scripts {
+            if let Some(scripts_table) = scripts.as_table() {
+                // Priority order: gradio+infer scripts first, then any other script
+                let mut fallback_script: Option<(String, String)> = None;
+                
+                for (script_name, script_value) in scripts_table {
+                    if let Some(script_str) = script_value.as_str() {
+                        let name_lower = script_name.to_lowercase();
+                        let value_lower = script_str.to_lowercase();
+                        
+                        // Check if script name or value contains both 'gradio' and 'infer'
+                        if (name_lower.contains("gradio") && name_lower.contains("infer")) ||
+                           (value_lower.contains("gradio") && value_lower.contains("infer")) {
+                            info!("Found gradio+infer script: {} = {}", script_name, script_str);
+                            // Extract module path (before ':') for python -m usage
+                            let module_path = if let Some(colon_pos) = script_str.find(':') {
+                                script_str[..colon_pos].to_string()
+                            } else {
+                                script_str.to_string()
+                            };
+                            return Ok((true, Some(module_path)));
+                        }
+                        
+                        // Store first script as fallback
+                        if fallback_script.is_none() {
+                            // Extract module path (before ':') for python -m usage
+                            let module_path = if let Some(colon_pos) = script_str.find(':') {
+                                script_str[..colon_pos].to_string()
+                            } else {
+                                script_str.to_string()
+                            };
+                            fallback_script = Some((script_name.clone(), module_path));
                         }
                     }
-                    
-                    // If no gradio+infer script found, use any available script
-                    if let Some((script_name, script_str)) = fallback_script {
-                        info!("Found pyproject.toml script: {} = {}", script_name, script_str);
-                        return Ok((true, Some(script_str)));
-                    }
+                }
+                
+                // If no gradio+infer script found, use any available script
+                if let Some((script_name, script_str)) = fallback_script {
+                    info!("Found pyproject.toml script: {} = {}", script_name, script_str);
+                    return Ok((true, Some(script_str)));
                 }
             }
         }
@@ -452,81 +458,156 @@ impl<'a> PipManager<'a> {
         Ok((false, None))
     }
 
-    /// Install requirements from requirements.txt file using uv or pip
-    pub fn install_requirements_with_uv_or_pip(&self, repo_name: &str, requirements: &Path, repo_path: Option<&Path>) -> Result<()> {
-        if !requirements.exists() {
-            return Err(PortableSourceError::repository(format!("Requirements file not found: {:?}", requirements)));
+    /// Split a requirements file into the lines installed from it directly and the packages
+    /// that need a hardware-specific build or a custom install (matched by exact name)
+    fn split_requirements(content: &str, rules: &OverrideRules, target: &OverrideTarget) -> SplitRequirements {
+        let mut split = SplitRequirements::default();
+        for line in logical_lines(content) {
+            let requirement = match RequirementLine::parse(&line) {
+                Ok(Some(RequirementLine::Requirement(requirement))) => requirement,
+                Ok(_) => {
+                    split.base.push(line);
+                    continue;
+                }
+                Err(e) => {
+                    // pip/uv may still understand it; keep the line and let them decide
+                    warn!("{}; passing the line to the installer unchanged", e);
+                    split.base.push(line);
+                    continue;
+                }
+            };
+            let (requirement, with, overridden) = match rules.apply(&requirement, target) {
+                Some(applied) => {
+                    for url in &applied.extra_index_urls {
+                        if !split.extra_index_urls.contains(url) {
+                            split.extra_index_urls.push(url.clone());
+                        }
+                    }
+                    let result = (applied.requirement.clone(), applied.with.clone(), true);
+                    split.overridden.push(applied);
+                    result
+                }
+                None => (requirement, Vec::new(), false),
+            };
+            match PackageType::of(&requirement) {
+                // Lines without an override are passed on as written
+                PackageType::Regular if !overridden => split.base.push(line),
+                PackageType::Regular => split.base.push(requirement.to_string()),
+                PackageType::Torch => split.torch.push(requirement),
+                PackageType::Onnxruntime => split.onnx.push(requirement),
+                PackageType::Triton => split.triton.push(requirement),
+                PackageType::Insightface => {
+                    split.insightface.push(requirement);
+                    split.insightface_with.extend(with);
+                    continue;
+                }
+            }
+            split.base.extend(with.iter().map(|w| w.to_string()));
+        }
+        for url in split.extra_index_urls.iter().rev() {
+            split.base.insert(0, format!("--extra-index-url {}", url));
+        }
+        split
+    }
+
+    /// Torch index for the requested packages: a pinned local version (`torch==2.3.1+cu118`)
+    /// selects its own index, otherwise the install plan's index or the one matching the hardware is used
+    fn torch_index_for(&self, torch: &[Requirement], plan_index: Option<&str>) -> (String, &'static str) {
+        let local_tag = torch.iter()
+            .filter_map(|r| r.specifier.pinned_version())
+            .find_map(|version| version.split_once('+').map(|(_, tag)| tag.to_string()));
+        match (local_tag, plan_index) {
+            (Some(tag), _) => (format!("https://download.pytorch.org/whl/{}", tag), "build pinned in requirements"),
+            (None, Some(url)) => (url.to_string(), "index from the install plan"),
+            (None, None) => (self.get_default_torch_index_url(), "build for this hardware"),
         }
+    }
 
-        let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
-        
-        // Handle case when requirements is in different directory than repo_path
-        let tmp = if let Some(repo) = repo_path {
-            if requirements.starts_with(repo) {
-                requirements.to_path_buf()
-            } else {
-                // Copy requirements to repo directory for proper resolution
-                let tmp_req = repo.join("requirements_tmp.txt");
-                std::fs::copy(requirements, &tmp_req)?;
-                tmp_req
+    /// ONNX Runtime requirement to install: the user's variant and version if pinned,
+    /// otherwise the GPU-specific package
+    fn onnx_requirement_for(&self, onnx: &[Requirement]) -> (Requirement, bool) {
+        let detected = Requirement::parse(&self.get_onnx_package_spec()).unwrap_or_else(|_| Requirement::new("onnxruntime"));
+        match onnx.first() {
+            // An explicit variant (onnxruntime-gpu, -directml, ...) is kept as written
+            Some(requested) if requested.canonical_name() != "onnxruntime" => (requested.clone(), true),
+            Some(requested) if !requested.specifier.is_empty() || requested.url.is_some() => {
+                let mut requirement = requested.clone();
+                requirement.name = detected.name;
+                (requirement, true)
             }
-        } else {
-            requirements.to_path_buf()
-        };
+            _ => (detected, false),
+        }
+    }
 
-        // Filter out packages that we install separately from requirements
-        let filtered_req = if repo_path.is_some() {
-            let filtered_path = tmp.parent().unwrap().join("requirements_filtered.txt");
-            let content = std::fs::read_to_string(&tmp)?;
-            let filtered_content = content
-                .lines()
-                .filter(|line| {
-                    let line_lower = line.trim().to_lowercase();
-                    // Skip empty lines and comments
-                    if line_lower.is_empty() || line_lower.starts_with('#') {
-                        return true;
-                    }
-                    // Filter out packages we install separately
-                    !line_lower.starts_with("insightface") && 
-                    !line_lower.contains("insightface") &&
-                    !line_lower.starts_with("onnxruntime") && 
-                    !line_lower.contains("onnxruntime") &&
-                    !line_lower.starts_with("torch") && 
-                    !line_lower.contains("torch") &&
-                    !line_lower.starts_with("triton") && 
-                    !line_lower.contains("triton")
-                })
-                .collect::<Vec<_>>()
-                .join("\n");
-            std::fs::write(&filtered_path, filtered_content)?;
-            filtered_path
+    /// Install requirements from requirements.txt file using uv or pip
+    pub fn install_requirements_with_uv_or_pip(&self, repo_name: &str, requirements: &Path, repo_path: Option<&Path>) -> Result<()> {
+        self.install_requirement_files(repo_name, &[requirements.to_path_buf()], repo_path)
+    }
+
+    /// Read requirements files with `-r` includes inlined (each file once) and `-c`
+    /// constraint paths made absolute, so the result can be installed from any folder
+    fn read_requirements_with_includes(&self, file: &Path, visited: &mut Vec<PathBuf>, lines: &mut Vec<String>) -> Result<()> {
+        if visited.iter().any(|v| v == file) {
+            return Ok(());
+        }
+        visited.push(file.to_path_buf());
+
+        // Dry-run of a fresh install: the repository is not cloned yet, so its files are unknown
+        let content = if self.command_runner.file_exists(file) {
+            self.command_runner.read_to_string(file)?
+        } else if self.command_runner.is_dry_run() {
+            self.command_runner.record(PlannedAction::Note(format!(
+                "{:?} does not exist yet; its packages are not analyzed", file
+            )));
+            return Ok(());
         } else {
-            tmp.clone()
+            return Err(PortableSourceError::repository(format!("Requirements file not found: {:?}", file)));
         };
 
-        if uv_available {
-            let mut uv_cmd = self.get_uv_executable(repo_name);
-            uv_cmd.extend(["pip".into(), "install".into(), "-r".into(), filtered_req.to_string_lossy().to_string()]);
-            self.command_runner.run(&uv_cmd, Some("Installing requirements (uv)"), repo_path)?;
-        } else {
-            let mut pip_cmd = self.get_pip_executable(repo_name);
-            pip_cmd.extend(["install".into(), "-r".into(), filtered_req.to_string_lossy().to_string()]);
-            self.command_runner.run(&pip_cmd, Some("Installing requirements (pip)"), repo_path)?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for line in logical_lines(&content) {
+            match RequirementLine::parse(&line) {
+                Ok(Some(RequirementLine::Option(option))) if option.name == "--requirement" => {
+                    let include = dir.join(option.value.unwrap_or_default());
+                    debug!("Including {:?} from {:?}", include, file);
+                    self.read_requirements_with_includes(&include, visited, lines)?;
+                }
+                Ok(Some(RequirementLine::Option(mut option))) if option.name == "--constraint" => {
+                    option.value = option.value.map(|v| dir.join(v).to_string_lossy().to_string());
+                    lines.push(option.to_string());
+                }
+                _ => lines.push(line),
+            }
         }
+        Ok(())
+    }
 
-        // Clean up temporary files if created
-        if repo_path.is_some() {
-            if tmp.file_name() == Some(std::ffi::OsStr::new("requirements_tmp.txt")) {
-                let _ = std::fs::remove_file(&tmp);
-            }
-            if filtered_req.file_name() == Some(std::ffi::OsStr::new("requirements_filtered.txt")) {
-                let _ = std::fs::remove_file(&filtered_req);
-            }
+    /// Install one or more requirements files (with their `-r` includes) in a single resolution
+    pub fn install_requirement_files(&self, repo_name: &str, files: &[PathBuf], repo_path: Option<&Path>) -> Result<()> {
+        let mut visited = Vec::new();
+        let mut merged = Vec::new();
+        for file in files {
+            self.read_requirements_with_includes(file, &mut visited, &mut merged)?;
         }
+        let requirements = files.first().map(PathBuf::as_path).unwrap_or(Path::new("requirements.txt"));
+        let base_dir = repo_path.or(requirements.parent()).unwrap_or(Path::new(""));
+        let file_names: Vec<String> = visited.iter()
+            .map(|f| f.strip_prefix(base_dir).unwrap_or(f).to_string_lossy().replace('\\', "/"))
+            .collect();
+        let source = RequirementsSource::Files { names: file_names.join(", "), dir: base_dir };
+        self.install_requirement_lines(repo_name, &merged.join("\n"), &source, repo_path)
+    }
 
-        // Install ONNX with GPU detection after base requirements
-        let onnx_spec = self.get_onnx_package_spec();
-        let mut onnx_cmd = if uv_available {
+    /// Install requirement lines with torch, ONNX Runtime, Triton and InsightFace routed to
+    /// their own installs, and report every line that was rerouted
+    fn install_requirement_lines(&self, repo_name: &str, content: &str, source: &RequirementsSource, repo_path: Option<&Path>) -> Result<()> {
+        let rules = self.override_rules(repo_name)?;
+        let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
+        let split = Self::split_requirements(content, &rules, &self.override_target());
+        self.report_overrides(&split.overridden);
+        // Requirements files also get the GPU runtime packages; a server step installs only what it lists
+        let complete = matches!(source, RequirementsSource::Files { .. });
+        let install_cmd = || if uv_available {
             let mut cmd = self.get_uv_executable(repo_name);
             cmd.extend(["pip".into(), "install".into()]);
             cmd
@@ -535,118 +616,168 @@ impl<'a> PipManager<'a> {
             cmd.push("install".into());
             cmd
         };
-        
-        // Check if we need --pre flag for nightly builds (Blackwell GPUs)
-        if self.needs_onnx_nightly() {
-            onnx_cmd.push("--pre".into());
+        let mut rerouted: Vec<String> = Vec::new();
+
+        // Leave out packages that we install separately from requirements.
+        // Relative paths in requirement lines are resolved against the working directory (the repository)
+        if !split.base.is_empty() {
+            let filtered_dir = match source {
+                RequirementsSource::Files { dir, .. } if !dir.as_os_str().is_empty() => dir,
+                _ => repo_path.unwrap_or(Path::new(".")),
+            };
+            let filtered_req = filtered_dir.join("requirements_filtered.txt");
+            self.command_runner.write_file(&filtered_req, &split.base.join("\n"))?;
+
+            let mut base_cmd = install_cmd();
+            base_cmd.extend(["-r".into(), filtered_req.to_string_lossy().to_string()]);
+            let label = if uv_available { "Installing requirements (uv)" } else { "Installing requirements (pip)" };
+            let result = self.command_runner.run(&self.package_command(base_cmd), Some(label), repo_path);
+
+            // Clean up the temporary file
+            if !self.command_runner.is_dry_run() {
+                let _ = std::fs::remove_file(&filtered_req);
+            }
+            result?;
         }
+
+        // Install ONNX with GPU detection after base requirements
+        if complete || !split.onnx.is_empty() {
+            let (onnx_requirement, onnx_pinned) = self.onnx_requirement_for(&split.onnx);
+            let onnx_spec = onnx_requirement.to_string();
+            for requested in &split.onnx {
+                rerouted.push(if onnx_pinned {
+                    format!("{} -> {} (installed separately, version kept)", requested, onnx_spec)
+                } else {
+                    format!("{} -> {} (build for this GPU)", requested, onnx_spec)
+                });
+            }
+            let mut onnx_cmd = install_cmd();
         
-        if uv_available {
-            onnx_cmd.extend(["--index-strategy".into(), "unsafe-best-match".into()]);
-        }
-        onnx_cmd.push(onnx_spec);
+            // Check if we need --pre flag for nightly builds (Blackwell GPUs); a pinned version is used as is
+            let use_pre = self.needs_onnx_nightly() && !onnx_pinned;
+            if use_pre {
+                onnx_cmd.push("--pre".into());
+            }
         
-        if let Err(_) = self.command_runner.run_verbose(&onnx_cmd, Some("Installing ONNX with GPU support"), repo_path) {
-            // Fallback without --pre if it fails
-            if self.needs_onnx_nightly() {
-                let mut fallback_cmd = if uv_available {
-                    let mut cmd = self.get_uv_executable(repo_name);
-                    cmd.extend(["pip".into(), "install".into()]);
-                    cmd
-                } else {
-                    let mut cmd = self.get_pip_executable(repo_name);
-                    cmd.push("install".into());
-                    cmd
-                };
-                if uv_available {
-                    fallback_cmd.extend(["--index-strategy".into(), "unsafe-best-match".into()]);
+            if uv_available {
+                onnx_cmd.extend(["--index-strategy".into(), "unsafe-best-match".into()]);
+            }
+            onnx_cmd.push(onnx_spec.clone());
+        
+            if self.command_runner.run_verbose(&self.package_command(onnx_cmd), Some("Installing ONNX with GPU support"), repo_path).is_err() {
+                // Fallback without --pre if it fails
+                if use_pre {
+                    let mut fallback_cmd = install_cmd();
+                    if uv_available {
+                        fallback_cmd.extend(["--index-strategy".into(), "unsafe-best-match".into()]);
+                    }
+                    fallback_cmd.push(onnx_spec.clone());
+                    if self.command_runner.run_verbose(&self.package_command(fallback_cmd), Some("Installing ONNX (fallback)"), repo_path).is_ok() {
+                        self.installed_variants.borrow_mut().onnx_package = Some(onnx_spec);
+                    }
                 }
-                fallback_cmd.push(self.get_onnx_package_spec());
-                let _ = self.command_runner.run_verbose(&fallback_cmd, Some("Installing ONNX (fallback)"), repo_path);
+            } else {
+                self.installed_variants.borrow_mut().onnx_package = Some(onnx_spec);
             }
         }
 
-        // Check if torch is installed and reinstall with CUDA index if needed
-        let mut check_cmd = self.get_pip_executable(repo_name);
-        check_cmd.extend(["show".into(), "torch".into()]);
-        
-        let cfg = self.config_manager.get_config();
-        let venv_path = cfg.install_path.join("envs").join(repo_name);
-        
-        if let Ok(output) = std::process::Command::new(&check_cmd[0])
-            .args(&check_cmd[1..])
-            .env("VIRTUAL_ENV", venv_path)
-            .output() {
-            if output.status.success() {
-                let mut reinstall_cmd = if uv_available {
-                    let mut cmd = self.get_uv_executable(repo_name);
-                    cmd.extend(["pip".into(), "install".into()]);
-                    cmd
-                } else {
-                    let mut cmd = self.get_pip_executable(repo_name);
-                    cmd.push("install".into());
-                    cmd
-                };
-                
-                reinstall_cmd.extend([
-                    "--force-reinstall".into(), 
-                    "--index-url".into(), 
+        if !split.torch.is_empty() {
+            // Requested torch packages (with the user's versions) from the matching torch index
+            let plan_index = match source {
+                RequirementsSource::ServerStep { torch_index_url } => *torch_index_url,
+                RequirementsSource::Files { .. } => None,
+            };
+            let (torch_index, reason) = self.torch_index_for(&split.torch, plan_index);
+            for requested in &split.torch {
+                rerouted.push(format!("{} -> {} ({})", requested, torch_index, reason));
+            }
+            let mut torch_packages = split.torch.clone();
+            let names: Vec<String> = torch_packages.iter().map(Requirement::canonical_name).collect();
+            if names.iter().any(|n| n == "torch") {
+                for companion in ["torchvision", "torchaudio"] {
+                    if !names.iter().any(|n| n == companion) {
+                        torch_packages.push(Requirement::new(companion));
+                    }
+                }
+            }
+
+            let mut torch_cmd = install_cmd();
+            torch_cmd.extend(["--force-reinstall".into(), "--index-url".into(), torch_index.clone()]);
+            torch_cmd.extend(torch_packages.iter().map(Requirement::to_string));
+            self.command_runner.run_verbose(&self.package_command(torch_cmd), Some("Installing torch packages"), repo_path)?;
+            self.installed_variants.borrow_mut().torch_index_url = Some(torch_index);
+        } else if complete {
+            // Torch pulled in by another package: reinstall it with CUDA index if needed
+            if self.has_torch(repo_name, repo_path) {
+                let torch_args = [
+                    "--force-reinstall".to_string(),
+                    "--index-url".to_string(),
                     self.get_default_torch_index_url(),
-                    "torch".into(), 
-                    "torchvision".into(), 
-                    "torchaudio".into()
-                ]);
+                    "torch".to_string(),
+                    "torchvision".to_string(),
+                    "torchaudio".to_string(),
+                ];
+                let mut reinstall_cmd = install_cmd();
+                reinstall_cmd.extend(torch_args.iter().cloned());
                 
-                if let Err(_) = self.command_runner.run_verbose(&reinstall_cmd, Some("Reinstalling torch with CUDA"), repo_path) {
+                let mut torch_installed = self.command_runner.run_verbose(&self.package_command(reinstall_cmd), Some("Reinstalling torch with CUDA"), repo_path).is_ok();
+                if !torch_installed && uv_available {
                     // Fallback to pip if uv fails
-                    if uv_available {
-                        let mut pip_cmd = self.get_pip_executable(repo_name);
-                        pip_cmd.extend([
-                            "install".into(), 
-                            "--force-reinstall".into(), 
-                            "--index-url".into(), 
-                            self.get_default_torch_index_url(),
-                            "torch".into(), 
-                            "torchvision".into(), 
-                            "torchaudio".into()
-                        ]);
-                        let _ = self.command_runner.run_verbose(&pip_cmd, Some("Reinstalling torch with CUDA (pip)"), repo_path);
-                    }
+                    let mut pip_cmd = self.get_pip_executable(repo_name);
+                    pip_cmd.push("install".into());
+                    pip_cmd.extend(torch_args.iter().cloned());
+                    torch_installed = self.command_runner.run_verbose(&self.package_command(pip_cmd), Some("Reinstalling torch with CUDA (pip)"), repo_path).is_ok();
+                }
+                if torch_installed {
+                    self.installed_variants.borrow_mut().torch_index_url = Some(self.get_default_torch_index_url());
                 }
             }
         }
 
         // Install Triton with platform-specific package names
-        let mut triton_cmd = if uv_available {
-            let mut cmd = self.get_uv_executable(repo_name);
-            cmd.extend(["pip".into(), "install".into()]);
-            cmd
-        } else {
-            let mut cmd = self.get_pip_executable(repo_name);
-            cmd.push("install".into());
-            cmd
-        };
-        
-        // Use platform-specific triton package names
-        #[cfg(windows)]
-        triton_cmd.push("triton-windows".into());
-        #[cfg(not(windows))]
-        triton_cmd.push("triton".into());
-        
-        let _ = self.command_runner.run(&triton_cmd, Some("Installing Triton"), repo_path);
-
-        // Check if InsightFace was in the original requirements
-        let needs_insightface = std::fs::read_to_string(&tmp)?
-            .lines()
-            .any(|line| {
-                let line_lower = line.trim().to_lowercase();
-                line_lower.starts_with("insightface") || 
-                line_lower.contains("insightface")
-            });
+        if complete || !split.triton.is_empty() {
+            let mut triton_cmd = install_cmd();
+            #[cfg(windows)]
+            {
+                for requested in &split.triton {
+                    rerouted.push(format!("{} -> triton-windows (Windows build of Triton)", requested));
+                }
+                triton_cmd.push("triton-windows".into());
+            }
+            #[cfg(not(windows))]
+            {
+                match split.triton.iter().find(|r| r.canonical_name() == "triton") {
+                    Some(requested) => triton_cmd.push(requested.to_string()),
+                    None => triton_cmd.push("triton".into()),
+                }
+            }
+            let _ = self.command_runner.run(&self.package_command(triton_cmd), Some("Installing Triton"), repo_path);
+        }
 
         // Install InsightFace only if it was requested in requirements
-        if needs_insightface {
-            self.handle_insightface_package(repo_name, repo_path)?;
+        if !split.insightface.is_empty() {
+            let mut packages = split.insightface.clone();
+            packages.extend(split.insightface_with.iter().cloned());
+            let names: Vec<String> = packages.iter().map(|p| p.to_string()).collect();
+            for requested in &split.insightface {
+                rerouted.push(format!("{} -> installed separately: {}", requested, names.join(" ")));
+            }
+            self.handle_insightface_package(repo_name, &packages, &split.extra_index_urls, repo_path)?;
+        }
+
+        if !rerouted.is_empty() {
+            match source {
+                RequirementsSource::Files { names, .. } => println!("[PortableSource] Requirements from {} installed separately:", names),
+                RequirementsSource::ServerStep { .. } => println!("[PortableSource] Packages of the server plan installed separately:"),
+            }
+            for line in &rerouted {
+                println!("  - {}", line);
+            }
+            if self.command_runner.is_dry_run() {
+                for line in rerouted {
+                    self.command_runner.record(PlannedAction::Note(format!("Rerouted requirement: {}", line)));
+                }
+            }
         }
 
         Ok(())
@@ -655,20 +786,115 @@ impl<'a> PipManager<'a> {
     /// Install repository as package using uv or pip
     pub fn install_repo_as_package(&self, repo_name: &str, repo_path: &Path) -> Result<()> {
         let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
+
+        // The offline install builds the package without an index, so the build backend has to be in the wheelhouse
+        if self.is_download_only() {
+            let mut build_cmd = self.get_pip_executable(repo_name);
+            build_cmd.push("install".into());
+            build_cmd.extend(build_requirements(repo_path));
+            self.command_runner.run_silent(&self.package_command(build_cmd), Some("Downloading build requirements"), Some(repo_path))?;
+        }
         
         if uv_available {
             let mut uv_cmd = self.get_uv_executable(repo_name);
             uv_cmd.extend(["pip".into(), "install".into(), ".".into()]);
-            self.command_runner.run_silent(&uv_cmd, Some("Installing repository as package (uv)"), Some(repo_path))
+            self.command_runner.run_silent(&self.package_command(uv_cmd), Some("Installing repository as package (uv)"), Some(repo_path))
         } else {
             let mut pip_cmd = self.get_pip_executable(repo_name);
             pip_cmd.extend(["install".into(), ".".into()]);
-            self.command_runner.run_silent(&pip_cmd, Some("Installing repository as package (pip)"), Some(repo_path))
+            self.command_runner.run_silent(&self.package_command(pip_cmd), Some("Installing repository as package (pip)"), Some(repo_path))
+        }
+    }
+
+    /// Exact versions installed in the environment (`uv pip freeze`, `pip freeze` as fallback)
+    pub fn freeze(&self, repo_name: &str) -> Result<String> {
+        // Only use uv if it is already in the environment
+        let mut uv_cmd = self.get_uv_executable(repo_name);
+        uv_cmd.extend(["pip".into(), "freeze".into()]);
+        match self.command_runner.output(&uv_cmd, None) {
+            Ok(output) if output.success() => return Ok(output.stdout),
+            Ok(output) => debug!("uv pip freeze failed ({}): {}", output.status_text(), output.stderr),
+            Err(e) => debug!("uv pip freeze failed: {}", e),
+        }
+        // --all keeps pip, setuptools and wheel in the list
+        let mut pip_cmd = self.get_pip_executable(repo_name);
+        pip_cmd.extend(["freeze".into(), "--all".into()]);
+        let output = self.command_runner.output(&pip_cmd, None)?;
+        if !output.success() {
+            return Err(PortableSourceError::command(format!(
+                "pip freeze failed with {}\nOutput:\n{}", output.status_text(), output.stderr
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Check the environment for broken requirements (`uv pip check`, or `pip check` without uv)
+    pub fn check_dependencies(&self, repo_name: &str) -> Result<DependencyCheck> {
+        let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
+        let cmd = if uv_available {
+            let mut cmd = self.get_uv_executable(repo_name);
+            cmd.extend(["pip".into(), "check".into()]);
+            cmd
+        } else {
+            let mut cmd = self.get_pip_executable(repo_name);
+            cmd.push("check".into());
+            cmd
+        };
+        let output = self.command_runner.output(&cmd, None)?;
+        // Both exit with 1 when conflicts are found
+        let check = DependencyCheck::parse(&output.stdout, &output.stderr, output.success());
+        if !output.success() && check.is_clean() {
+            return Err(PortableSourceError::command(format!(
+                "{} failed with {}: {}", cmd[1..].join(" "), output.status_text(), output.stderr.trim()
+            )));
+        }
+        Ok(check)
+    }
+
+    /// Install exactly the locked package set: `uv pip sync`, or `pip install --no-deps` without uv
+    pub fn install_locked(&self, repo_name: &str, requirements: &Path, torch_index_url: Option<&str>, extra_index_urls: &[String], repo_path: Option<&Path>) -> Result<()> {
+        let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
+        let requirements_arg = requirements.to_string_lossy().to_string();
+
+        let mut cmd = if uv_available {
+            let mut cmd = self.get_uv_executable(repo_name);
+            cmd.extend(["pip".into(), "sync".into(), requirements_arg]);
+            cmd
+        } else {
+            let mut cmd = self.get_pip_executable(repo_name);
+            cmd.extend(["install".into(), "--no-deps".into(), "-r".into(), requirements_arg]);
+            cmd
+        };
+        // Local torch versions (2.7.0+cu128) only exist on the torch index,
+        // packages substituted by override rules may only exist on their index
+        let index_urls: Vec<&str> = torch_index_url.into_iter().chain(extra_index_urls.iter().map(String::as_str)).collect();
+        for index_url in &index_urls {
+            cmd.extend(["--extra-index-url".into(), index_url.to_string()]);
+        }
+        if uv_available && !index_urls.is_empty() {
+            cmd.extend(["--index-strategy".into(), "unsafe-best-match".into()]);
         }
+        let label = if uv_available { "Installing locked packages (uv)" } else { "Installing locked packages (pip)" };
+        self.command_runner.run_verbose(&self.package_command(cmd), Some(label), repo_path)?;
+
+        let mut variants = self.installed_variants.borrow_mut();
+        variants.torch_index_url = torch_index_url.map(String::from);
+        variants.extra_index_urls = extra_index_urls.to_vec();
+        if let Ok(content) = self.command_runner.read_to_string(requirements) {
+            variants.onnx_package = logical_lines(&content).into_iter()
+                .find(|line| matches!(
+                    RequirementLine::parse(line),
+                    Ok(Some(RequirementLine::Requirement(ref r))) if PackageType::of(r) == PackageType::Onnxruntime
+                ));
+        }
+        Ok(())
     }
 
     /// Apply ONNX GPU detection to package name
     pub fn apply_onnx_gpu_detection(&self, base: &str) -> String {
+        if let Some(package) = self.backend_onnx_package() {
+            return if base == "onnxruntime" { package.into() } else { base.into() };
+        }
         let up = self.config_manager.get_gpu_name().to_uppercase();
         if base.starts_with("onnxruntime") && !base.contains("-gpu") && !base.contains("-directml") {
             if up.contains("NVIDIA") {
@@ -683,6 +909,9 @@ impl<'a> PipManager<'a> {
 
     /// Check if ONNX nightly build is needed for GPU compatibility
     pub fn needs_onnx_nightly(&self) -> bool {
+        if self.torch_backend != TorchBackend::Auto {
+            return false;
+        }
         // Blackwell GPUs need nightly builds
         if self.config_manager.has_cuda() {
             let gpu_generation = self.config_manager.detect_current_gpu_generation();
@@ -710,6 +939,9 @@ impl<'a> PipManager<'a> {
 
     /// Get ONNX package specification with GPU generation consideration
     pub fn get_onnx_package_spec(&self) -> String {
+        if let Some(package) = self.backend_onnx_package() {
+            return package.into();
+        }
         if self.config_manager.has_cuda() {
             let gpu_generation = self.config_manager.detect_current_gpu_generation();
             let gpu_name = self.config_manager.get_gpu_name();
@@ -733,12 +965,15 @@ impl<'a> PipManager<'a> {
 
     /// Get default torch index URL based on GPU and CUDA configuration
     pub fn get_default_torch_index_url(&self) -> String {
+        if let Some(index_url) = self.torch_backend.index_url() {
+            return index_url;
+        }
         if self.config_manager.has_cuda() {
             let gpu_name = self.config_manager.get_gpu_name();
             let gpu_generation = self.config_manager.detect_current_gpu_generation();
-            let _name_up = gpu_name.to_uppercase();
+            let name_up = gpu_name.to_uppercase();
             let gen_str = format!("{:?}", gpu_generation).to_lowercase();
-            let is_blackwell = gen_str.contains("blackwell");
+            let is_blackwell = gen_str.contains("blackwell") || name_up.contains("RTX 50");
             
             if is_blackwell {
                 return "https://download.pytorch.org/whl/nightly/cu128".to_string();
@@ -771,6 +1006,16 @@ impl<'a> PipManager<'a> {
             }
         }
         
+        // AMD GPUs on Linux: ROCm build
+        if cfg!(target_os = "linux") {
+            let name_up = self.config_manager.get_gpu_name().to_uppercase();
+            if name_up.contains("AMD") || name_up.contains("RADEON") {
+                if let Some(index_url) = TorchBackend::detect_rocm().index_url() {
+                    return index_url;
+                }
+            }
+        }
+        
         "https://download.pytorch.org/whl/cpu".to_string()
     }
     
@@ -780,11 +1025,9 @@ impl<'a> PipManager<'a> {
     }
 
     /// Execute server installation plan steps
-    pub fn execute_server_installation_plan(&self, repo_name: &str, plan: &JsonValue, repo_path: Option<&Path>) -> Result<bool> {
-        let steps = plan.get("steps").and_then(|s| s.as_array()).cloned().unwrap_or_default();
-        
+    pub fn execute_server_installation_plan(&self, repo_name: &str, plan: &ServerInstallPlan, repo_path: Option<&Path>) -> Result<bool> {
         // Process all installation steps
-        for step in &steps {
+        for step in &plan.steps {
             self.process_server_step(repo_name, step, repo_path)?;
         }
         
@@ -792,279 +1035,188 @@ impl<'a> PipManager<'a> {
     }
 
     /// Process individual server installation step
-    pub fn process_server_step(&self, repo_name: &str, step: &JsonValue, repo_path: Option<&Path>) -> Result<()> {
-        let step_type = step.get("type").and_then(|s| s.as_str()).unwrap_or("");
-        match step_type {
-            "requirements" => {
-                if let Some(path) = step.get("path").and_then(|s| s.as_str()) {
-                    let req_path = if let Some(rp) = repo_path { 
-                        rp.join(path) 
-                    } else { 
-                        PathBuf::from(path) 
-                    };
-                    self.install_requirements_with_uv_or_pip(repo_name, &req_path, repo_path)?;
-                }
+    pub fn process_server_step(&self, repo_name: &str, step: &PlanStep, repo_path: Option<&Path>) -> Result<()> {
+        match step {
+            PlanStep::Requirements { path } => {
+                let req_path = if let Some(rp) = repo_path { 
+                    rp.join(path) 
+                } else { 
+                    PathBuf::from(path) 
+                };
+                self.install_requirements_with_uv_or_pip(repo_name, &req_path, repo_path)?;
             }
-            "pip_install" | "regular" | "regular_only" => {
+            PlanStep::PipInstall(packages) | PlanStep::Regular(packages) | PlanStep::RegularOnly(packages) => {
                 // Handle pip installation steps with package analysis and UV installation
-                info!("Processing {} step with {} packages", step_type, 
-                     step.get("packages").and_then(|p| p.as_array()).map(|a| a.len()).unwrap_or(0));
-                self.handle_pip_install_step(repo_name, step, repo_path)?;
-            }
-            _ => {
-                debug!("Unknown step type in server plan: {}", step_type);
+                info!("Processing {} step with {} packages", step.type_name(), packages.packages.len());
+                self.handle_pip_install_step(repo_name, packages, repo_path)?;
             }
         }
         Ok(())
     }
 
-    /// Handle pip_install step with comprehensive package analysis and separation
-    fn handle_pip_install_step(&self, repo_name: &str, step: &JsonValue, repo_path: Option<&Path>) -> Result<()> {
+    /// Install the packages of a server plan step the same way as a requirements file
+    fn handle_pip_install_step(&self, repo_name: &str, step: &PackagesStep, repo_path: Option<&Path>) -> Result<()> {
+        let source = RequirementsSource::ServerStep { torch_index_url: step.torch_index_url.as_deref() };
+        self.install_requirement_lines(repo_name, &step.packages.join("\n"), &source, repo_path)
+    }
+
+    /// Install InsightFace together with its companion packages from the override rules
+    /// (the prebuilt Windows wheel and the numpy pin come from the built-in rules)
+    pub fn handle_insightface_package(&self, repo_name: &str, packages: &[Requirement], extra_index_urls: &[String], repo_path: Option<&Path>) -> Result<()> {
         let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
-        
-        // Create analyzer for intelligent package processing
-        let analyzer = RequirementsAnalyzer::new(self.config_manager);
-        
-        // Parse packages into PackageInfo structs with proper version handling
-        let mut packages = Vec::new();
-        if let Some(pkgs) = step.get("packages").and_then(|p| p.as_array()) {
-            for p in pkgs {
-                if let Some(s) = p.as_str() {
-                    if let Some(pkg_info) = analyzer.parse_requirement_line(s) {
-                        packages.push(pkg_info);
-                    }
-                }
-            }
-        }
-        
-        // Create installation plan with intelligent package separation
-        let plan = analyzer.create_installation_plan(&packages);
-        
-        // Install regular packages first (no special index needed)
-        if !plan.regular_packages.is_empty() {
-            let mut cmd = if uv_available {
-                let mut c = self.get_uv_executable(repo_name);
-                c.extend(["pip".into(), "install".into()]);
-                c
-            } else {
-                let mut c = self.get_pip_executable(repo_name);
-                c.push("install".into());
-                c
-            };
-            
-            // Check if we need --pre flag for any onnx packages that got classified as regular
-            let needs_pre = self.needs_onnx_nightly() && 
-                plan.regular_packages.iter().any(|pkg| {
-                    pkg.name.starts_with("onnxruntime") && pkg.name.contains("gpu")
-                });
-            if needs_pre {
-                cmd.push("--pre".into());
-            }
-            
-            // Add dependency resolution strategy flags for better conflict handling (uv only)
-            if uv_available {
-                cmd.extend(["--resolution".into(), "highest".into()]);
-                cmd.extend(["--index-strategy".into(), "unsafe-best-match".into()]);
-            }
-            
-            // Add package specs with proper version handling
-            for pkg in &plan.regular_packages {
-                let pkg_spec = if pkg.name == "tensorflow" && pkg.version.is_none() {
-                    // Handle unversioned tensorflow with platform-specific logic
-                    #[cfg(windows)]
-                    {
-                        // On Windows, use regular tensorflow (CUDA libraries come separately)
-                        // Use compatible version that works with typing-extensions>=4.8.0
-                        "tensorflow==2.15.0".to_string()
-                    }
-                    #[cfg(not(windows))]
-                    {
-                        // On Linux, can use tensorflow with CUDA extensions
-                        if self.config_manager.has_cuda() {
-                            "tensorflow==2.15.0".to_string()
-                        } else {
-                            "tensorflow-cpu==2.15.0".to_string()
-                        }
-                    }
-                } else if pkg.name == "typing-extensions" && pkg.version.is_some() {
-                    // Use a compatible typing-extensions version that works with both tensorflow and onnx
-                    // onnx>=1.18.0 requires typing-extensions>=4.7.1
-                    // tensorflow 2.15.0 can work with typing-extensions>=4.7.1
-                    "typing-extensions>=4.7.1".to_string()
-                } else {
-                    pkg.to_string()
-                };
-                cmd.push(pkg_spec);
-            }
-            
-            self.command_runner.run(&cmd, Some("Installing regular packages"), repo_path)?;
-        }
-        
-        // Install torch packages with appropriate index URL
-        if !plan.torch_packages.is_empty() {
-            let mut cmd = if uv_available {
-                let mut c = self.get_uv_executable(repo_name);
-                c.extend(["pip".into(), "install".into()]);
-                c
-            } else {
-                let mut c = self.get_pip_executable(repo_name);
-                c.push("install".into());
-                c
-            };
-            
-            // Use torch index URL from plan or step or default
-            let torch_index = plan.torch_index_url.as_ref()
-                .map(|s| s.as_str())
-                .or_else(|| step.get("torch_index_url").and_then(|s| s.as_str()))
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| self.get_default_torch_index_url());
-            
-            cmd.extend(["--index-url".into(), torch_index]);
-            
-            // Complete torch package trio - ensure torch, torchvision, torchaudio are all present
-            let torch_names: std::collections::HashSet<String> = plan.torch_packages.iter().map(|p| p.name.clone()).collect();
-            let mut final_packages = plan.torch_packages.clone();
-            
-            // Add missing torch packages to complete the trio
-            if torch_names.contains("torch") {
-                if !torch_names.contains("torchvision") {
-                    final_packages.push(PackageInfo {
-                        name: "torchvision".to_string(),
-                        version: None,
-                        package_type: PackageType::Torch,
-                    });
-                }
-                if !torch_names.contains("torchaudio") {
-                    final_packages.push(PackageInfo {
-                        name: "torchaudio".to_string(),
-                        version: None,
-                        package_type: PackageType::Torch,
-                    });
-                }
-            }
-            
-            // Add torch package specs with versions
-            for pkg in &final_packages {
-                cmd.push(pkg.to_string());
-            }
-            
-            self.command_runner.run(&cmd, Some("Installing torch packages"), repo_path)?;
-        }
-        
-        // Install onnx packages with GPU detection and version handling
-        if !plan.onnx_packages.is_empty() {
-            let mut cmd = if uv_available {
-                let mut c = self.get_uv_executable(repo_name);
-                c.extend(["pip".into(), "install".into()]);
-                c
-            } else {
-                let mut c = self.get_pip_executable(repo_name);
-                c.push("install".into());
-                c
-            };
-            
-            // Check if we need --pre flag for nightly builds
-            if self.needs_onnx_nightly() {
-                cmd.push("--pre".into());
-            }
-            
-            // Apply GPU detection to onnx packages and add to command
-            for pkg in &plan.onnx_packages {
-                let onnx_spec = self.apply_onnx_gpu_detection(&pkg.to_string());
-                cmd.push(onnx_spec);
-            }
-            
-            self.command_runner.run(&cmd, Some("Installing ONNX packages"), repo_path)?;
-        }
-        
-        // Handle special packages with custom installation logic
-        if !plan.insightface_packages.is_empty() {
-            self.handle_insightface_package(repo_name, repo_path)?;
-        }
-        
-        // Handle triton packages with platform-specific logic
-        if !plan.triton_packages.is_empty() {
-            let mut cmd = if uv_available {
-                let mut c = self.get_uv_executable(repo_name);
-                c.extend(["pip".into(), "install".into()]);
-                c
-            } else {
-                let mut c = self.get_pip_executable(repo_name);
-                c.push("install".into());
-                c
-            };
-            
-            // Use platform-specific triton package names
-            #[cfg(windows)]
-            cmd.push("triton-windows".into());
-            #[cfg(not(windows))]
-            cmd.push("triton".into());
-            
-            self.command_runner.run(&cmd, Some("Installing Triton packages"), repo_path)?;
+        let mut cmd = if uv_available {
+            let mut c = self.get_uv_executable(repo_name);
+            c.extend(["pip".into(), "install".into()]);
+            c
+        } else {
+            let mut c = self.get_pip_executable(repo_name);
+            c.push("install".into());
+            c
+        };
+        cmd.extend(["--force-reinstall".into(), "-U".into()]);
+        for url in extra_index_urls {
+            cmd.extend(["--extra-index-url".into(), url.clone()]);
         }
-        
-        Ok(())
+        cmd.extend(packages.iter().map(|p| self.offline_requirement(p).to_string()));
+        let label = if uv_available { "Installing insightface (uv)" } else { "Installing insightface (pip)" };
+        self.command_runner.run_verbose(&self.package_command(cmd), Some(label), repo_path)
     }
 
-    /// Handle insightface package installation with Windows wheel support
-    pub fn handle_insightface_package(&self, repo_name: &str, repo_path: Option<&Path>) -> Result<()> {
-        #[cfg(windows)]
-        {
-            let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
-            
-            // Use precompiled wheel for Windows
-            let wheel = "https://huggingface.co/hanamizuki-ai/pypi-wheels/resolve/main/insightface/insightface-0.7.3-cp311-cp311-win_amd64.whl";
-            if uv_available {
-                let mut uv_cmd = self.get_uv_executable(repo_name);
-                uv_cmd.extend([
-                    "pip".into(), 
-                    "install".into(), 
-                    "--force-reinstall".into(),
-                    "-U".into(),
-                    wheel.into(),
-                    "numpy==1.26.4".into()
-                ]);
-                self.command_runner.run_verbose(&uv_cmd, Some("Installing insightface + numpy (uv)"), repo_path)
-            } else {
-                let mut pip_cmd = self.get_pip_executable(repo_name);
-                pip_cmd.extend([
-                    "install".into(), 
-                    "--force-reinstall".into(),
-                    "-U".into(),
-                    wheel.into(),
-                    "numpy==1.26.4".into()
-                ]);
-                self.command_runner.run_verbose(&pip_cmd, Some("Installing insightface + numpy (pip)"), repo_path)
-            }
-        }
-        
-        #[cfg(not(windows))]
-        {
-            let uv_available = self.install_uv_in_venv(repo_name).unwrap_or(false);
-            
-            if uv_available {
-                let mut uv_cmd = self.get_uv_executable(repo_name);
-                uv_cmd.extend([
-                    "pip".into(), 
-                    "install".into(), 
-                    "--force-reinstall".into(),
-                    "-U".into(), 
-                    "insightface".into(), 
-                    "numpy==1.26.4".into()
-                ]);
-                self.command_runner.run_verbose(&uv_cmd, Some("Installing insightface + numpy (uv)"), repo_path)
-            } else {
-                let mut pip_cmd = self.get_pip_executable(repo_name);
-                pip_cmd.extend([
-                    "install".into(), 
-                    "--force-reinstall".into(),
-                    "-U".into(), 
-                    "insightface".into(), 
-                    "numpy==1.26.4".into()
-                ]);
-                self.command_runner.run_verbose(&pip_cmd, Some("Installing insightface + numpy (pip)"), repo_path)
-            }
-        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envs_manager::PortableEnvironmentManager;
+    use crate::installer::executor::{OutputMode, ReplayExecutor, TranscriptEntry};
+    use std::sync::Arc;
+
+    /// Config and environment manager of an installation in `dir`
+    fn install_in(dir: &Path) -> (ConfigManager, PortableEnvironmentManager) {
+        let mut config = ConfigManager::new(Some(dir.join("config.json"))).unwrap();
+        config.set_install_path(dir.to_path_buf()).unwrap();
+        let env_manager = PortableEnvironmentManager::with_config(dir.to_path_buf(), config.clone());
+        (config, env_manager)
+    }
+
+    #[test]
+    fn test_split_requirements_by_exact_name() {
+        let content = "torchmetrics>=1.0\npytorch-lightning\ntorchsde\nopen_clip_torch\nTorch==2.3.1+cu118\nonnxruntime-gpu==1.17.1\nonnxruntime-extensions\n-r extra.txt\ninsightface\n";
+        let linux_cpu = OverrideTarget { platform: "linux".into(), hardware: HardwareProfile::Cpu };
+        let split = PipManager::split_requirements(content, &OverrideRules::default(), &linux_cpu);
+        assert_eq!(split.base, vec!["torchmetrics>=1.0", "pytorch-lightning", "torchsde", "open_clip_torch", "onnxruntime-extensions", "-r extra.txt"]);
+        assert_eq!(split.torch.iter().map(ToString::to_string).collect::<Vec<_>>(), vec!["Torch==2.3.1+cu118"]);
+        assert_eq!(split.onnx[0].specifier.pinned_version(), Some("1.17.1"));
+        assert_eq!(split.insightface.len(), 1);
+
+        // Override rules substitute packages and add companions before the split
+        let overridden = PipManager::split_requirements("numpy<2 # pinned\ntensorflow\ninsightface\n", &OverrideRules::builtin(), &linux_cpu);
+        assert_eq!(overridden.base, vec!["numpy<2", "tensorflow-cpu==2.15.0"]);
+        assert_eq!(overridden.insightface_with.iter().map(ToString::to_string).collect::<Vec<_>>(), vec!["numpy==1.26.4"]);
+        assert_eq!(overridden.overridden.len(), 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (config, env_manager) = install_in(dir.path());
+        let runner = CommandRunner::new(&env_manager);
+        let pip_manager = PipManager::new(&runner, &config);
+        assert_eq!(pip_manager.torch_index_for(&split.torch, None).0, "https://download.pytorch.org/whl/cu118");
+        assert_eq!(pip_manager.onnx_requirement_for(&split.onnx), (split.onnx[0].clone(), true));
+    }
+
+    #[test]
+    fn test_explicit_torch_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, env_manager) = install_in(dir.path());
+        let runner = CommandRunner::new(&env_manager);
+
+        let cuda = PipManager::new(&runner, &config).with_torch_backend(TorchBackend::parse("cu121").unwrap());
+        assert_eq!(cuda.get_default_torch_index_url(), "https://download.pytorch.org/whl/cu121");
+        assert_eq!(cuda.get_onnx_package_spec(), "onnxruntime-gpu");
+        assert_eq!(cuda.hardware_profile(), HardwareProfile::Nvidia { cuda: Some(121) });
+
+        let rocm = PipManager::new(&runner, &config).with_torch_backend(TorchBackend::parse("ROCm6.2").unwrap());
+        assert_eq!(rocm.get_default_torch_index_url(), "https://download.pytorch.org/whl/rocm6.2");
+        assert_eq!(rocm.apply_onnx_gpu_detection("onnxruntime"), "onnxruntime");
+        assert_eq!(rocm.hardware_profile(), HardwareProfile::Amd);
+
+        assert_eq!(TorchBackend::parse("cu117"), None);
+        assert_eq!(TorchBackend::parse("rocm6"), None);
+        assert_eq!(TorchBackend::parse("rocm9.9"), None);
+        assert_eq!(TorchBackend::parse("rocm6.2.4"), Some(TorchBackend::Rocm("6.2.4".into())));
     }
 
+    #[test]
+    fn test_wheelhouse_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, env_manager) = install_in(dir.path());
+        let runner = CommandRunner::new(&env_manager);
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<String>>();
+
+        let offline = PipManager::new(&runner, &config).with_wheelhouse(Wheelhouse::Offline(PathBuf::from("/wh")));
+        assert_eq!(
+            offline.package_command(args("py -m uv pip install --force-reinstall --index-url https://download.pytorch.org/whl/cu128 torch")),
+            args("py -m uv pip install --force-reinstall torch --no-index --find-links /wh")
+        );
+        let wheel = Requirement::parse("insightface @ https://example.com/insightface-0.7.3-cp311-cp311-win_amd64.whl").unwrap();
+        assert_eq!(offline.offline_requirement(&wheel).to_string(), "insightface==0.7.3");
+
+        let download = PipManager::new(&runner, &config).with_wheelhouse(Wheelhouse::Download(PathBuf::from("/wh")));
+        assert_eq!(
+            download.package_command(args("py -m pip install --force-reinstall -U --index-url https://download.pytorch.org/whl/cu128 torch")),
+            args("py -m pip download -d /wh --index-url https://download.pytorch.org/whl/cu128 torch")
+        );
+        assert_eq!(download.offline_requirement(&wheel), wheel);
+    }
+
+    #[test]
+    fn test_install_repo_as_package_falls_back_to_pip() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, env_manager) = install_in(dir.path());
+        let repo_path = dir.path().join("repos").join("demo");
+
+        let python = PipManager::venv_python(dir.path(), "demo");
+        fs::create_dir_all(python.parent().unwrap()).unwrap();
+        fs::write(&python, "").unwrap();
+        let module = |args: &[&str]| std::iter::once(python.to_string_lossy().to_string()).chain(args.iter().map(|s| s.to_string())).collect::<Vec<_>>();
+
+        // uv is missing and cannot be installed, so the package is installed with pip
+        let executor = Arc::new(ReplayExecutor::strict(vec![
+            TranscriptEntry::finished(module(&["-m", "uv", "--version"]), None, OutputMode::Silent).with_exit_code(1),
+            TranscriptEntry::finished(module(&["-m", "pip", "install", "uv"]), None, OutputMode::Captured).with_exit_code(1),
+            TranscriptEntry::finished(module(&["-m", "pip", "install", "."]), Some(&repo_path), OutputMode::Silent),
+        ]));
+        let runner = CommandRunner::with_executor(&env_manager, executor.clone());
+        let pip_manager = PipManager::new(&runner, &config);
+
+        pip_manager.install_repo_as_package("demo", &repo_path).unwrap();
+        assert_eq!(executor.remaining(), 0);
+    }
+
+    #[test]
+    fn test_server_step_reroutes_like_requirements() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, env_manager) = install_in(dir.path());
+        let runner = CommandRunner::new_dry_run(&env_manager);
+        let pip_manager = PipManager::new(&runner, &config);
+        let step = PackagesStep {
+            packages: vec!["numpy".into(), "torch==2.3.1+cu118".into(), "triton==3.1.0".into()],
+            torch_index_url: Some("https://download.pytorch.org/whl/cu121".into()),
+        };
+        pip_manager.handle_pip_install_step("demo", &step, Some(dir.path())).unwrap();
+
+        let actions = runner.planned_actions();
+        let notes: Vec<String> = actions.iter().filter_map(|a| match a {
+            PlannedAction::Note(note) => Some(note.clone()),
+            _ => None,
+        }).collect();
+        assert!(notes.contains(&"Rerouted requirement: torch==2.3.1+cu118 -> https://download.pytorch.org/whl/cu118 (build pinned in requirements)".to_string()));
+        // The server step installs what it lists: no ONNX Runtime, and the user's Triton pin
+        let commands: Vec<String> = actions.iter().filter_map(|a| match a {
+            PlannedAction::Command { args, .. } => Some(args.join(" ")),
+            _ => None,
+        }).collect();
+        assert!(!commands.iter().any(|c| c.contains("onnxruntime")));
+        let triton = if cfg!(windows) { " triton-windows" } else { " triton==3.1.0" };
+        assert!(commands.iter().any(|c| c.ends_with(triton)), "{:?}", commands);
+    }
 }
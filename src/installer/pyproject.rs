@@ -0,0 +1,520 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Dependencies declared by the project itself.
+//!
+//! Reads `pyproject.toml` (PEP 621 `[project]`, PEP 735 `[dependency-groups]`, Poetry and
+//! PDM sections) and `setup.cfg` (`install_requires` / `extras_require`). Everything is
+//! converted to PEP 508 requirement strings, so the result can be written to a
+//! requirements file and installed like any other.
+
+use crate::{Result, PortableSourceError};
+use crate::installer::requirements::{canonicalize_name, Requirement};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml::Value as TomlValue;
+
+/// File the dependencies were read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectFile {
+    Pyproject,
+    SetupCfg,
+}
+
+impl ProjectFile {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ProjectFile::Pyproject => "pyproject.toml",
+            ProjectFile::SetupCfg => "setup.cfg",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectDependencies {
+    pub file: ProjectFile,
+    /// Canonical project name, used to expand self-references like `myproject[gpu]`
+    pub name: Option<String>,
+    /// Always installed
+    pub dependencies: Vec<String>,
+    /// Extras: `[project.optional-dependencies]`, Poetry extras, `extras_require`
+    pub optional: BTreeMap<String, Vec<String>>,
+    /// Dependency groups: PEP 735, Poetry groups, PDM dev-dependencies
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// The repository can be built and installed as a package
+    pub is_package: bool,
+}
+
+impl ProjectDependencies {
+    fn new(file: ProjectFile) -> Self {
+        Self {
+            file,
+            name: None,
+            dependencies: Vec::new(),
+            optional: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            is_package: false,
+        }
+    }
+
+    /// Dependencies from `pyproject.toml` in the repository, if there is one
+    pub fn load_pyproject(repo_path: &Path) -> Result<Option<Self>> {
+        let path = repo_path.join(ProjectFile::Pyproject.file_name());
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| PortableSourceError::repository(format!("Failed to read pyproject.toml: {}", e)))?;
+        let mut project = Self::from_pyproject(&content, repo_path)?;
+        // Without a [build-system] pip falls back to setup.py, if there is one
+        project.is_package = project.is_package || repo_path.join("setup.py").exists();
+        Ok(Some(project))
+    }
+
+    /// Dependencies from `setup.cfg` in the repository, if it declares any
+    pub fn load_setup_cfg(repo_path: &Path) -> Result<Option<Self>> {
+        let path = repo_path.join(ProjectFile::SetupCfg.file_name());
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| PortableSourceError::repository(format!("Failed to read setup.cfg: {}", e)))?;
+        let mut project = Self::from_setup_cfg(&content);
+        if project.dependencies.is_empty() && project.optional.is_empty() {
+            return Ok(None);
+        }
+        project.is_package = repo_path.join("setup.py").exists() || repo_path.join("pyproject.toml").exists();
+        Ok(Some(project))
+    }
+
+    /// Parse `pyproject.toml`. Local path dependencies (Poetry `path = ...`) are resolved against `repo_path`.
+    pub fn from_pyproject(content: &str, repo_path: &Path) -> Result<Self> {
+        let toml: TomlValue = content.parse()
+            .map_err(|e| PortableSourceError::repository(format!("Failed to parse pyproject.toml: {}", e)))?;
+        let mut project = Self::new(ProjectFile::Pyproject);
+        let poetry = toml.get("tool").and_then(|t| t.get("poetry"));
+        let pdm = toml.get("tool").and_then(|t| t.get("pdm"));
+
+        // PEP 621
+        if let Some(pep621) = toml.get("project") {
+            project.name = pep621.get("name").and_then(|n| n.as_str()).map(canonicalize_name);
+            project.dependencies = string_array(pep621.get("dependencies"));
+            if let Some(optional) = pep621.get("optional-dependencies").and_then(|o| o.as_table()) {
+                for (extra, deps) in optional {
+                    project.optional.insert(extra.clone(), string_array(Some(deps)));
+                }
+            }
+        }
+
+        // Poetry: [project] takes precedence when both declare dependencies (Poetry 2)
+        if let Some(poetry) = poetry {
+            if project.name.is_none() {
+                project.name = poetry.get("name").and_then(|n| n.as_str()).map(canonicalize_name);
+            }
+            let mut optional_deps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            if project.dependencies.is_empty() {
+                for (name, value) in poetry_table(poetry.get("dependencies")) {
+                    for (requirement, optional) in poetry_requirements(name, value, repo_path) {
+                        if optional {
+                            optional_deps.entry(canonicalize_name(name)).or_default().push(requirement);
+                        } else {
+                            project.dependencies.push(requirement);
+                        }
+                    }
+                }
+            }
+            // Extras list package names of optional dependencies
+            if let Some(extras) = poetry.get("extras").and_then(|e| e.as_table()) {
+                for (extra, packages) in extras {
+                    let deps = string_array(Some(packages)).iter()
+                        .flat_map(|package| optional_deps.get(&canonicalize_name(package)).cloned().unwrap_or_default())
+                        .collect();
+                    project.optional.entry(extra.clone()).or_insert(deps);
+                }
+            }
+            let mut groups: Vec<(String, &TomlValue)> = Vec::new();
+            if let Some(dev) = poetry.get("dev-dependencies") {
+                groups.push(("dev".into(), dev));
+            }
+            if let Some(poetry_groups) = poetry.get("group").and_then(|g| g.as_table()) {
+                for (group, table) in poetry_groups {
+                    if let Some(deps) = table.get("dependencies") {
+                        groups.push((group.clone(), deps));
+                    }
+                }
+            }
+            for (group, deps) in groups {
+                let requirements = poetry_table(Some(deps)).into_iter()
+                    .flat_map(|(name, value)| poetry_requirements(name, value, repo_path))
+                    .map(|(requirement, _)| requirement);
+                project.groups.entry(group).or_default().extend(requirements);
+            }
+        }
+
+        // PEP 735
+        if let Some(groups) = toml.get("dependency-groups").and_then(|g| g.as_table()) {
+            for group in groups.keys() {
+                let deps = resolve_dependency_group(groups, group, &mut Vec::new());
+                project.groups.entry(group.clone()).or_default().extend(deps);
+            }
+        }
+
+        // PDM keeps development groups in its own section; editable local entries are skipped
+        if let Some(dev) = pdm.and_then(|p| p.get("dev-dependencies")).and_then(|d| d.as_table()) {
+            for (group, deps) in dev {
+                let deps = string_array(Some(deps)).into_iter().filter(|dep| !dep.starts_with("-e"));
+                project.groups.entry(group.clone()).or_default().extend(deps);
+            }
+        }
+
+        let package_mode = poetry.and_then(|p| p.get("package-mode")).and_then(|m| m.as_bool())
+            .or_else(|| toml.get("tool").and_then(|t| t.get("uv")).and_then(|u| u.get("package")).and_then(|p| p.as_bool()))
+            .or_else(|| pdm.and_then(|p| p.get("distribution")).and_then(|d| d.as_bool()));
+        project.is_package = package_mode.unwrap_or_else(|| toml.get("build-system").is_some());
+        Ok(project)
+    }
+
+    /// Parse `install_requires` and `extras_require` from `setup.cfg`
+    pub fn from_setup_cfg(content: &str) -> Self {
+        let mut project = Self::new(ProjectFile::SetupCfg);
+        for (section, key, values) in setup_cfg_entries(content) {
+            match (section.as_str(), key.as_str()) {
+                ("metadata", "name") => project.name = values.first().map(|n| canonicalize_name(n)),
+                ("options", "install_requires") => project.dependencies = values,
+                ("options.extras_require", extra) => {
+                    project.optional.insert(extra.to_string(), values);
+                }
+                _ => {}
+            }
+        }
+        project
+    }
+
+    /// Names that can be passed to `--extras`
+    pub fn available(&self) -> Vec<&str> {
+        self.optional.keys().chain(self.groups.keys()).map(String::as_str).collect()
+    }
+
+    /// Base dependencies plus the selected extras and dependency groups
+    pub fn select(&self, names: &[String]) -> Result<Vec<String>> {
+        let mut selected = self.dependencies.clone();
+        for name in names {
+            // Extra and group names compare normalized (PEP 685 / PEP 735)
+            let key = canonicalize_name(name);
+            let extra = self.optional.iter().find(|(extra, _)| canonicalize_name(extra) == key);
+            let group = self.groups.iter().find(|(group, _)| canonicalize_name(group) == key);
+            if extra.is_none() && group.is_none() {
+                let available = self.available();
+                return Err(PortableSourceError::config(format!(
+                    "{} has no extra or dependency group '{}' (available: {})",
+                    self.file.file_name(),
+                    name,
+                    if available.is_empty() { "none".to_string() } else { available.join(", ") }
+                )));
+            }
+            selected.extend(extra.into_iter().chain(group).flat_map(|(_, deps)| deps.iter().cloned()));
+        }
+
+        let mut expanded = Vec::new();
+        let mut visited = Vec::new();
+        self.expand_self_references(selected, &mut visited, &mut expanded);
+        Ok(expanded)
+    }
+
+    /// Replace `myproject[gpu]` with the dependencies of the `gpu` extra and drop duplicates
+    fn expand_self_references(&self, deps: Vec<String>, visited: &mut Vec<String>, out: &mut Vec<String>) {
+        for dep in deps {
+            let self_extras = match (&self.name, Requirement::parse(&dep)) {
+                (Some(name), Ok(requirement)) if &requirement.canonical_name() == name => Some(requirement.extras),
+                _ => None,
+            };
+            match self_extras {
+                Some(extras) => {
+                    for extra in extras {
+                        let key = canonicalize_name(&extra);
+                        if visited.contains(&key) {
+                            continue;
+                        }
+                        visited.push(key.clone());
+                        if let Some((_, deps)) = self.optional.iter().find(|(name, _)| canonicalize_name(name) == key) {
+                            self.expand_self_references(deps.clone(), visited, out);
+                        }
+                    }
+                }
+                None if !out.contains(&dep) => out.push(dep),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Packages needed to build the repository as a package: `[build-system] requires`,
+/// or setuptools and wheel when pyproject.toml does not say (the pip default)
+pub fn build_requirements(repo_path: &Path) -> Vec<String> {
+    let declared = fs::read_to_string(repo_path.join(ProjectFile::Pyproject.file_name())).ok()
+        .and_then(|content| content.parse::<TomlValue>().ok())
+        .and_then(|toml| toml.get("build-system").map(|b| string_array(b.get("requires"))));
+    declared.unwrap_or_else(|| vec!["setuptools>=40.8.0".to_string(), "wheel".to_string()])
+}
+
+fn string_array(value: Option<&TomlValue>) -> Vec<String> {
+    value.and_then(|v| v.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_str()).map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn poetry_table(value: Option<&TomlValue>) -> Vec<(&String, &TomlValue)> {
+    value.and_then(|v| v.as_table())
+        .map(|table| table.iter().filter(|(name, _)| name.as_str() != "python").collect())
+        .unwrap_or_default()
+}
+
+/// PEP 735 group with its `{include-group = "..."}` entries inlined
+fn resolve_dependency_group(groups: &toml::map::Map<String, TomlValue>, group: &str, stack: &mut Vec<String>) -> Vec<String> {
+    if stack.iter().any(|g| g == group) {
+        return Vec::new();
+    }
+    stack.push(group.to_string());
+    let mut deps = Vec::new();
+    for item in groups.get(group).and_then(|g| g.as_array()).into_iter().flatten() {
+        if let Some(dep) = item.as_str() {
+            deps.push(dep.trim().to_string());
+        } else if let Some(include) = item.get("include-group").and_then(|i| i.as_str()) {
+            deps.extend(resolve_dependency_group(groups, include, stack));
+        }
+    }
+    stack.pop();
+    deps
+}
+
+/// Poetry dependency as PEP 508 requirements, each with its `optional` flag.
+/// A list of constraints (one per platform or Python version) gives several requirements.
+fn poetry_requirements(name: &str, value: &TomlValue, repo_path: &Path) -> Vec<(String, bool)> {
+    match value {
+        TomlValue::String(constraint) => vec![(format!("{}{}", name, poetry_specifier(constraint)), false)],
+        TomlValue::Array(items) => items.iter().flat_map(|item| poetry_requirements(name, item, repo_path)).collect(),
+        TomlValue::Table(table) => {
+            let get = |key: &str| table.get(key).and_then(|v| v.as_str());
+            let extras = string_array(table.get("extras"));
+            let mut requirement = name.to_string();
+            if !extras.is_empty() {
+                requirement.push_str(&format!("[{}]", extras.join(",")));
+            }
+
+            if let Some(git) = get("git") {
+                requirement.push_str(&format!(" @ git+{}", git));
+                if let Some(reference) = get("rev").or(get("tag")).or(get("branch")) {
+                    requirement.push_str(&format!("@{}", reference));
+                }
+                if let Some(subdirectory) = get("subdirectory") {
+                    requirement.push_str(&format!("#subdirectory={}", subdirectory));
+                }
+            } else if let Some(url) = get("url") {
+                requirement.push_str(&format!(" @ {}", url));
+            } else if let Some(path) = get("path") {
+                let path = repo_path.join(path).to_string_lossy().replace('\\', "/");
+                requirement.push_str(&format!(" @ file:///{}", path.trim_start_matches('/')));
+            } else if let Some(version) = get("version") {
+                requirement.push_str(&poetry_specifier(version));
+            }
+
+            let mut markers = Vec::new();
+            if let Some(python) = get("python") {
+                let clauses: Vec<String> = poetry_specifier(python)
+                    .split(',')
+                    .filter(|clause| !clause.is_empty())
+                    .map(|clause| {
+                        let operator_len = clause.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+                        format!("python_version {} \"{}\"", &clause[..operator_len], &clause[operator_len..])
+                    })
+                    .collect();
+                markers.extend(clauses);
+            }
+            if let Some(platform) = get("platform") {
+                markers.push(format!("sys_platform == \"{}\"", platform));
+            }
+            if let Some(marker) = get("markers") {
+                markers.push(if marker.contains(" or ") { format!("({})", marker) } else { marker.to_string() });
+            }
+            if !markers.is_empty() {
+                requirement.push_str(&format!("; {}", markers.join(" and ")));
+            }
+
+            let optional = table.get("optional").and_then(|o| o.as_bool()).unwrap_or(false);
+            vec![(requirement, optional)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Poetry version constraint as a PEP 440 specifier: `^1.2.3` -> `>=1.2.3,<2.0.0`,
+/// `~1.2` -> `>=1.2,<1.3`, `1.2.3` -> `==1.2.3`. Unions (`||`) cannot be expressed and are dropped.
+pub fn poetry_specifier(constraint: &str) -> String {
+    let constraint = constraint.trim();
+    if constraint.is_empty() || constraint == "*" || constraint.contains("||") {
+        return String::new();
+    }
+    constraint
+        .split(',')
+        .map(|clause| clause.trim())
+        .filter(|clause| !clause.is_empty())
+        .map(|clause| {
+            if let Some(version) = clause.strip_prefix('^') {
+                let parts = version_parts(version.trim());
+                let bump = parts.iter().position(|&p| p != 0).unwrap_or(parts.len() - 1);
+                format!(">={},<{}", version.trim(), upper_bound(&parts, bump))
+            } else if clause.starts_with("~=") {
+                clause.replace(' ', "")
+            } else if let Some(version) = clause.strip_prefix('~') {
+                let parts = version_parts(version.trim());
+                let bump = if parts.len() > 1 { 1 } else { 0 };
+                format!(">={},<{}", version.trim(), upper_bound(&parts, bump))
+            } else if let Some(operator) = [">=", "<=", "==", "!=", ">", "<"].iter().find(|op| clause.starts_with(*op)) {
+                format!("{}{}", operator, clause[operator.len()..].trim())
+            } else if let Some(version) = clause.strip_prefix('=') {
+                format!("=={}", version.trim())
+            } else {
+                format!("=={}", clause)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Numeric release segments; pre-release suffixes are ignored (`3b1` -> 3)
+fn version_parts(version: &str) -> Vec<u64> {
+    let parts: Vec<u64> = version
+        .split('.')
+        .map(|part| part.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().unwrap_or(0))
+        .collect();
+    if parts.is_empty() { vec![0] } else { parts }
+}
+
+fn upper_bound(parts: &[u64], bump: usize) -> String {
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| match i.cmp(&bump) {
+            std::cmp::Ordering::Less => part.to_string(),
+            std::cmp::Ordering::Equal => (part + 1).to_string(),
+            std::cmp::Ordering::Greater => "0".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// `(section, key, values)` of an INI file where a value may continue on indented lines
+fn setup_cfg_entries(content: &str) -> Vec<(String, String, Vec<String>)> {
+    let mut entries: Vec<(String, String, Vec<String>)> = Vec::new();
+    let mut section = String::new();
+    let mut in_entry = false;
+    for raw in content.lines() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let continuation = raw.starts_with([' ', '\t']);
+        if continuation && in_entry {
+            if let Some((_, _, values)) = entries.last_mut() {
+                values.push(line.to_string());
+            }
+        } else if line.starts_with('[') && line.ends_with(']') {
+            section = line[1..line.len() - 1].trim().to_string();
+            in_entry = false;
+        } else if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim();
+            let values = if value.is_empty() { Vec::new() } else { vec![value.to_string()] };
+            entries.push((section.clone(), key.trim().to_string(), values));
+            in_entry = true;
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poetry_pyproject() {
+        let content = r#"
+[tool.poetry]
+name = "demo-app"
+package-mode = false
+
+[tool.poetry.dependencies]
+python = "^3.10"
+numpy = "^1.24.2"
+gradio = "~4.1"
+pillow = "10.2.0"
+onnxruntime-gpu = { version = "^0.17", optional = true }
+requests = { version = "*", extras = ["socks"], markers = "sys_platform == 'win32'" }
+insightface = { git = "https://github.com/deepinsight/insightface", tag = "v0.7.3" }
+
+[tool.poetry.extras]
+gpu = ["onnxruntime-gpu"]
+
+[tool.poetry.group.dev.dependencies]
+pytest = ">= 7.0"
+
+[dependency-groups]
+lint = ["ruff"]
+all = [{ include-group = "lint" }, "mypy"]
+"#;
+        let project = ProjectDependencies::from_pyproject(content, Path::new("/repo")).unwrap();
+        assert!(!project.is_package);
+        assert_eq!(project.dependencies, vec![
+            "gradio>=4.1,<4.2",
+            "insightface @ git+https://github.com/deepinsight/insightface@v0.7.3",
+            "numpy>=1.24.2,<2.0.0",
+            "pillow==10.2.0",
+            "requests[socks]; sys_platform == 'win32'",
+        ]);
+        assert_eq!(project.optional["gpu"], vec!["onnxruntime-gpu>=0.17,<0.18"]);
+        assert_eq!(project.groups["dev"], vec!["pytest>=7.0"]);
+        assert_eq!(project.groups["all"], vec!["ruff", "mypy"]);
+
+        let selected = project.select(&["GPU".into(), "all".into()]).unwrap();
+        assert_eq!(selected.len(), 8);
+        assert!(project.select(&["ui".into()]).is_err());
+        assert_eq!(poetry_specifier("^0.0.3"), ">=0.0.3,<0.0.4");
+    }
+
+    #[test]
+    fn test_pep621_and_setup_cfg() {
+        let content = r#"
+[build-system]
+requires = ["setuptools"]
+
+[project]
+name = "Demo_App"
+dependencies = ["numpy"]
+
+[project.optional-dependencies]
+ui = ["gradio"]
+full = ["demo-app[ui]", "numpy"]
+"#;
+        let project = ProjectDependencies::from_pyproject(content, Path::new("/repo")).unwrap();
+        assert!(project.is_package);
+        assert_eq!(project.select(&["full".into()]).unwrap(), vec!["numpy", "gradio"]);
+
+        let setup_cfg = "[metadata]\nname = demo\n\n[options]\ninstall_requires =\n    numpy>=1.20\n    pywin32; sys_platform == \"win32\"\n\n[options.extras_require]\ngpu = onnxruntime-gpu\n";
+        let project = ProjectDependencies::from_setup_cfg(setup_cfg);
+        assert_eq!(project.dependencies, vec!["numpy>=1.20", "pywin32; sys_platform == \"win32\""]);
+        assert_eq!(project.optional["gpu"], vec!["onnxruntime-gpu"]);
+    }
+}
@@ -0,0 +1,645 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Requirement model following PEP 508 and the pip requirements file format.
+//!
+//! A requirements file line is either a requirement (`name[extras] specifiers ; marker`,
+//! `name @ url ; marker` or a bare URL/path) or an option line (`-r`, `-c`, `-e`,
+//! `--index-url`, `--find-links`, ...). Both are written back unchanged in meaning by
+//! their `Display` implementations, so they can be passed straight to pip or uv.
+
+use crate::{Result, PortableSourceError};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Comparison operators in the order they have to be matched (longest first)
+const OPERATORS: &[&str] = &["===", "~=", "==", "!=", "<=", ">=", "<", ">"];
+
+/// Options that are not followed by a value
+const FLAG_OPTIONS: &[&str] = &["--pre", "--prefer-binary", "--no-index", "--require-hashes"];
+
+/// One version clause, e.g. `>=1.2`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Specifier {
+    pub operator: String,
+    pub version: String,
+}
+
+impl fmt::Display for Specifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.operator, self.version)
+    }
+}
+
+/// Comma separated version clauses, e.g. `>=1.2,<2`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpecifierSet(pub Vec<Specifier>);
+
+impl SpecifierSet {
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        // PEP 508 allows the specifiers to be wrapped in parentheses
+        let input = input.strip_prefix('(').and_then(|s| s.strip_suffix(')')).unwrap_or(input);
+        let mut specifiers = Vec::new();
+        for clause in input.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            let operator = OPERATORS.iter().find(|op| clause.starts_with(**op)).ok_or_else(|| {
+                PortableSourceError::installation(format!("Invalid version specifier '{}'", clause))
+            })?;
+            let version = clause[operator.len()..].trim();
+            if version.is_empty() || version.contains(char::is_whitespace) {
+                return Err(PortableSourceError::installation(format!("Invalid version specifier '{}'", clause)));
+            }
+            specifiers.push(Specifier { operator: operator.to_string(), version: version.to_string() });
+        }
+        Ok(Self(specifiers))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Version of an exact `==` pin
+    pub fn pinned_version(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [Specifier { operator, version }] if operator == "==" || operator == "===" => Some(version),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SpecifierSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let clauses: Vec<String> = self.0.iter().map(Specifier::to_string).collect();
+        write!(f, "{}", clauses.join(","))
+    }
+}
+
+/// A single requirement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Requirement {
+    /// Project name as written
+    pub name: String,
+    pub extras: Vec<String>,
+    pub specifier: SpecifierSet,
+    /// Direct reference (`name @ url`, or a bare URL/path)
+    pub url: Option<String>,
+    /// Environment marker without the leading `;`
+    pub marker: Option<String>,
+    /// Per-requirement options such as `--hash=sha256:...`
+    pub options: Vec<RequirementOption>,
+    /// Written as a bare URL or path rather than `name @ url`
+    pub bare: bool,
+}
+
+impl Requirement {
+    /// Requirement on any version of `name`
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            extras: Vec::new(),
+            specifier: SpecifierSet::default(),
+            url: None,
+            marker: None,
+            options: Vec::new(),
+            bare: false,
+        }
+    }
+
+    /// Parse a requirement (a requirements file line without comments or continuations)
+    pub fn parse(input: &str) -> Result<Self> {
+        let invalid = |reason: &str| PortableSourceError::installation(format!("Invalid requirement '{}': {}", input.trim(), reason));
+
+        // Trailing per-requirement options (`pkg==1.0 --hash=sha256:...`)
+        let (body, options) = match input.find(" --") {
+            Some(idx) => (&input[..idx], parse_options(&input[idx..])?),
+            None => (input, Vec::new()),
+        };
+        let body = body.trim();
+        if body.is_empty() {
+            return Err(invalid("empty requirement"));
+        }
+
+        if is_bare_reference(body) {
+            let (url, marker) = split_url_marker(body);
+            let name = name_from_url(url).ok_or_else(|| invalid("cannot determine the project name; use 'name @ url'"))?;
+            let mut requirement = Requirement::new(name);
+            requirement.url = Some(url.to_string());
+            requirement.marker = marker;
+            requirement.options = options;
+            requirement.bare = true;
+            return Ok(requirement);
+        }
+
+        let name_end = body
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+            .unwrap_or(body.len());
+        let name = &body[..name_end];
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            return Err(invalid("missing project name"));
+        }
+        let mut requirement = Requirement::new(name);
+        requirement.options = options;
+        let mut rest = body[name_end..].trim_start();
+
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| invalid("unclosed '['"))?;
+            requirement.extras = after[..end]
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(String::from)
+                .collect();
+            rest = after[end + 1..].trim_start();
+        }
+
+        if let Some(after) = rest.strip_prefix('@') {
+            let (url, marker) = split_url_marker(after.trim_start());
+            if url.is_empty() {
+                return Err(invalid("missing URL after '@'"));
+            }
+            requirement.url = Some(url.to_string());
+            requirement.marker = marker;
+            return Ok(requirement);
+        }
+
+        let (spec, marker) = match rest.split_once(';') {
+            Some((spec, marker)) => (spec, Some(marker.trim().to_string()).filter(|m| !m.is_empty())),
+            None => (rest, None),
+        };
+        requirement.specifier = SpecifierSet::parse(spec).map_err(|e| invalid(&e.to_string()))?;
+        requirement.marker = marker;
+        Ok(requirement)
+    }
+
+    /// Normalized project name (PEP 503): lowercase, runs of `-_.` replaced by `-`
+    pub fn canonical_name(&self) -> String {
+        canonicalize_name(&self.name)
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.url, self.bare) {
+            (Some(url), true) => write!(f, "{}", url)?,
+            _ => {
+                write!(f, "{}", self.name)?;
+                if !self.extras.is_empty() {
+                    write!(f, "[{}]", self.extras.join(","))?;
+                }
+                match &self.url {
+                    Some(url) => write!(f, " @ {}", url)?,
+                    None => write!(f, "{}", self.specifier)?,
+                }
+            }
+        }
+        if let Some(marker) = &self.marker {
+            // A space before ';' is required after a URL and harmless otherwise
+            write!(f, " ; {}", marker)?;
+        }
+        for option in &self.options {
+            write!(f, " {}", option)?;
+        }
+        Ok(())
+    }
+}
+
+/// An option line (`-r file`, `--index-url URL`, `--pre`, ...) or a per-requirement option
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequirementOption {
+    /// Long option name, e.g. `--index-url` (short forms are expanded)
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl RequirementOption {
+    /// Arguments for a `pip install` / `uv pip install` command line
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![self.name.clone()];
+        args.extend(self.value.clone());
+        args
+    }
+}
+
+impl fmt::Display for RequirementOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} {}", self.name, value),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A meaningful line of a requirements file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RequirementLine {
+    Requirement(Requirement),
+    Option(RequirementOption),
+}
+
+impl RequirementLine {
+    /// Parse one logical line; `Ok(None)` for blank lines and comments
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if line.starts_with('-') {
+            let mut options = parse_options(line)?;
+            if options.len() != 1 {
+                return Err(PortableSourceError::installation(format!("Expected a single option in '{}'", line)));
+            }
+            return Ok(Some(RequirementLine::Option(options.remove(0))));
+        }
+        Requirement::parse(line).map(|r| Some(RequirementLine::Requirement(r)))
+    }
+}
+
+impl fmt::Display for RequirementLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequirementLine::Requirement(requirement) => write!(f, "{}", requirement),
+            RequirementLine::Option(option) => write!(f, "{}", option),
+        }
+    }
+}
+
+/// Logical lines of a requirements file: `\` continuations joined, comments and blank lines dropped
+pub fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut logical = String::new();
+    for raw in content.lines() {
+        if let Some(continued) = raw.strip_suffix('\\') {
+            logical.push_str(continued);
+            logical.push(' ');
+            continue;
+        }
+        logical.push_str(raw);
+        let line = strip_comment(&logical).trim();
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+        logical.clear();
+    }
+    let line = strip_comment(&logical).trim();
+    if !line.is_empty() {
+        lines.push(line.to_string());
+    }
+    lines
+}
+
+/// Parse the content of a requirements file
+pub fn parse_requirements(content: &str) -> Result<Vec<RequirementLine>> {
+    let mut lines = Vec::new();
+    for line in logical_lines(content) {
+        lines.extend(RequirementLine::parse(&line)?);
+    }
+    Ok(lines)
+}
+
+/// Normalized project name (PEP 503)
+pub fn canonicalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    let mut separator = false;
+    for c in name.trim().chars() {
+        if matches!(c, '-' | '_' | '.') {
+            separator = true;
+        } else {
+            if separator && !normalized.is_empty() {
+                normalized.push('-');
+            }
+            separator = false;
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+/// `#` starts a comment at the beginning of a line or after whitespace (URLs keep `#egg=`)
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    for (i, b) in bytes.iter().enumerate() {
+        if *b == b'#' && (i == 0 || bytes[i - 1].is_ascii_whitespace()) {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+/// Parse option tokens: `-r file`, `-rfile`, `--index-url=URL`, `--hash=...`, `--pre`
+fn parse_options(input: &str) -> Result<Vec<RequirementOption>> {
+    let mut tokens = input.split_whitespace().peekable();
+    let mut options = Vec::new();
+    while let Some(token) = tokens.next() {
+        let (name, inline_value) = if let Some(long) = token.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((name, value)) => (format!("--{}", name), Some(value.to_string())),
+                None => (token.to_string(), None),
+            }
+        } else if token.starts_with('-') && token.len() >= 2 {
+            let (short, attached) = token.split_at(2);
+            let attached = attached.trim_start_matches('=');
+            (expand_short_option(short)?, Some(attached.to_string()).filter(|v| !v.is_empty()))
+        } else {
+            return Err(PortableSourceError::installation(format!("Unexpected '{}' in options '{}'", token, input.trim())));
+        };
+
+        let value = if inline_value.is_some() || FLAG_OPTIONS.contains(&name.as_str()) {
+            inline_value
+        } else {
+            Some(tokens.next().ok_or_else(|| {
+                PortableSourceError::installation(format!("Option {} requires a value", name))
+            })?.to_string())
+        };
+        options.push(RequirementOption { name, value });
+    }
+    Ok(options)
+}
+
+fn expand_short_option(short: &str) -> Result<String> {
+    let long = match short {
+        "-r" => "--requirement",
+        "-c" => "--constraint",
+        "-e" => "--editable",
+        "-i" => "--index-url",
+        "-f" => "--find-links",
+        _ => return Err(PortableSourceError::installation(format!("Unsupported option '{}'", short))),
+    };
+    Ok(long.to_string())
+}
+
+/// Line is a URL or a local path rather than `name ...`
+fn is_bare_reference(body: &str) -> bool {
+    let first = body.split_whitespace().next().unwrap_or("");
+    first.contains("://")
+        || first.starts_with("git+")
+        || first.starts_with('.')
+        || first.starts_with('/')
+        || first.starts_with('\\')
+        || first.get(1..3) == Some(":\\")
+        || first.ends_with(".whl")
+        || first.ends_with(".tar.gz")
+        || first.ends_with(".zip")
+}
+
+/// Split `url ; marker` (the URL ends at whitespace, as in PEP 508)
+fn split_url_marker(input: &str) -> (&str, Option<String>) {
+    let url_end = input.find(char::is_whitespace).unwrap_or(input.len());
+    let (url, rest) = input.split_at(url_end);
+    let marker = rest.trim().strip_prefix(';').map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    (url.trim_end_matches(';'), marker)
+}
+
+/// Project name of a bare URL: `#egg=name`, or the wheel/sdist file name
+fn name_from_url(url: &str) -> Option<String> {
+    if let Some((_, fragment)) = url.split_once('#') {
+        if let Some(egg) = fragment.split('&').find_map(|part| part.strip_prefix("egg=")) {
+            return Some(egg.to_string()).filter(|n| !n.is_empty());
+        }
+    }
+    let path = url.split(['#', '?']).next().unwrap_or(url).trim_end_matches(['/', '\\']);
+    let file = path.rsplit(['/', '\\']).next()?;
+    if let Some(stem) = file.strip_suffix(".whl") {
+        // name-version(-build)?-python-abi-platform.whl
+        return stem.split('-').next().map(String::from).filter(|n| !n.is_empty());
+    }
+    let stem = file.strip_suffix(".tar.gz").or_else(|| file.strip_suffix(".zip"))?;
+    stem.rsplit_once('-').map(|(name, _)| name.to_string()).filter(|n| !n.is_empty())
+}
+
+/// Files written by PortableSource itself next to the repository requirements
+const GENERATED_FILES: &[&str] = &[
+    "requirements_pyp.txt",
+    "requirements_filtered.txt",
+    "requirements_tmp.txt",
+    "requirements_locked.txt",
+];
+
+/// File name words of requirements that are never installed (dev/test/docs tooling)
+const SKIPPED_WORDS: &[&str] = &["dev", "test", "tests", "testing", "doc", "docs", "lint", "ci"];
+/// File name words of optional extras installed together with the base file
+const OPTIONAL_WORDS: &[&str] = &["optional", "extra", "extras"];
+/// Base file names inside a `requirements/` folder
+const BASE_STEMS: &[&str] = &["requirements", "base", "common", "main", "core"];
+
+/// Hardware a requirements variant (`requirements-cuda.txt`, `requirements-rocm.txt`, ...) is chosen for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HardwareProfile {
+    /// NVIDIA GPU with the CUDA version of the torch build (124 for cu124)
+    Nvidia { cuda: Option<u32> },
+    Amd,
+    Intel,
+    Cpu,
+}
+
+impl HardwareProfile {
+    /// `nvidia`, `amd`, `intel` or `cpu`
+    pub fn vendor(&self) -> &'static str {
+        match self {
+            HardwareProfile::Nvidia { .. } => "nvidia",
+            HardwareProfile::Amd => "amd",
+            HardwareProfile::Intel => "intel",
+            HardwareProfile::Cpu => "cpu",
+        }
+    }
+
+    /// How well a file name word matches this hardware; `None` if it names other hardware,
+    /// a CUDA version newer than the detected one or no hardware at all. Exact CUDA versions
+    /// beat generic words, which beat older versions.
+    fn score(&self, word: &str) -> Option<(u8, i64)> {
+        let cuda_version = word.strip_prefix("cu").and_then(|v| v.parse::<u32>().ok());
+        match self {
+            HardwareProfile::Nvidia { cuda } => match (cuda_version, cuda) {
+                (Some(v), Some(detected)) if v == *detected => Some((3, 0)),
+                // Prefer the newest version the driver supports; newer builds do not run on it
+                (Some(v), Some(detected)) if v < *detected => Some((1, i64::from(v))),
+                (Some(_), Some(_)) => None,
+                (Some(v), None) => Some((1, -i64::from(v))),
+                (None, _) if matches!(word, "cuda" | "gpu" | "nvidia") => Some((2, 0)),
+                _ => None,
+            },
+            HardwareProfile::Amd if word.starts_with("rocm") || word == "amd" => Some((2, 0)),
+            HardwareProfile::Amd | HardwareProfile::Intel if cfg!(windows) && matches!(word, "directml" | "dml") => Some((1, 0)),
+            HardwareProfile::Intel if matches!(word, "intel" | "xpu") => Some((2, 0)),
+            HardwareProfile::Cpu if word == "cpu" => Some((2, 0)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HardwareProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HardwareProfile::Nvidia { cuda: Some(v) } => write!(f, "NVIDIA, cu{}", v),
+            HardwareProfile::Nvidia { cuda: None } => write!(f, "NVIDIA"),
+            HardwareProfile::Amd => write!(f, "AMD"),
+            HardwareProfile::Intel => write!(f, "Intel"),
+            HardwareProfile::Cpu => write!(f, "CPU"),
+        }
+    }
+}
+
+/// Word names some hardware (`cuda`, `cu121`, `rocm6`, `cpu`, ...)
+fn is_hardware_word(word: &str) -> bool {
+    matches!(word, "cuda" | "gpu" | "nvidia" | "amd" | "cpu" | "directml" | "dml" | "intel" | "xpu" | "mps" | "mac" | "macos" | "apple" | "darwin")
+        || word.starts_with("rocm")
+        || word.strip_prefix("cu").is_some_and(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Requirements files of a repository to install, in install order: the base file, the
+/// variant for `hardware`, optional extras, and (only without a base file) any other
+/// `requirements*.txt`. Dev/test/docs files and variants for other hardware are skipped.
+pub fn discover_requirements_files(repo_path: &Path, hardware: &HardwareProfile) -> Vec<PathBuf> {
+    let mut candidates: Vec<(PathBuf, bool)> = Vec::new();
+    for (dir, in_requirements_dir) in [(repo_path.to_path_buf(), false), (repo_path.join("requirements"), true)] {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+            .map(|e| e.path())
+            .filter(|p| {
+                let name = p.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+                name.ends_with(".txt")
+                    && !GENERATED_FILES.contains(&name.as_str())
+                    && (in_requirements_dir || name.starts_with("requirements"))
+            })
+            .collect();
+        files.sort();
+        candidates.extend(files.into_iter().map(|p| (p, in_requirements_dir)));
+    }
+
+    let mut base = Vec::new();
+    let mut optional = Vec::new();
+    let mut other = Vec::new();
+    let mut best_variant: Option<((u8, i64), PathBuf)> = None;
+    for (path, in_requirements_dir) in candidates {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+        let words: Vec<&str> = stem.split(['_', '-', '.']).filter(|w| !w.is_empty() && *w != "requirements").collect();
+        if words.iter().any(|w| SKIPPED_WORDS.contains(w)) {
+            continue;
+        }
+        if words.iter().any(|w| is_hardware_word(w)) {
+            let score = words.iter().filter_map(|w| hardware.score(w)).max();
+            if let Some(score) = score {
+                if best_variant.as_ref().is_none_or(|(best, _)| score > *best) {
+                    best_variant = Some((score, path));
+                }
+            }
+        } else if words.is_empty() || (in_requirements_dir && BASE_STEMS.contains(&stem.as_str())) {
+            base.push(path);
+        } else if words.iter().any(|w| OPTIONAL_WORDS.contains(w)) {
+            optional.push(path);
+        } else {
+            other.push(path);
+        }
+    }
+
+    let mut files = base;
+    let has_base = !files.is_empty();
+    files.extend(best_variant.map(|(_, path)| path));
+    files.extend(optional);
+    if !has_base {
+        files.extend(other);
+    }
+    if files.is_empty() {
+        let fallback = repo_path.join("install").join("requirements.txt");
+        if fallback.exists() {
+            files.push(fallback);
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirement(line: &str) -> Requirement {
+        match RequirementLine::parse(line).unwrap() {
+            Some(RequirementLine::Requirement(r)) => r,
+            other => panic!("expected a requirement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_requirements() {
+        let r = requirement("Pillow[jpeg, tiff] >= 1.2 , <2 ; python_version < '3.12'  # images");
+        assert_eq!(r.canonical_name(), "pillow");
+        assert_eq!(r.extras, vec!["jpeg", "tiff"]);
+        assert_eq!(r.specifier.to_string(), ">=1.2,<2");
+        assert_eq!(r.marker.as_deref(), Some("python_version < '3.12'"));
+        assert_eq!(r.to_string(), "Pillow[jpeg,tiff]>=1.2,<2 ; python_version < '3.12'");
+
+        let r = requirement("my_pkg @ https://example.com/my_pkg-1.0.tar.gz#sha256=abc ; sys_platform == 'win32'");
+        assert_eq!(r.url.as_deref(), Some("https://example.com/my_pkg-1.0.tar.gz#sha256=abc"));
+        assert_eq!(r.marker.as_deref(), Some("sys_platform == 'win32'"));
+
+        let r = requirement("https://host/wheels/insightface-0.7.3-cp311-cp311-win_amd64.whl");
+        assert_eq!((r.name.as_str(), r.bare), ("insightface", true));
+        assert_eq!(r.to_string(), "https://host/wheels/insightface-0.7.3-cp311-cp311-win_amd64.whl");
+        assert_eq!(requirement("git+https://github.com/x/y.git#egg=y_lib").name, "y_lib");
+
+        let r = requirement("torch==2.7.0+cu128 --hash=sha256:abc");
+        assert_eq!(r.specifier.pinned_version(), Some("2.7.0+cu128"));
+        assert_eq!(r.options, vec![RequirementOption { name: "--hash".into(), value: Some("sha256:abc".into()) }]);
+        assert!(Requirement::parse("numpy 1.2").is_err());
+
+        let lines = parse_requirements("-r base.txt\n-i https://pypi.org/simple\n--find-links=./wheels\n--pre\n-e .\nnumpy\\\n  ==1.26.4\n").unwrap();
+        let rendered: Vec<String> = lines.iter().map(ToString::to_string).collect();
+        assert_eq!(rendered, vec![
+            "--requirement base.txt",
+            "--index-url https://pypi.org/simple",
+            "--find-links ./wheels",
+            "--pre",
+            "--editable .",
+            "numpy==1.26.4",
+        ]);
+    }
+
+    #[test]
+    fn test_discover_requirements_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        for name in ["requirements.txt", "requirements_optional.txt", "requirements-cuda.txt", "requirements-cu118.txt",
+                     "requirements-rocm.txt", "requirements-cpu.txt", "requirements-dev.txt", "requirements_pyp.txt"] {
+            std::fs::write(repo.join(name), "").unwrap();
+        }
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files.iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect()
+        };
+
+        let nvidia = discover_requirements_files(repo, &HardwareProfile::Nvidia { cuda: Some(124) });
+        assert_eq!(names(nvidia), vec!["requirements.txt", "requirements-cuda.txt", "requirements_optional.txt"]);
+        let old_cuda = discover_requirements_files(repo, &HardwareProfile::Nvidia { cuda: Some(118) });
+        assert_eq!(names(old_cuda)[1], "requirements-cu118.txt");
+        assert_eq!(names(discover_requirements_files(repo, &HardwareProfile::Amd))[1], "requirements-rocm.txt");
+        assert_eq!(names(discover_requirements_files(repo, &HardwareProfile::Cpu))[1], "requirements-cpu.txt");
+    }
+
+    #[test]
+    fn test_newer_cuda_variant_is_not_selected() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["requirements.txt", "requirements-cu118.txt", "requirements-cu128.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let cuda_121 = HardwareProfile::Nvidia { cuda: Some(121) };
+        assert_eq!(cuda_121.score("cu128"), None);
+        let files = discover_requirements_files(dir.path(), &cuda_121);
+        assert_eq!(files, vec![dir.path().join("requirements.txt"), dir.path().join("requirements-cu118.txt")]);
+
+        std::fs::remove_file(dir.path().join("requirements-cu118.txt")).unwrap();
+        assert_eq!(discover_requirements_files(dir.path(), &cuda_121), vec![dir.path().join("requirements.txt")]);
+    }
+}
@@ -0,0 +1,168 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Snapshot of a repository taken before update-repo, used to roll back a failed update.
+//!
+//! The virtual environment is renamed to `envs/<repo>.rollback` and the checked out
+//! commit/branch is written to `envs/<repo>.rollback.json`. The state file is written
+//! first, so an update interrupted at any point can be rolled back on the next run.
+
+use crate::{Result, PortableSourceError};
+use crate::installer::git_manager::GitManager;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SnapshotState {
+    #[serde(default)]
+    head: Option<String>,
+    /// Branch that was checked out (`None` for a detached HEAD)
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    had_venv: bool,
+}
+
+pub struct UpdateSnapshot {
+    venv_path: PathBuf,
+    snapshot_path: PathBuf,
+    state_path: PathBuf,
+    state: SnapshotState,
+}
+
+impl UpdateSnapshot {
+    fn paths(install_path: &Path, env_name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let envs = install_path.join("envs");
+        (
+            envs.join(env_name),
+            envs.join(format!("{}.rollback", env_name)),
+            envs.join(format!("{}.rollback.json", env_name)),
+        )
+    }
+
+    /// Snapshot left behind by an update that was interrupted (e.g. killed or crashed)
+    pub fn find_interrupted(install_path: &Path, env_name: &str) -> Option<Self> {
+        let (venv_path, snapshot_path, state_path) = Self::paths(install_path, env_name);
+        let content = fs::read_to_string(&state_path).ok()?;
+        let state = serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Invalid rollback state {:?}: {}", state_path, e);
+            SnapshotState { had_venv: snapshot_path.exists(), ..Default::default() }
+        });
+        Some(Self { venv_path, snapshot_path, state_path, state })
+    }
+
+    /// Record the current HEAD and move the environment aside
+    pub fn take(install_path: &Path, env_name: &str, git_manager: &GitManager, repo_path: &Path) -> Result<Self> {
+        let (venv_path, snapshot_path, state_path) = Self::paths(install_path, env_name);
+        if snapshot_path.exists() {
+            fs::remove_dir_all(&snapshot_path)?;
+        }
+
+        let state = SnapshotState {
+            head: git_manager.current_commit(repo_path),
+            branch: git_manager.current_branch(repo_path),
+            had_venv: venv_path.exists(),
+        };
+        if let Some(parent) = state_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&state_path, serde_json::to_string_pretty(&state)?)?;
+
+        if state.had_venv {
+            fs::rename(&venv_path, &snapshot_path).map_err(|e| {
+                let _ = fs::remove_file(&state_path);
+                PortableSourceError::environment(format!(
+                    "Failed to move {:?} aside before updating (is the application still running?): {}", venv_path, e
+                ))
+            })?;
+        }
+        info!("Snapshot taken: HEAD {:?}, environment moved to {:?}", state.head, snapshot_path);
+        Ok(Self { venv_path, snapshot_path, state_path, state })
+    }
+
+    /// Put the previous environment and commit back
+    pub fn restore(self, git_manager: &GitManager, repo_path: &Path) -> Result<()> {
+        if self.state.had_venv && self.snapshot_path.exists() {
+            if self.venv_path.exists() {
+                fs::remove_dir_all(&self.venv_path)?;
+            }
+            fs::rename(&self.snapshot_path, &self.venv_path)?;
+        }
+        if let Some(head) = &self.state.head {
+            git_manager.restore_checkout(repo_path, self.state.branch.as_deref(), head)?;
+        }
+        fs::remove_file(&self.state_path)?;
+        Ok(())
+    }
+
+    /// The update succeeded: drop the snapshot
+    pub fn discard(self) {
+        if self.snapshot_path.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.snapshot_path) {
+                warn!("Failed to remove snapshot {:?}: {}", self.snapshot_path, e);
+            }
+        }
+        let _ = fs::remove_file(&self.state_path);
+    }
+
+    pub fn snapshot_path(&self) -> &Path {
+        &self.snapshot_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigManager;
+    use crate::envs_manager::PortableEnvironmentManager;
+    use crate::installer::command_runer::CommandRunner;
+    use crate::installer::executor::{ReplayExecutor, TranscriptEntry};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshot_is_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ConfigManager::new(Some(dir.path().join("config.json"))).unwrap();
+        let env_manager = PortableEnvironmentManager::with_config(dir.path().to_path_buf(), config);
+        let repo_path = dir.path().join("repos").join("demo");
+        let venv = dir.path().join("envs").join("demo");
+        fs::create_dir_all(&venv).unwrap();
+        fs::write(venv.join("marker"), "old").unwrap();
+
+        let executor = Arc::new(ReplayExecutor::strict(vec![
+            TranscriptEntry::git(&["rev-parse", "HEAD"], &repo_path).with_stdout("386aa945\n"),
+            TranscriptEntry::git(&["symbolic-ref", "--quiet", "--short", "HEAD"], &repo_path).with_stdout("main\n"),
+            TranscriptEntry::git(&["checkout", "main"], &repo_path),
+            TranscriptEntry::git(&["reset", "--keep", "386aa945"], &repo_path),
+        ]));
+        let runner = CommandRunner::with_executor(&env_manager, executor.clone());
+        let git_manager = GitManager::new(&runner, &env_manager);
+
+        let snapshot = UpdateSnapshot::take(dir.path(), "demo", &git_manager, &repo_path).unwrap();
+        assert!(!venv.exists());
+
+        // A half-built environment from the failed update is replaced by the snapshot
+        fs::create_dir_all(&venv).unwrap();
+        assert!(UpdateSnapshot::find_interrupted(dir.path(), "demo").is_some());
+        snapshot.restore(&git_manager, &repo_path).unwrap();
+
+        assert_eq!(fs::read_to_string(venv.join("marker")).unwrap(), "old");
+        assert!(UpdateSnapshot::find_interrupted(dir.path(), "demo").is_none());
+        assert_eq!(executor.remaining(), 0);
+    }
+}
@@ -26,8 +26,8 @@ use crate::config::ConfigManager;
 use crate::Result;
 use log::{info, warn};
 use std::path::{Path, PathBuf};
+#[cfg(unix)]
 use std::fs;
-use std::io::Write;
 
 #[derive(Debug, Clone)]
 pub struct RepositoryInfo {
@@ -98,8 +98,7 @@ impl<'a> ScriptGenerator<'a> {
             .replace("{{LAUNCH_CMD}}", &launch_cmd);
 
         // 5. Write File
-        let mut f = fs::File::create(&bat_file)?;
-        f.write_all(content.as_bytes())?;
+        self.pip_manager.command_runner().write_file(&bat_file, &content)?;
 
         Ok(true)
     }
@@ -179,8 +178,11 @@ set PATH=%cuda_nvvm_lib%;%PATH%"#.to_string()
             .replace("{{LAUNCH_CMD}}", &launch_cmd);
 
         // 4. Write File & Set Permissions
-        let mut f = fs::File::create(&sh_file)?;
-        f.write_all(content.as_bytes())?;
+        let runner = self.pip_manager.command_runner();
+        runner.write_file(&sh_file, &content)?;
+        if runner.is_dry_run() {
+            return Ok(true);
+        }
         let mut perms = fs::metadata(&sh_file)?.permissions();
         perms.set_mode(0o755);
         fs::set_permissions(&sh_file, perms)?;
@@ -17,6 +17,7 @@
 //! Server client for communicating with PortableSource API server.
 
 use crate::Result;
+use crate::installer::install_plan::ServerInstallPlan;
 use log::warn;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -28,6 +29,29 @@ pub struct RepositoryInfo {
     pub program_args: Option<String>,
 }
 
+/// Short repository description returned by search
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RepositorySummary {
+    pub name: String,
+    pub url: Option<String>,
+    pub description: Option<String>,
+}
+
+impl RepositorySummary {
+    /// Accepts both the new (camelCase) and the legacy (snake_case) server formats
+    fn from_json(v: &serde_json::Value) -> Option<Self> {
+        let field = |keys: &[&str]| keys.iter()
+            .find_map(|k| v.get(*k).and_then(|s| s.as_str()))
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        Some(Self {
+            name: field(&["name", "repositoryName"])?,
+            url: field(&["repositoryUrl", "url"]),
+            description: field(&["description"]),
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ServerClient {
     server_url: String,
@@ -129,15 +153,38 @@ impl ServerClient {
         res
     }
 
-    /// Search for repositories by name (optional enhancement)
-    #[allow(dead_code)]
-    pub fn search_repositories(&self, _name: &str) -> Vec<serde_json::Value> {
-        // Optional enhancement: implement when server supports search endpoint
-        Vec::new()
+    /// Search repositories on the server; `None` means the server could not be queried
+    pub fn search_repositories(&self, query: &str) -> Option<Vec<RepositorySummary>> {
+        let base = format!("{}/api/repositories/search", self.server_url);
+        let url = url::Url::parse_with_params(&base, &[("q", query)]).ok()?.to_string();
+        let timeout = self.timeout_secs;
+
+        std::thread::spawn(move || {
+            let resp = reqwest::blocking::Client::new()
+                .get(&url)
+                .timeout(Duration::from_secs(timeout))
+                .send();
+
+            match resp {
+                Ok(r) if r.status().is_success() => {
+                    let v: serde_json::Value = r.json().unwrap_or(serde_json::json!({}));
+                    // Either {"success": true, "repositories": [...]} or a bare array
+                    let items = v.get("repositories")
+                        .and_then(|r| r.as_array())
+                        .or_else(|| v.as_array())?;
+                    Some(items.iter().filter_map(RepositorySummary::from_json).collect())
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("Server error search_repositories: {}", e);
+                    None
+                }
+            }
+        }).join().unwrap_or(None)
     }
 
-    /// Get installation plan for a repository
-    pub fn get_installation_plan(&self, name: &str) -> Result<Option<serde_json::Value>> {
+    /// Get and validate the installation plan for a repository
+    pub fn get_installation_plan(&self, name: &str) -> Result<Option<ServerInstallPlan>> {
         let url = format!("{}/api/repositories/{}/install-plan", self.server_url, name.to_lowercase());
         let timeout = self.timeout_secs;
         
@@ -154,7 +201,7 @@ impl ServerClient {
                         
                         if v.get("success").and_then(|b| b.as_bool()).unwrap_or(false) {
                             if let Some(plan) = v.get("installation_plan") {
-                                return Ok(Some(plan.clone()));
+                                return ServerInstallPlan::from_json(plan).map(Some);
                             }
                         }
                         Ok(None)
@@ -0,0 +1,317 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runtime check of the installed ML stack.
+//!
+//! A successful pip run does not mean the stack works: torch may come as a CPU wheel on a
+//! CUDA machine, or onnxruntime-gpu may fail to load its CUDA libraries. After an install
+//! a small script runs in the environment, imports the key packages and asks torch and
+//! ONNX Runtime what they can use. The result is compared with the builds that were
+//! installed and saved to `.portablesource.verify.json`, where `check-env` finds it.
+
+use crate::{Result, PortableSourceError};
+use crate::installer::command_runer::CommandRunner;
+use crate::installer::manifest::{now_rfc3339, InstalledVariants};
+use crate::installer::requirements::Requirement;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Verification report inside the repository folder
+pub const VERIFY_FILE: &str = ".portablesource.verify.json";
+
+/// Prefix of the report line printed by the script (packages may print to stdout on import)
+const REPORT_PREFIX: &str = "PORTABLESOURCE_VERIFY ";
+
+const VERIFY_SCRIPT: &str = r#"
+import importlib, importlib.util, json, os, sys
+
+def describe(e):
+    return "%s: %s" % (type(e).__name__, e)
+
+report = {"packages": {}}
+for name in ("numpy", "torch", "torchvision", "torchaudio", "onnxruntime", "insightface"):
+    if importlib.util.find_spec(name) is None:
+        continue
+    try:
+        module = importlib.import_module(name)
+        report["packages"][name] = {"version": str(getattr(module, "__version__", "")) or None}
+    except Exception as e:
+        report["packages"][name] = {"error": describe(e)}
+
+torch = sys.modules.get("torch")
+if torch is not None:
+    status = {"cuda_version": torch.version.cuda, "hip_version": getattr(torch.version, "hip", None)}
+    try:
+        status["cuda_available"] = bool(torch.cuda.is_available())
+        if status["cuda_available"]:
+            status["device"] = torch.cuda.get_device_name(0)
+    except Exception as e:
+        status["error"] = describe(e)
+    try:
+        status["xpu_available"] = bool(hasattr(torch, "xpu") and torch.xpu.is_available())
+    except Exception:
+        pass
+    report["torch"] = status
+
+ort = sys.modules.get("onnxruntime")
+if ort is not None:
+    status = {"providers": list(ort.get_available_providers()), "provider_errors": {}}
+    # Providers are listed when compiled in, even if their CUDA/cuDNN libraries cannot be loaded
+    if "CUDAExecutionProvider" in status["providers"]:
+        import ctypes
+        lib = "onnxruntime_providers_cuda.dll" if os.name == "nt" else "libonnxruntime_providers_cuda.so"
+        try:
+            if hasattr(ort, "preload_dlls"):
+                ort.preload_dlls()
+            ctypes.CDLL(os.path.join(os.path.dirname(ort.__file__), "capi", lib))
+        except Exception as e:
+            status["provider_errors"]["CUDAExecutionProvider"] = describe(e)
+    report["onnxruntime"] = status
+
+print("PORTABLESOURCE_VERIFY " + json.dumps(report))
+"#;
+
+/// Import result of one package
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TorchStatus {
+    /// CUDA version torch was built with (`torch.version.cuda`); `None` for CPU and ROCm builds
+    #[serde(default)]
+    pub cuda_version: Option<String>,
+    #[serde(default)]
+    pub hip_version: Option<String>,
+    /// `torch.cuda.is_available()` (also true for a working ROCm build)
+    #[serde(default)]
+    pub cuda_available: bool,
+    #[serde(default)]
+    pub xpu_available: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OnnxStatus {
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// Providers that are listed but whose libraries fail to load
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provider_errors: BTreeMap<String, String>,
+}
+
+/// What the script found in the environment
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeReport {
+    #[serde(default)]
+    pub packages: BTreeMap<String, PackageStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torch: Option<TorchStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub onnxruntime: Option<OnnxStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VerificationProblem {
+    pub message: String,
+    /// What to do about it
+    pub hint: String,
+}
+
+/// Saved verification result of a repository environment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<String>,
+    /// Builds the installer chose (what the environment should contain)
+    #[serde(flatten)]
+    pub expected: InstalledVariants,
+    #[serde(flatten)]
+    pub report: RuntimeReport,
+    #[serde(default)]
+    pub problems: Vec<VerificationProblem>,
+}
+
+impl Verification {
+    /// Run the check script with the environment's Python
+    pub fn run(command_runner: &CommandRunner, python: &Path, name: &str, expected: &InstalledVariants) -> Result<Self> {
+        let args = vec![python.to_string_lossy().to_string(), "-c".to_string(), VERIFY_SCRIPT.to_string()];
+        let output = command_runner.output(&args, None)?;
+        let report = output.stdout.lines().rev()
+            .find_map(|line| line.trim().strip_prefix(REPORT_PREFIX))
+            .ok_or_else(|| PortableSourceError::environment(format!(
+                "Runtime check script failed with {}: {}", output.status_text(), output.stderr.trim()
+            )))?;
+        let report: RuntimeReport = serde_json::from_str(report)?;
+        Ok(Self::evaluate(name, expected.clone(), report))
+    }
+
+    /// Compare the report with the installed builds
+    pub fn evaluate(name: &str, expected: InstalledVariants, report: RuntimeReport) -> Self {
+        let mut problems = Vec::new();
+        let mut problem = |message: String, hint: String| problems.push(VerificationProblem { message, hint });
+        let reinstall = format!("Reinstall the packages: portablesource-rs update-repo {}", name);
+
+        for (package, status) in &report.packages {
+            if let Some(error) = &status.error {
+                problem(format!("import {} fails: {}", package, error), reinstall.clone());
+            }
+        }
+
+        let variant = expected.torch_variant().map(str::to_string);
+        if let (Some(torch), Some(variant)) = (&report.torch, &variant) {
+            let with_backend = format!(
+                "Reinstall torch for this machine: portablesource-rs update-repo {} --torch-backend {}", name, variant
+            );
+            if let Some(cuda) = variant.strip_prefix("cu") {
+                match &torch.cuda_version {
+                    None => problem(format!("torch is a CPU build, expected CUDA build {}", variant), with_backend.clone()),
+                    Some(version) if version.replace('.', "") != cuda => problem(
+                        format!("torch is built for CUDA {}, expected {}", version, variant), with_backend.clone()
+                    ),
+                    Some(version) if !torch.cuda_available => problem(
+                        "torch.cuda.is_available() is False".to_string(),
+                        format!("Check the NVIDIA driver with nvidia-smi; it must support CUDA {}", version),
+                    ),
+                    Some(_) => {}
+                }
+            } else if variant.starts_with("rocm") {
+                if torch.hip_version.is_none() {
+                    problem(format!("torch is not a ROCm build, expected {}", variant), with_backend.clone());
+                } else if !torch.cuda_available {
+                    problem(
+                        "torch does not see the AMD GPU (torch.cuda.is_available() is False)".to_string(),
+                        "Check the ROCm driver with rocminfo and that the user is in the render and video groups".to_string(),
+                    );
+                }
+            } else if variant == "xpu" && !torch.xpu_available {
+                problem(
+                    "torch does not see the Intel GPU (torch.xpu.is_available() is False)".to_string(),
+                    "Install the Intel GPU driver with Level Zero support".to_string(),
+                );
+            }
+        }
+
+        let expected_provider = expected.onnx_package.as_deref()
+            .and_then(|spec| Requirement::parse(spec).ok())
+            .and_then(|requirement| match requirement.canonical_name().as_str() {
+                "onnxruntime-gpu" => Some(("CUDAExecutionProvider", requirement.name)),
+                "onnxruntime-directml" => Some(("DmlExecutionProvider", requirement.name)),
+                "onnxruntime-openvino" => Some(("OpenVINOExecutionProvider", requirement.name)),
+                _ => None,
+            });
+        if let (Some(onnx), Some((provider, package))) = (&report.onnxruntime, expected_provider) {
+            if !onnx.providers.iter().any(|p| p == provider) {
+                problem(
+                    format!("onnxruntime has no {} (providers: {})", provider, onnx.providers.join(", ")),
+                    format!(
+                        "Another onnxruntime package probably shadows {}: uninstall all onnxruntime packages in the environment, then run update-repo {}",
+                        package, name
+                    ),
+                );
+            } else if let Some(error) = onnx.provider_errors.get(provider) {
+                problem(
+                    format!("onnxruntime cannot load {}: {}", provider, error),
+                    "Install the CUDA and cuDNN runtime libraries this onnxruntime-gpu version needs (setup-env installs them)".to_string(),
+                );
+            }
+        }
+
+        Self { name: name.to_string(), checked_at: Some(now_rfc3339()), expected, report, problems }
+    }
+
+    pub fn ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// One line about what works, e.g. "torch 2.7.0+cu128 (CUDA 12.8, NVIDIA RTX 4090); onnxruntime: CUDA, CPU"
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(torch) = &self.report.torch {
+            let version = self.report.packages.get("torch").and_then(|p| p.version.clone()).unwrap_or_default();
+            let build = match (&torch.cuda_version, &torch.hip_version) {
+                (Some(cuda), _) => format!("CUDA {}", cuda),
+                (None, Some(hip)) => format!("ROCm {}", hip),
+                (None, None) if torch.xpu_available => "XPU".to_string(),
+                (None, None) => "CPU".to_string(),
+            };
+            match &torch.device {
+                Some(device) => parts.push(format!("torch {} ({}, {})", version, build, device)),
+                None => parts.push(format!("torch {} ({})", version, build)),
+            }
+        }
+        if let Some(onnx) = &self.report.onnxruntime {
+            let providers: Vec<&str> = onnx.providers.iter().map(|p| p.trim_end_matches("ExecutionProvider")).collect();
+            parts.push(format!("onnxruntime: {}", providers.join(", ")));
+        }
+        if parts.is_empty() {
+            "no torch or onnxruntime in the environment".to_string()
+        } else {
+            parts.join("; ")
+        }
+    }
+
+    pub fn load(repo_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(repo_path.join(VERIFY_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, command_runner: &CommandRunner, repo_path: &Path) -> Result<()> {
+        command_runner.write_file(&repo_path.join(VERIFY_FILE), &serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_cpu_torch_and_shadowed_onnx() {
+        let expected = InstalledVariants {
+            torch_index_url: Some("https://download.pytorch.org/whl/cu128".to_string()),
+            onnx_package: Some("onnxruntime-gpu".to_string()),
+            ..Default::default()
+        };
+        let report: RuntimeReport = serde_json::from_str(r#"{
+            "packages": {"torch": {"version": "2.7.0+cpu"}, "onnxruntime": {"version": "1.22.0"}},
+            "torch": {"cuda_version": null, "hip_version": null, "cuda_available": false},
+            "onnxruntime": {"providers": ["CPUExecutionProvider"]}
+        }"#).unwrap();
+        let verification = Verification::evaluate("demo", expected.clone(), report);
+        assert_eq!(verification.problems.len(), 2);
+        assert!(verification.problems[0].message.contains("CPU build"));
+        assert!(verification.problems[0].hint.contains("--torch-backend cu128"));
+        assert!(verification.problems[1].message.contains("CUDAExecutionProvider"));
+        assert_eq!(verification.summary(), "torch 2.7.0+cpu (CPU); onnxruntime: CPU");
+
+        let report: RuntimeReport = serde_json::from_str(r#"{
+            "packages": {"torch": {"version": "2.7.0+cu128"}},
+            "torch": {"cuda_version": "12.8", "cuda_available": true, "device": "RTX 4090"},
+            "onnxruntime": {"providers": ["CUDAExecutionProvider", "CPUExecutionProvider"]}
+        }"#).unwrap();
+        assert!(Verification::evaluate("demo", expected, report).ok());
+    }
+}
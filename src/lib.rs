@@ -19,13 +19,18 @@
 //! This is a Rust implementation of the PortableSource CLI tool,
 //! originally written in Python.
 
+pub mod archive;
+pub mod cache;
+pub mod catalog;
 pub mod cli;
 pub mod config;
+pub mod downloader;
 pub mod gpu;
 pub mod utils;
 pub mod envs_manager;
 pub mod installer;
 pub mod repository_installer;
+pub mod tools;
 pub mod error;
 
 pub use error::{Result, PortableSourceError};
\ No newline at end of file
@@ -15,12 +15,16 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use portablesource_rs::{
-    cli::{Cli, Commands},
-    config::ConfigManager,
+    cache::PackageCache,
+    catalog::{CatalogEntry, RepositoryCatalog},
+    cli::{CacheAction, CatalogAction, Cli, Commands, LockAction},
+    config::{ConfigManager, TorchBackend, TORCH_CUDA_VERSIONS, TORCH_ROCM_VERSIONS},
     gpu::GpuDetector,
+    installer::{LocalChanges, RepoPin, Verification},
     utils,
     envs_manager::PortableEnvironmentManager,
-    repository_installer::RepositoryInstaller,
+    repository_installer::{InstallOptions, RepositoryInstaller},
+    tools::{InstalledTools, ToolManifest},
     PortableSourceError,
     Result,
 };
@@ -63,15 +67,20 @@ async fn run(cli: Cli) -> Result<()> {
 
     // Initialize configuration manager
     let mut config_manager = ConfigManager::new(None)?;
+    let transcript_options = InstallOptions {
+        record_transcript: cli.record_transcript.clone(),
+        replay_transcript: cli.replay_transcript.clone(),
+        ..Default::default()
+    };
     
     // Handle install path from CLI, registry, config, or default
     // Skip interactive prompt for commands that don't need install_path
     #[cfg(windows)]
-    let needs_install_path = matches!(cli.command, Some(Commands::SetupEnv) | Some(Commands::InstallRepo { .. }) | Some(Commands::UpdateRepo { .. }) | Some(Commands::DeleteRepo { .. }) | Some(Commands::ListRepos) | Some(Commands::CheckEnv) | Some(Commands::Pack { .. }));
+    let needs_install_path = matches!(cli.command, Some(Commands::SetupEnv { .. }) | Some(Commands::InstallRepo { .. }) | Some(Commands::DownloadWheels { .. }) | Some(Commands::FetchTools { .. }) | Some(Commands::UpdateRepo { .. }) | Some(Commands::DeleteRepo { .. }) | Some(Commands::ListRepos { .. }) | Some(Commands::Info { .. }) | Some(Commands::CheckEnv) | Some(Commands::Catalog { .. }) | Some(Commands::Lock { .. }) | Some(Commands::Cache { .. }) | Some(Commands::SearchRepo { .. }) | Some(Commands::Pack { .. }));
     #[cfg(unix)]
-    let needs_install_path = matches!(cli.command, Some(Commands::SetupEnv) | Some(Commands::InstallRepo { .. }) | Some(Commands::UpdateRepo { .. }) | Some(Commands::DeleteRepo { .. }) | Some(Commands::ListRepos) | Some(Commands::ChangePath) | Some(Commands::CheckEnv) | Some(Commands::Uninstall));
+    let needs_install_path = matches!(cli.command, Some(Commands::SetupEnv { .. }) | Some(Commands::InstallRepo { .. }) | Some(Commands::DownloadWheels { .. }) | Some(Commands::FetchTools { .. }) | Some(Commands::UpdateRepo { .. }) | Some(Commands::DeleteRepo { .. }) | Some(Commands::ListRepos { .. }) | Some(Commands::Info { .. }) | Some(Commands::ChangePath) | Some(Commands::CheckEnv) | Some(Commands::Catalog { .. }) | Some(Commands::Lock { .. }) | Some(Commands::Cache { .. }) | Some(Commands::SearchRepo { .. }) | Some(Commands::Uninstall));
     #[cfg(all(not(windows), not(unix)))]
-    let needs_install_path = matches!(cli.command, Some(Commands::SetupEnv) | Some(Commands::InstallRepo { .. }) | Some(Commands::UpdateRepo { .. }) | Some(Commands::DeleteRepo { .. }) | Some(Commands::ListRepos) | Some(Commands::CheckEnv));
+    let needs_install_path = matches!(cli.command, Some(Commands::SetupEnv { .. }) | Some(Commands::InstallRepo { .. }) | Some(Commands::DownloadWheels { .. }) | Some(Commands::FetchTools { .. }) | Some(Commands::UpdateRepo { .. }) | Some(Commands::DeleteRepo { .. }) | Some(Commands::ListRepos { .. }) | Some(Commands::Info { .. }) | Some(Commands::CheckEnv) | Some(Commands::Catalog { .. }) | Some(Commands::Lock { .. }) | Some(Commands::Cache { .. }) | Some(Commands::SearchRepo { .. }));
 
     let install_path = if let Some(cached_path) = SESSION_INSTALL_PATH.get() {
         // Используем сохраненный путь из текущей сессии
@@ -167,7 +176,7 @@ async fn run(cli: Cli) -> Result<()> {
                 validated_path
             } else if !config_manager.get_config().install_path.as_os_str().is_empty() {
                 let existing = config_manager.get_config().install_path.clone();
-                if matches!(cli.command, Some(Commands::SetupEnv)) {
+                if matches!(cli.command, Some(Commands::SetupEnv { .. })) {
                     println!("\nCurrent installation path: {}", existing.display());
                     let chosen = utils::prompt_install_path_linux(&existing)?;
                     let _ = utils::save_install_path_to_registry(&chosen);
@@ -179,7 +188,7 @@ async fn run(cli: Cli) -> Result<()> {
                     validated_path
                 }
             } else {
-                if matches!(cli.command, Some(Commands::SetupEnv)) {
+                if matches!(cli.command, Some(Commands::SetupEnv { .. })) {
                     let default_path = utils::default_install_path_linux();
                     let chosen = utils::prompt_install_path_linux(&default_path)?;
                     let _ = utils::save_install_path_to_registry(&chosen);
@@ -210,12 +219,12 @@ async fn run(cli: Cli) -> Result<()> {
 
     // Linux: выбор режима CLOUD/DESK и базовая подготовка — только когда действительно готовим базу
     #[cfg(unix)]
-    if matches!(cli.command, Some(Commands::SetupEnv)) {
-        use portablesource_rs::utils::{detect_linux_mode, LinuxMode, detect_cuda_version_from_system, setup_micromamba_base_env};
+    if let Some(Commands::SetupEnv { from_dir }) = cli.command.as_ref() {
+        use portablesource_rs::utils::{detect_linux_mode, LinuxMode, setup_micromamba_base_env};
         match detect_linux_mode() {
                         LinuxMode::Cloud => {
                 info!("Linux CLOUD mode detected: using system git/python/cuda");
-                let _cv_for_indexes = detect_cuda_version_from_system();
+                let _cv_for_indexes = utils::detect_cuda_version_from_system();
                 let check = |name: &str| -> bool { utils::is_command_available(name) };
                 let git_ok = check("git");
                 let py_ok = check("python3") || check("python");
@@ -235,33 +244,17 @@ async fn run(cli: Cli) -> Result<()> {
             }
             LinuxMode::Desk => {
                 info!("Linux DESK mode detected: setting up micromamba base env");
-                let cv = match detect_cuda_version_from_system() {
-                    Some(_) => None,
-                    None => {
-                        if config_manager.has_cuda() {
-                            if let Some(cuda_version) = config_manager.get_cuda_version() {
-                                Some(match cuda_version {
-                                    portablesource_rs::config::CudaVersion::Cuda128 => portablesource_rs::config::CudaVersionLinux::Cuda128,
-                                    portablesource_rs::config::CudaVersion::Cuda124 => portablesource_rs::config::CudaVersionLinux::Cuda124,
-                                    portablesource_rs::config::CudaVersion::Cuda118 => portablesource_rs::config::CudaVersionLinux::Cuda118,
-                                })
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    }
-                };
-                setup_micromamba_base_env(&install_path, cv)?;
+                let from_dir = from_dir.as_deref().map(existing_tools_dir).transpose()?;
+                setup_micromamba_base_env(&install_path, base_env_cuda_version(&config_manager), from_dir.as_deref())?;
             }
         }
     }
     
     // Handle commands
     match cli.command.as_ref() {
-        Some(Commands::SetupEnv) => {
-            setup_environment(&install_path, &mut config_manager).await
+        Some(Commands::SetupEnv { from_dir }) => {
+            let from_dir = from_dir.as_deref().map(existing_tools_dir).transpose()?;
+            setup_environment(&install_path, &mut config_manager, from_dir.as_deref()).await
         }
         #[cfg(unix)]
         Some(Commands::SetupReg) => {
@@ -283,21 +276,87 @@ async fn run(cli: Cli) -> Result<()> {
         Some(Commands::ChangePath) => {
             change_installation_path(&mut config_manager).await
         }
-        Some(Commands::InstallRepo { repo, python_ver }) => {
-            install_repository(repo, python_ver.as_deref(), &install_path, &config_manager).await
+        Some(Commands::InstallRepo { repo, python_ver, dry_run, branch, tag, commit, locked, lock_file, requirements, extras, torch_backend, strict, offline: _, wheelhouse }) => {
+            let pin = match (branch, tag, commit) {
+                (Some(b), _, _) => Some(RepoPin::Branch(b.clone())),
+                (_, Some(t), _) => Some(RepoPin::Tag(t.clone())),
+                (_, _, Some(c)) => Some(RepoPin::Commit(c.clone())),
+                _ => None,
+            };
+            let options = InstallOptions {
+                dry_run: *dry_run,
+                pin,
+                locked: *locked || lock_file.is_some(),
+                lock_file: lock_file.clone(),
+                requirements: requirements.clone(),
+                extras: extras.clone(),
+                torch_backend: parse_torch_backend(torch_backend.as_deref())?,
+                offline_wheelhouse: wheelhouse.as_deref().map(existing_wheelhouse).transpose()?,
+                strict: *strict,
+                ..transcript_options
+            };
+            install_repository(repo, python_ver.as_deref(), options, &install_path, &config_manager).await
+        }
+        Some(Commands::UpdateRepo { repo, dry_run, to, force, no_stash, locked, lock_file, requirements, extras, torch_backend, strict }) => {
+            let local_changes = if *force {
+                LocalChanges::Discard
+            } else if *no_stash {
+                LocalChanges::Refuse
+            } else {
+                LocalChanges::Stash
+            };
+            let options = InstallOptions {
+                dry_run: *dry_run,
+                update_to: to.clone(),
+                local_changes,
+                locked: *locked || lock_file.is_some(),
+                lock_file: lock_file.clone(),
+                requirements: requirements.clone(),
+                extras: extras.clone(),
+                torch_backend: parse_torch_backend(torch_backend.as_deref())?,
+                strict: *strict,
+                ..transcript_options
+            };
+            update_repository(repo.clone(), options, &install_path, &config_manager).await
         }
-        Some(Commands::UpdateRepo { repo }) => {
-            update_repository(repo.clone(), &install_path, &config_manager).await
+        Some(Commands::DownloadWheels { repo, wheelhouse }) => {
+            let installer = RepositoryInstaller::new(install_path.clone(), config_manager.clone());
+            let wheelhouse = wheelhouse.as_deref().map(std::path::absolute).transpose()?;
+            let dir = installer.download_wheels(repo, wheelhouse.as_deref()).await?;
+            println!("[PortableSource] Wheelhouse ready: {:?}", dir);
+            println!("[PortableSource] Copy it with the repository to the offline machine and run: install-repo {} --offline --wheelhouse <DIR>", repo);
+            Ok(())
+        }
+        Some(Commands::FetchTools { dir }) => {
+            fetch_tools(dir, &install_path, &config_manager)
         }
         Some(Commands::DeleteRepo { repo }) => {
             delete_repository(repo, &install_path, &config_manager)
         }
-        Some(Commands::ListRepos) => {
-            list_repositories(&install_path, &config_manager)
+        Some(Commands::SearchRepo { query }) => {
+            search_repositories(query, &install_path, &config_manager)
+        }
+        Some(Commands::ShowPlan { repo, json }) => {
+            show_installation_plan(repo, *json, &install_path, &config_manager)
+        }
+        Some(Commands::ListRepos { json }) => {
+            list_repositories(*json, &install_path, &config_manager)
+        }
+        Some(Commands::Info { repo, json }) => {
+            show_repository_info(repo, *json, &install_path, &config_manager)
         }
         Some(Commands::RunRepo { repo, args }) => {
             utils::run_repository(repo, &install_path, args).await
         }
+        Some(Commands::Catalog { action }) => {
+            catalog_command(action, &install_path)
+        }
+        Some(Commands::Lock { action }) => {
+            lock_command(action, &install_path, &config_manager)
+        }
+        Some(Commands::Cache { action }) => {
+            cache_command(action, &install_path)
+        }
         Some(Commands::SystemInfo) => {
             show_system_info(&mut config_manager).await
         }
@@ -336,7 +395,7 @@ async fn run(cli: Cli) -> Result<()> {
     }
 }
 
-async fn setup_environment(install_path: &PathBuf, config_manager: &mut ConfigManager) -> Result<()> {
+async fn setup_environment(install_path: &PathBuf, config_manager: &mut ConfigManager, from_dir: Option<&Path>) -> Result<()> {
     // Create directory structure
     utils::create_directory_structure(install_path)?;
     
@@ -344,7 +403,10 @@ async fn setup_environment(install_path: &PathBuf, config_manager: &mut ConfigMa
     #[cfg(windows)]
     {
         // Initialize environment manager
-        let env_manager = PortableEnvironmentManager::new(install_path.clone());
+        let mut env_manager = PortableEnvironmentManager::new(install_path.clone());
+        if let Some(dir) = from_dir {
+            env_manager = env_manager.with_artifact_dir(dir)?;
+        }
         // Setup environment via portable archives
         env_manager.setup_environment().await?;
     }
@@ -352,27 +414,8 @@ async fn setup_environment(install_path: &PathBuf, config_manager: &mut ConfigMa
     // Linux/macOS: используем системный tar, готовим базу через micromamba
     #[cfg(unix)]
     {
-        use portablesource_rs::utils::{detect_cuda_version_from_system, setup_micromamba_base_env};
-        // Если системная CUDA есть — не ставим CUDA в базу
-        let cv = match detect_cuda_version_from_system() {
-            Some(_) => None,
-            None => {
-                if config_manager.has_cuda() {
-                    if let Some(cuda_version) = config_manager.get_cuda_version() {
-                        Some(match cuda_version {
-                            portablesource_rs::config::CudaVersion::Cuda128 => portablesource_rs::config::CudaVersionLinux::Cuda128,
-                            portablesource_rs::config::CudaVersion::Cuda124 => portablesource_rs::config::CudaVersionLinux::Cuda124,
-                            portablesource_rs::config::CudaVersion::Cuda118 => portablesource_rs::config::CudaVersionLinux::Cuda118,
-                        })
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }
-        };
-        setup_micromamba_base_env(install_path, cv)?;
+        use portablesource_rs::utils::setup_micromamba_base_env;
+        setup_micromamba_base_env(install_path, base_env_cuda_version(config_manager), from_dir)?;
     }
     
     // GPU detection is now handled dynamically by ConfigManager
@@ -415,24 +458,35 @@ async fn change_installation_path(config_manager: &mut ConfigManager) -> Result<
     Ok(())
 }
 
-async fn install_repository(repo: &str, python_ver: Option<&str>, install_path: &PathBuf, config_manager: &ConfigManager) -> Result<()> {
+fn parse_torch_backend(backend: Option<&str>) -> Result<Option<TorchBackend>> {
+    backend.map(|value| TorchBackend::parse(value).ok_or_else(|| {
+        let builds: Vec<String> = TORCH_CUDA_VERSIONS.iter().map(|v| format!("cu{}", v))
+            .chain(TORCH_ROCM_VERSIONS.iter().map(|v| format!("rocm{}", v)))
+            .collect();
+        PortableSourceError::config(format!("Invalid torch backend: {}. Use auto, cpu, {} or xpu", value, builds.join(", ")))
+    })).transpose()
+}
+
+async fn install_repository(repo: &str, python_ver: Option<&str>, mut options: InstallOptions, install_path: &PathBuf, config_manager: &ConfigManager) -> Result<()> {
     let mut installer = RepositoryInstaller::new(install_path.clone(), config_manager.clone());
     
     // Set Python version context if specified
     if let Some(ver_str) = python_ver {
         if let Some(version) = portablesource_rs::config::PythonVersion::from_str(ver_str) {
             info!("Using Python version: {}", version.as_str());
-            // The version will be used during dependency installation
+            options.python_version = Some(version);
         } else {
             return Err(PortableSourceError::config(format!("Invalid Python version: {}. Use 310 or 311", ver_str)));
         }
     }
     
+    installer.set_options(options)?;
     installer.install_repository(repo).await
 }
 
-async fn update_repository(repo: Option<String>, install_path: &PathBuf, config_manager: &ConfigManager) -> Result<()> {
+async fn update_repository(repo: Option<String>, options: InstallOptions, install_path: &PathBuf, config_manager: &ConfigManager) -> Result<()> {
     let mut installer = RepositoryInstaller::new(install_path.clone(), config_manager.clone());
+    installer.set_options(options)?;
     if let Some(name) = repo {
         return installer.update_repository(&name).await;
     }
@@ -470,8 +524,45 @@ fn delete_repository(repo: &str, install_path: &PathBuf, config_manager: &Config
     installer.delete_repository(repo)
 }
 
-fn list_repositories(install_path: &PathBuf, config_manager: &ConfigManager) -> Result<()> {
+fn search_repositories(query: &str, install_path: &Path, config_manager: &ConfigManager) -> Result<()> {
+    let installer = RepositoryInstaller::new(install_path.to_path_buf(), config_manager.clone());
+    let results = installer.search_repositories(query);
+
+    if results.is_empty() {
+        println!("No repositories found for '{}'", query);
+        return Ok(());
+    }
+
+    println!("Repositories matching '{}':", query);
+    for (repo, source) in results {
+        println!("  - {} [{}]", repo.name, source);
+        println!("      url: {}", repo.url.as_deref().unwrap_or("-"));
+        if let Some(desc) = &repo.description {
+            println!("      {}", desc);
+        }
+    }
+    Ok(())
+}
+
+fn show_installation_plan(repo: &str, json: bool, install_path: &Path, config_manager: &ConfigManager) -> Result<()> {
+    let installer = RepositoryInstaller::new(install_path.to_path_buf(), config_manager.clone());
+    match installer.get_installation_plan(repo)? {
+        Some(plan) if json => println!("{}", serde_json::to_string_pretty(&plan)?),
+        Some(plan) => {
+            println!("Installation plan for '{}':", repo);
+            print!("{}", plan);
+        }
+        None => println!("No server installation plan for '{}'; local requirements files will be used", repo),
+    }
+    Ok(())
+}
+
+fn list_repositories(json: bool, install_path: &PathBuf, config_manager: &ConfigManager) -> Result<()> {
     let installer = RepositoryInstaller::new(install_path.clone(), config_manager.clone());
+    if json {
+        println!("{}", serde_json::to_string_pretty(&installer.list_manifests()?)?);
+        return Ok(());
+    }
     let repos = installer.list_repositories()?;
     
     if repos.is_empty() {
@@ -486,6 +577,182 @@ fn list_repositories(install_path: &PathBuf, config_manager: &ConfigManager) ->
     Ok(())
 }
 
+fn show_repository_info(repo: &str, json: bool, install_path: &Path, config_manager: &ConfigManager) -> Result<()> {
+    let installer = RepositoryInstaller::new(install_path.to_path_buf(), config_manager.clone());
+    let manifest = installer.repository_manifest(repo)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&manifest)?);
+        return Ok(());
+    }
+
+    let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    println!("Repository: {}", manifest.name);
+    println!("  source:         {}", manifest.source);
+    println!("  url:            {}", or_dash(&manifest.url));
+    println!("  commit:         {}", or_dash(&manifest.commit));
+    println!("  pinned to:      {}", manifest.pin.as_ref().map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()));
+    println!("  python:         {}", or_dash(&manifest.python_version));
+    println!("  torch:          {}", manifest.variants.torch_variant().unwrap_or("-"));
+    println!("  torch backend:  {}", manifest.torch_backend.as_deref().unwrap_or("auto"));
+    println!("  onnx:           {}", or_dash(&manifest.variants.onnx_package));
+    println!("  main file:      {}", or_dash(&manifest.main_file));
+    println!("  program args:   {}", or_dash(&manifest.program_args));
+    println!("  dependencies:   {}", manifest.plan_source.as_ref().map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()));
+    println!("  installed at:   {}", or_dash(&manifest.installed_at));
+    println!("  updated at:     {}", or_dash(&manifest.updated_at));
+    Ok(())
+}
+
+/// CUDA for the micromamba base environment; none if the system already has CUDA
+#[cfg(unix)]
+fn base_env_cuda_version(config_manager: &ConfigManager) -> Option<portablesource_rs::config::CudaVersionLinux> {
+    use portablesource_rs::config::{CudaVersion, CudaVersionLinux};
+    if utils::detect_cuda_version_from_system().is_some() || !config_manager.has_cuda() {
+        return None;
+    }
+    Some(match config_manager.get_cuda_version()? {
+        CudaVersion::Cuda128 => CudaVersionLinux::Cuda128,
+        CudaVersion::Cuda124 => CudaVersionLinux::Cuda124,
+        CudaVersion::Cuda118 => CudaVersionLinux::Cuda118,
+    })
+}
+
+/// `--from-dir` of setup-env, as an absolute path (tools are referenced by file:// URLs)
+fn existing_tools_dir(dir: &Path) -> Result<PathBuf> {
+    if !dir.is_dir() {
+        return Err(PortableSourceError::config(format!("Tool directory {:?} does not exist", dir)));
+    }
+    Ok(std::path::absolute(dir)?)
+}
+
+fn fetch_tools(dir: &Path, install_path: &Path, config_manager: &ConfigManager) -> Result<()> {
+    let dir = std::path::absolute(dir)?;
+    std::fs::create_dir_all(&dir)?;
+    #[cfg(windows)]
+    {
+        let _ = config_manager;
+        let env_manager = PortableEnvironmentManager::new(install_path.to_path_buf());
+        let fetched = env_manager.fetch_tools(&dir)?;
+        println!("[PortableSource] Fetched {} tool archive(s) into {:?}", fetched.len(), dir);
+    }
+    #[cfg(unix)]
+    {
+        utils::fetch_micromamba_base_env(install_path, &dir, base_env_cuda_version(config_manager))?;
+        println!("[PortableSource] Fetched micromamba and the base environment packages into {:?}", dir);
+    }
+    println!("[PortableSource] Copy it to the offline machine and run: setup-env --from-dir <DIR>");
+    Ok(())
+}
+
+/// `--wheelhouse` of an offline install, as an absolute path (commands run inside the repository)
+fn existing_wheelhouse(dir: &Path) -> Result<PathBuf> {
+    if !dir.is_dir() {
+        return Err(PortableSourceError::config(format!("Wheelhouse {:?} does not exist", dir)));
+    }
+    Ok(std::path::absolute(dir)?)
+}
+
+fn lock_command(action: &LockAction, install_path: &Path, config_manager: &ConfigManager) -> Result<()> {
+    let installer = RepositoryInstaller::new(install_path.to_path_buf(), config_manager.clone());
+    match action {
+        LockAction::Export { repo, output } => {
+            let path = installer.export_lock(repo, output.as_deref())?;
+            println!("Exported lockfile for '{}' to {}", repo, path.display());
+        }
+    }
+    Ok(())
+}
+
+fn cache_command(action: &CacheAction, install_path: &Path) -> Result<()> {
+    let cache = PackageCache::new(install_path);
+    match action {
+        CacheAction::Stats => {
+            let stats = cache.stats()?;
+            println!("Cache: {}", cache.dir().display());
+            println!("  total: {}", utils::format_file_size(stats.total_size()));
+            println!("  uv:    {}", utils::format_file_size(stats.uv_size));
+            println!("  pip:   {}", utils::format_file_size(stats.pip_size));
+            let packages = stats.by_package();
+            if !packages.is_empty() {
+                println!("Packages:");
+            }
+            for (name, size, in_use) in packages {
+                println!("  {:>10}  {}{}", utils::format_file_size(size), name, if in_use { "" } else { " (unused)" });
+            }
+            let unused: u64 = cache.prune(true)?.iter().map(|e| e.size).sum();
+            if unused > 0 {
+                println!("{} can be freed with 'cache prune'", utils::format_file_size(unused));
+            }
+        }
+        CacheAction::Prune { dry_run } => {
+            let removed = cache.prune(*dry_run)?;
+            for entry in &removed {
+                println!("  {} {}-{} ({})", if *dry_run { "would remove" } else { "removed" },
+                    entry.distribution.0, entry.distribution.1, utils::format_file_size(entry.size));
+            }
+            let freed: u64 = removed.iter().map(|e| e.size).sum();
+            if removed.is_empty() {
+                println!("Nothing to prune");
+            } else if *dry_run {
+                println!("{} entries, {} would be freed", removed.len(), utils::format_file_size(freed));
+            } else {
+                println!("Pruned {} entries, freed {}", removed.len(), utils::format_file_size(freed));
+            }
+        }
+        CacheAction::Clear => {
+            let freed = cache.clear()?;
+            println!("Cleared {}, freed {}", cache.dir().display(), utils::format_file_size(freed));
+        }
+    }
+    Ok(())
+}
+
+fn catalog_command(action: &CatalogAction, install_path: &Path) -> Result<()> {
+    let mut catalog = RepositoryCatalog::load(install_path)?;
+    match action {
+        CatalogAction::Add { name, url, main_file, program_args, branch, python_ver, description } => {
+            if let Some(ver) = python_ver {
+                if portablesource_rs::config::PythonVersion::from_str(ver).is_none() {
+                    return Err(PortableSourceError::config(format!("Invalid Python version: {}. Use 310 or 311", ver)));
+                }
+            }
+            let entry = CatalogEntry {
+                url: Some(url.clone()),
+                main_file: main_file.clone(),
+                program_args: program_args.clone(),
+                branch: branch.clone(),
+                python_version: python_ver.clone(),
+                description: description.clone(),
+            };
+            catalog.add_local(name, entry)?;
+            println!("Added '{}' to {}", name.to_lowercase(), catalog.local_path().display());
+        }
+        CatalogAction::Remove { name } => {
+            if catalog.remove_local(name)? {
+                println!("Removed '{}' from {}", name.to_lowercase(), catalog.local_path().display());
+            } else {
+                println!("'{}' is not in the local catalog", name);
+            }
+        }
+        CatalogAction::List => {
+            let items = catalog.list();
+            if items.is_empty() {
+                println!("Catalog is empty");
+            }
+            for (name, entry, source) in items {
+                println!("  - {} [{}]", name, source);
+                if let Some(url) = &entry.url { println!("      url: {}", url); }
+                if let Some(main_file) = &entry.main_file { println!("      main_file: {}", main_file); }
+                if let Some(args) = &entry.program_args { println!("      program_args: {}", args); }
+                if let Some(branch) = &entry.branch { println!("      branch: {}", branch); }
+                if let Some(ver) = &entry.python_version { println!("      python: {}", ver); }
+                if let Some(desc) = &entry.description { println!("      description: {}", desc); }
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn show_system_info(config_manager: &mut ConfigManager) -> Result<()> {
     println!("=== PortableSource System Information ===");
     // Assemble config if empty
@@ -628,6 +895,45 @@ async fn check_environment(install_path: &PathBuf, _config_manager: &ConfigManag
             println!("{}: {}", tool, if available { "Available" } else { "Not found" });
         }
     }
+
+    // Versions recorded by setup-env, compared with the current tool manifest
+    println!("\n=== Installed Tools ===");
+    let installed = InstalledTools::load(&install_path.join("ps_env"));
+    if installed.is_empty() {
+        println!("No tools recorded (run setup-env)");
+    }
+    let manifest = ToolManifest::for_install_path(install_path);
+    for (name, tool) in &installed {
+        let version = tool.version.as_deref().unwrap_or("unknown version");
+        match manifest.tools.get(name).and_then(|entry| entry.version.as_deref()) {
+            Some(wanted) if Some(wanted) != tool.version.as_deref() => println!("{}: {} (manifest: {})", name, version, wanted),
+            _ => println!("{}: {}", name, version),
+        }
+    }
+
+    // Runtime checks saved by install-repo / update-repo
+    println!("\n=== Repositories ===");
+    let mut repos: Vec<PathBuf> = std::fs::read_dir(install_path.join("repos"))
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default();
+    repos.sort();
+    if repos.is_empty() {
+        println!("No repositories installed");
+    }
+    for repo_path in repos {
+        let name = repo_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match Verification::load(&repo_path) {
+            None => println!("{}: not checked (run update-repo {} to check it)", name, name),
+            Some(verification) if verification.ok() => println!("{}: OK - {}", name, verification.summary()),
+            Some(verification) => {
+                println!("{}: {} problem(s) - {}", name, verification.problems.len(), verification.summary());
+                for problem in &verification.problems {
+                    println!("  - {}", problem.message);
+                    println!("    hint: {}", problem.hint);
+                }
+            }
+        }
+    }
     
     Ok(())
 }
@@ -21,27 +21,59 @@
 //! using a modular architecture with specialized components for different tasks.
 
 use crate::{Result, PortableSourceError};
-use crate::config::{ConfigManager, SERVER_DOMAIN};
+use crate::catalog::{self, CatalogEntry, RepositoryCatalog};
+use crate::config::{ConfigManager, PythonVersion, TorchBackend, SERVER_DOMAIN};
 use crate::envs_manager::PortableEnvironmentManager;
 use crate::installer::{
-    CommandRunner, GitManager, PipManager, DependencyInstaller, 
+    CommandRunner, CommandExecutor, PlannedAction, ProcessExecutor, RecordingExecutor, ReplayExecutor, GitManager, LocalChanges, RepoPin, PipManager, DependencyInstaller, 
     ScriptGenerator, RepositoryInfo as GitRepositoryInfo,
-    ScriptRepositoryInfo, ServerClient, MainFileFinder
+    ScriptRepositoryInfo, ServerClient, MainFileFinder, RepositorySummary,
+    ServerInstallPlan, PlanSource, RepoLock, RepoManifest, RepoSource, UpdateSnapshot, Wheelhouse
 };
-use log::info;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
+use crate::installer::lockfile::{LOCK_FILE, SHARED_LOCK_FILE};
+use crate::installer::manifest::now_rfc3339;
+use crate::installer::verification::{Verification, VERIFY_FILE};
+use log::{info, warn};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use url::Url;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct FallbackRepo {
-    pub url: Option<String>,
-    pub main_file: Option<String>,
-    pub program_args: Option<String>,
+/// Options for install-repo / update-repo
+#[derive(Clone, Debug, Default)]
+pub struct InstallOptions {
+    /// Python version for new environments (overrides the catalog entry and the default)
+    pub python_version: Option<PythonVersion>,
+    /// Only print what would be done
+    pub dry_run: bool,
+    /// Branch, tag or commit to pin a new installation to
+    pub pin: Option<RepoPin>,
+    /// Move the pin of an updated repository to this branch, tag or commit
+    pub update_to: Option<String>,
+    /// What to do with local modifications when updating
+    pub local_changes: LocalChanges,
+    /// Write every executed command with its result into this transcript
+    pub record_transcript: Option<PathBuf>,
+    /// Take command results from this transcript instead of running anything
+    pub replay_transcript: Option<PathBuf>,
+    /// Install exactly the package set from the repository lockfile
+    pub locked: bool,
+    /// Lockfile to use with `locked` instead of the one in the repository
+    pub lock_file: Option<PathBuf>,
+    /// Requirements files to install instead of the discovered ones (relative to the repository)
+    pub requirements: Vec<PathBuf>,
+    /// Extras and dependency groups to install from pyproject.toml / setup.cfg
+    pub extras: Vec<String>,
+    /// PyTorch build to install instead of the detected one
+    pub torch_backend: Option<TorchBackend>,
+    /// Install without network access, only from this wheel folder
+    pub offline_wheelhouse: Option<PathBuf>,
+    /// Fail when `pip check` finds dependency conflicts after the install
+    pub strict: bool,
 }
 
+/// Default wheelhouse folder of `download-wheels` inside the install path
+pub const WHEELHOUSE_DIR: &str = "wheelhouse";
+
 /// Main repository installer using modular components
 pub struct RepositoryInstaller {
     install_path: PathBuf,
@@ -49,7 +81,9 @@ pub struct RepositoryInstaller {
     env_manager: PortableEnvironmentManager,
     server_client: ServerClient,
     main_file_finder: MainFileFinder,
-    fallback_repositories: HashMap<String, FallbackRepo>,
+    catalog: RepositoryCatalog,
+    options: InstallOptions,
+    executor: Arc<dyn CommandExecutor>,
 }
 
 impl RepositoryInstaller {
@@ -57,7 +91,10 @@ impl RepositoryInstaller {
         let env_manager = PortableEnvironmentManager::with_config(install_path.clone(), config_manager.clone());
         let server_client = ServerClient::new(format!("https://{}", SERVER_DOMAIN));
         let main_file_finder = MainFileFinder::new(server_client.clone());
-        let fallback_repositories = default_fallback_repositories();
+        let catalog = RepositoryCatalog::load(&install_path).unwrap_or_else(|e| {
+            warn!("Failed to load repository catalog: {}", e);
+            RepositoryCatalog::empty(&install_path)
+        });
         
         // Anchor config to install dir
         config_manager.get_config_mut().install_path = install_path.clone();
@@ -69,14 +106,40 @@ impl RepositoryInstaller {
             env_manager,
             server_client,
             main_file_finder,
-            fallback_repositories,
+            catalog,
+            options: InstallOptions::default(),
+            executor: Arc::new(ProcessExecutor),
         }
     }
+
+    /// Apply install options; opens the transcript to replay, if any
+    pub fn set_options(&mut self, options: InstallOptions) -> Result<()> {
+        self.executor = if let Some(path) = &options.replay_transcript {
+            println!("[PortableSource] Replaying commands from transcript {:?}", path);
+            Arc::new(ReplayExecutor::from_file(path)?)
+        } else if let Some(path) = &options.record_transcript {
+            println!("[PortableSource] Recording commands to transcript {:?}", path);
+            Arc::new(RecordingExecutor::new(ProcessExecutor, path.clone()))
+        } else {
+            Arc::new(ProcessExecutor)
+        };
+        self.options = options;
+        Ok(())
+    }
+
+    /// Use a custom command executor (e.g. a replayed transcript in tests)
+    pub fn set_executor(&mut self, executor: Arc<dyn CommandExecutor>) {
+        self.executor = executor;
+    }
     
     /// Install a repository from URL or name
     pub async fn install_repository(&mut self, repo_url_or_name: &str) -> Result<()> {
         info!("Installing repository: {}", repo_url_or_name);
-        println!("[PortableSource] Installing repository: {}", repo_url_or_name);
+        if self.options.dry_run {
+            println!("[PortableSource] Dry run: planning installation of {}", repo_url_or_name);
+        } else {
+            println!("[PortableSource] Installing repository: {}", repo_url_or_name);
+        }
         
         if self.is_repository_url(repo_url_or_name) {
             self.install_from_url(repo_url_or_name).await
@@ -98,24 +161,83 @@ impl RepositoryInstaller {
         }
 
         // Create modular components for this operation
-        let command_runner = CommandRunner::new(&self.env_manager);
+        let command_runner = self.create_command_runner();
         let git_manager = GitManager::new(&command_runner, &self.env_manager);
 
-        // Use GitManager for update operations
-        git_manager.update_repository(&repo_path)?;
+        // Snapshot the environment and HEAD so a failed update can be rolled back
+        let env_name = repo_name.to_lowercase();
+        let snapshot = if command_runner.is_dry_run() {
+            command_runner.record(PlannedAction::Note(format!(
+                "Previous environment and commit are snapshotted to envs/{}.rollback and restored if the update fails", env_name
+            )));
+            None
+        } else {
+            if let Some(interrupted) = UpdateSnapshot::find_interrupted(&self.install_path, &env_name) {
+                println!("[PortableSource] Restoring the snapshot left by an interrupted update");
+                interrupted.restore(&git_manager, &repo_path)?;
+            }
+            Some(UpdateSnapshot::take(&self.install_path, &env_name, &git_manager, &repo_path)?)
+        };
 
-        // Create components for dependency installation
-        let pip_manager = PipManager::new(&command_runner, &self.config_manager);
-        let dependency_installer = DependencyInstaller::new(
-            &pip_manager,
-            &self.server_client,
-            self.install_path.clone(),
-        );
+        let result = self.run_update(repo_name, &repo_path, &command_runner, &git_manager).await;
+        match (result, snapshot) {
+            (Ok(()), Some(snapshot)) => snapshot.discard(),
+            (Ok(()), None) => self.print_dry_run_report(&command_runner),
+            (Err(e), Some(snapshot)) => {
+                println!("[PortableSource] Update failed: {}", e);
+                println!("[PortableSource] Rolling back to the previous environment and commit...");
+                let kept = snapshot.snapshot_path().to_path_buf();
+                match snapshot.restore(&git_manager, &repo_path) {
+                    Ok(()) => println!("[PortableSource] Rollback complete"),
+                    Err(restore_err) => {
+                        warn!("Rollback failed: {}", restore_err);
+                        println!("[PortableSource] Rollback failed: {}. The previous environment is kept at {:?}", restore_err, kept);
+                    }
+                }
+                return Err(e);
+            }
+            (Err(e), None) => return Err(e),
+        }
+        Ok(())
+    }
 
-        // Reinstall dependencies using DependencyInstaller
-        dependency_installer.install_dependencies(&repo_path).await?;
+    /// Git update, dependency reinstall and manifest update (the part that is rolled back on failure)
+    async fn run_update(&self, repo_name: &str, repo_path: &Path, command_runner: &CommandRunner<'_>, git_manager: &GitManager<'_>) -> Result<()> {
+        let mut manifest = RepoManifest::load(repo_path)
+            .unwrap_or_else(|| RepoManifest::new(repo_name, RepoSource::Server, None));
+
+        // Use GitManager for update operations, following the stored pin unless it is moved
+        if let Some(reference) = &self.options.update_to {
+            let pin = git_manager.move_pin(repo_path, reference, self.options.local_changes)?;
+            match &manifest.pin {
+                Some(old) => println!("[PortableSource] Moved pin from {} to {}", old, pin),
+                None => println!("[PortableSource] Pinned to {}", pin),
+            }
+            manifest.pin = Some(pin);
+        } else {
+            if let Some(pin) = &manifest.pin {
+                println!("[PortableSource] Repository is pinned to {} (use --to <ref> to move it)", pin);
+            }
+            git_manager.update_repository(repo_path, manifest.pin.as_ref(), self.options.local_changes)?;
+        }
+        manifest.updated_at = Some(now_rfc3339());
+        // Requirements files and extras chosen at install time are kept unless new ones are given
+        if !self.options.requirements.is_empty() {
+            manifest.requirements = self.requirements_option();
+        }
+        if !self.options.extras.is_empty() {
+            manifest.extras = self.options.extras.clone();
+        }
+        if let Some(backend) = &self.options.torch_backend {
+            manifest.torch_backend = Self::stored_torch_backend(backend);
+        }
+        let torch_backend = Self::manifest_torch_backend(&manifest)?;
 
-        Ok(())
+        // Reinstall dependencies using DependencyInstaller, on the interpreter the repository was installed with
+        let pip_manager = self.create_pip_manager(command_runner, torch_backend);
+        let python_version = self.options.python_version.clone().or_else(|| manifest.portable_python());
+        let plan_source = self.install_dependencies(repo_path, python_version, &manifest, &pip_manager).await?;
+        self.save_manifest(manifest, repo_path, plan_source, git_manager, &pip_manager)
     }
     
     /// Delete a repository
@@ -146,6 +268,10 @@ impl RepositoryInstaller {
                     format!("Failed to delete environment for '{}': {}", repo_name, e)
                 ))?;
         }
+        // Drop a leftover update snapshot so it is not restored into a reinstall
+        if let Some(snapshot) = UpdateSnapshot::find_interrupted(&self.install_path, &repo_name.to_lowercase()) {
+            snapshot.discard();
+        }
         
         info!("Repository '{}' deleted successfully", repo_name);
         Ok(())
@@ -165,15 +291,7 @@ impl RepositoryInstaller {
             let entry = entry?;
             if entry.file_type()?.is_dir() {
                 if let Some(name) = entry.file_name().to_str() {
-                    let repo_dir = entry.path();
-                    let link_file = repo_dir.join("link.txt");
-                    let suffix = if link_file.exists() {
-                        let link = fs::read_to_string(&link_file).unwrap_or_default();
-                        let link_lower = link.to_lowercase();
-                        if link_lower.contains("github.com") { " [From github]" } else { " [From git]" }
-                    } else {
-                        " [From server]"
-                    };
+                    let suffix = format!(" [{}]", Self::source_label(&entry.path()));
                     repositories.push(format!("{}{}", name, suffix));
                 }
             }
@@ -183,6 +301,41 @@ impl RepositoryInstaller {
         Ok(repositories)
     }
 
+    /// Manifests of all installed repositories, sorted by name
+    pub fn list_manifests(&self) -> Result<Vec<RepoManifest>> {
+        let mut manifests = Vec::new();
+        for name in self.list_repository_names_raw()? {
+            let repo_path = self.install_path.join("repos").join(&name);
+            manifests.push(RepoManifest::load(&repo_path).unwrap_or_else(|| RepoManifest::new(name, RepoSource::Server, None)));
+        }
+        Ok(manifests)
+    }
+
+    /// Manifest of one installed repository
+    pub fn repository_manifest(&self, repo_name: &str) -> Result<RepoManifest> {
+        let repo_path = self.install_path.join("repos").join(repo_name);
+        if !repo_path.exists() {
+            return Err(self.not_installed_error(repo_name));
+        }
+        Ok(RepoManifest::load(&repo_path).unwrap_or_else(|| RepoManifest::new(repo_name, RepoSource::Server, None)))
+    }
+
+    fn source_label(repo_dir: &Path) -> &'static str {
+        RepoManifest::load(repo_dir).map(|m| m.source_label()).unwrap_or("From server")
+    }
+
+    fn not_installed_error(&self, repo_name: &str) -> PortableSourceError {
+        let installed = self.list_repository_names_raw().unwrap_or_default();
+        let suggestions = catalog::suggest_names(repo_name, installed.iter().map(String::as_str), 3);
+        if suggestions.is_empty() {
+            PortableSourceError::repository(format!("Repository '{}' is not installed", repo_name))
+        } else {
+            PortableSourceError::repository(format!(
+                "Repository '{}' is not installed. Did you mean: {}?", repo_name, suggestions.join(", ")
+            ))
+        }
+    }
+
     /// List raw repository folder names (no suffixes)
     pub fn list_repository_names_raw(&self) -> Result<Vec<String>> {
         let repos_path = self.install_path.join("repos");
@@ -209,15 +362,7 @@ impl RepositoryInstaller {
             let entry = entry?;
             if entry.file_type()?.is_dir() {
                 if let Some(name) = entry.file_name().to_str() {
-                    let repo_dir = entry.path();
-                    let link_file = repo_dir.join("link.txt");
-                    let suffix = if link_file.exists() {
-                        let link = fs::read_to_string(&link_file).unwrap_or_default();
-                        let link_lower = link.to_lowercase();
-                        if link_lower.contains("github.com") { " [From github]" } else { " [From git]" }
-                    } else {
-                        " [From server]"
-                    };
+                    let suffix = format!(" [{}]", Self::source_label(&entry.path()));
                     items.push((name.to_string(), format!("{}{}", name, suffix)));
                 }
             }
@@ -226,6 +371,37 @@ impl RepositoryInstaller {
         Ok(items)
     }
     
+    /// Search the server and the local catalogs; returns matches with their source label
+    pub fn search_repositories(&self, query: &str) -> Vec<(RepositorySummary, String)> {
+        let mut results: Vec<(RepositorySummary, String)> = Vec::new();
+
+        // Catalog entries take precedence over server entries with the same name
+        for (name, entry, source) in self.catalog.search(query) {
+            results.push((
+                RepositorySummary { name, url: entry.url, description: entry.description },
+                source.to_string(),
+            ));
+        }
+
+        match self.server_client.search_repositories(query) {
+            Some(server_results) => {
+                for summary in server_results {
+                    if !results.iter().any(|(r, _)| r.name.eq_ignore_ascii_case(&summary.name)) {
+                        results.push((summary, "server".to_string()));
+                    }
+                }
+            }
+            None => warn!("Server search unavailable, showing local catalog matches only"),
+        }
+
+        results
+    }
+
+    /// Fetch and validate the server installation plan for a repository
+    pub fn get_installation_plan(&self, repo_name: &str) -> Result<Option<ServerInstallPlan>> {
+        self.server_client.get_installation_plan(repo_name)
+    }
+
     // Private helper methods
     
     async fn install_from_url(&mut self, repo_url: &str) -> Result<()> {
@@ -237,29 +413,28 @@ impl RepositoryInstaller {
         let repo_path = self.install_path.join("repos").join(&repo_name);
 
         // Create modular components for this operation
-        let command_runner = CommandRunner::new(&self.env_manager);
+        let command_runner = self.create_command_runner();
         let git_manager = GitManager::new(&command_runner, &self.env_manager);
-        let pip_manager = PipManager::new(&command_runner, &self.config_manager);
+        let pip_manager = self.create_pip_manager(&command_runner, self.options.torch_backend.clone().unwrap_or_default());
         
         // Clone or update using GitManager
         let repo_info = GitRepositoryInfo { 
             url: Some(repo_url.to_string()), 
             main_file: None, 
-            program_args: None 
+            program_args: None,
+            pin: self.options.pin.clone(),
         };
-        git_manager.clone_or_update_repository(&repo_info, &repo_path).await?;
+        self.clone_or_update(&git_manager, &repo_info, &repo_path).await?;
 
-        // Create URL marker and link.txt (source)
-        let _ = self.create_url_marker(&repo_path, &repo_name, repo_url);
-        let _ = self.write_link_file(&repo_path, repo_url);
+        let mut manifest = RepoManifest::new(repo_name.clone(), RepoSource::Url, Some(repo_url.to_string()));
+        manifest.pin = repo_info.pin.clone();
+        manifest.installed_at = Some(now_rfc3339());
+        manifest.requirements = self.requirements_option();
+        manifest.extras = self.options.extras.clone();
+        manifest.torch_backend = self.options.torch_backend.as_ref().and_then(Self::stored_torch_backend);
 
         // Install dependencies using DependencyInstaller
-        let dependency_installer = DependencyInstaller::new(
-            &pip_manager,
-            &self.server_client,
-            self.install_path.clone(),
-        );
-        dependency_installer.install_dependencies(&repo_path).await?;
+        let plan_source = self.install_dependencies(&repo_path, self.options.python_version.clone(), &manifest, &pip_manager).await?;
 
         // Generate startup script using ScriptGenerator
         let script_generator = ScriptGenerator::new(
@@ -274,9 +449,17 @@ impl RepositoryInstaller {
             program_args: None,
         };
         script_generator.generate_startup_script(&repo_path, &script_repo_info)?;
+        self.save_manifest(manifest, &repo_path, plan_source, &git_manager, &pip_manager)?;
+
+        if command_runner.is_dry_run() {
+            self.print_dry_run_report(&command_runner);
+            return Ok(());
+        }
 
         // Send stats (non-fatal)
-        let _ = self.server_client.send_download_stats(&repo_name);
+        if self.options.offline_wheelhouse.is_none() {
+            let _ = self.server_client.send_download_stats(&repo_name);
+        }
 
         info!("Repository '{}' installed successfully", repo_name);
         Ok(())
@@ -285,8 +468,20 @@ impl RepositoryInstaller {
     async fn install_from_name(&mut self, repo_name: &str) -> Result<()> {
         info!("Installing from name: {}", repo_name);
         println!("[PortableSource] Resolving repository '{}'", repo_name);
-        let repo_info = self.get_repository_info(repo_name)?
-            .ok_or_else(|| PortableSourceError::repository(format!("Repository '{}' not found", repo_name)))?;
+        let (repo_info, source) = match self.get_repository_info(repo_name)? {
+            Some(found) => found,
+            None => return Err(self.not_found_error(repo_name)),
+        };
+        if repo_info.url.is_none() {
+            return Err(PortableSourceError::repository(format!("Repository '{}' has no URL in the catalog or on the server", repo_name)));
+        }
+        let python_version = match (&self.options.python_version, &repo_info.python_version) {
+            (Some(v), _) => Some(v.clone()),
+            (None, Some(v)) => Some(PythonVersion::from_str(v).ok_or_else(|| {
+                PortableSourceError::config(format!("Invalid Python version '{}' for repository '{}'. Use 310 or 311", v, repo_name))
+            })?),
+            (None, None) => None,
+        };
 
         let name = self.normalize_repo_name(repo_name, &repo_info)?;
         let repo_path = self.install_path.join("repos").join(&name);
@@ -295,25 +490,31 @@ impl RepositoryInstaller {
         println!("[PortableSource] Cloning/Updating repository...");
         
         // Create modular components for this operation
-        let command_runner = CommandRunner::new(&self.env_manager);
+        let command_runner = self.create_command_runner();
         let git_manager = GitManager::new(&command_runner, &self.env_manager);
-        let pip_manager = PipManager::new(&command_runner, &self.config_manager);
+        let pip_manager = self.create_pip_manager(&command_runner, self.options.torch_backend.clone().unwrap_or_default());
         
         // Convert to GitRepositoryInfo
         let git_repo_info = GitRepositoryInfo {
             url: repo_info.url.clone(),
             main_file: repo_info.main_file.clone(),
             program_args: repo_info.program_args.clone(),
+            // An explicit pin wins over the catalog branch
+            pin: self.options.pin.clone().or_else(|| repo_info.branch.clone().map(RepoPin::Branch)),
         };
-        git_manager.clone_or_update_repository(&git_repo_info, &repo_path).await?;
+        self.clone_or_update(&git_manager, &git_repo_info, &repo_path).await?;
+
+        let mut manifest = RepoManifest::new(name.clone(), source, repo_info.url.clone());
+        manifest.pin = git_repo_info.pin.clone();
+        manifest.main_file = repo_info.main_file.clone();
+        manifest.program_args = repo_info.program_args.clone();
+        manifest.installed_at = Some(now_rfc3339());
+        manifest.requirements = self.requirements_option();
+        manifest.extras = self.options.extras.clone();
+        manifest.torch_backend = self.options.torch_backend.as_ref().and_then(Self::stored_torch_backend);
 
         println!("[PortableSource] Installing dependencies...");
-        let dependency_installer = DependencyInstaller::new(
-            &pip_manager,
-            &self.server_client,
-            self.install_path.clone(),
-        );
-        dependency_installer.install_dependencies(&repo_path).await?;
+        let plan_source = self.install_dependencies(&repo_path, python_version, &manifest, &pip_manager).await?;
 
         // Generate startup script using ScriptGenerator
         let script_generator = ScriptGenerator::new(
@@ -328,11 +529,185 @@ impl RepositoryInstaller {
             program_args: repo_info.program_args.clone(),
         };
         script_generator.generate_startup_script(&repo_path, &script_repo_info)?;
+        self.save_manifest(manifest, &repo_path, plan_source, &git_manager, &pip_manager)?;
+
+        if command_runner.is_dry_run() {
+            self.print_dry_run_report(&command_runner);
+            return Ok(());
+        }
 
-        let _ = self.server_client.send_download_stats(&name);
+        if self.options.offline_wheelhouse.is_none() {
+            let _ = self.server_client.send_download_stats(&name);
+        }
         Ok(())
     }
     
+    /// Install dependencies, then check the environment for conflicts left by the separate install passes
+    async fn install_dependencies(&self, repo_path: &Path, python_version: Option<PythonVersion>, manifest: &RepoManifest, pip_manager: &PipManager<'_>) -> Result<PlanSource> {
+        let plan_source = self.install_packages(repo_path, python_version, manifest, pip_manager).await?;
+        self.check_dependencies(&manifest.name, pip_manager)?;
+        Ok(plan_source)
+    }
+
+    /// Install dependencies from the lockfile with `--locked`, otherwise from the server plan or local files
+    /// (using the requirements files and extras recorded in the manifest)
+    async fn install_packages(&self, repo_path: &Path, python_version: Option<PythonVersion>, manifest: &RepoManifest, pip_manager: &PipManager<'_>) -> Result<PlanSource> {
+        let dependency_installer = DependencyInstaller::new(
+            pip_manager,
+            &self.server_client,
+            self.install_path.clone(),
+        );
+        if !self.options.locked {
+            return dependency_installer
+                .with_python_version(python_version)
+                .with_requirements(manifest.requirements.iter().map(PathBuf::from).collect())
+                .with_extras(manifest.extras.clone())
+                .install_dependencies(repo_path)
+                .await;
+        }
+
+        let command_runner = pip_manager.command_runner();
+        let lock_path = match self.options.lock_file.clone().or_else(|| RepoLock::find(repo_path)) {
+            Some(path) => path,
+            None if command_runner.is_dry_run() && !repo_path.exists() => {
+                command_runner.record(PlannedAction::Note(
+                    "Repository is not cloned yet; its lockfile is looked up after cloning".into()
+                ));
+                return Ok(PlanSource::Empty);
+            }
+            None => {
+                return Err(PortableSourceError::installation(format!(
+                    "No lockfile found in {:?}. Install without --locked first, or pass --lock-file", repo_path
+                )));
+            }
+        };
+        let lock = RepoLock::load(&lock_path)?;
+        println!("[PortableSource] Installing {} locked packages from {:?}", lock.packages.len(), lock_path);
+
+        // An explicit --python-ver wins over the interpreter recorded in the lock
+        let python_version = python_version.or_else(|| lock.portable_python());
+        dependency_installer.with_python_version(python_version).install_locked(repo_path, &lock, &lock_path)
+    }
+
+    /// Print the conflicts found by `pip check`; with `--strict` they fail the install
+    fn check_dependencies(&self, repo_name: &str, pip_manager: &PipManager) -> Result<()> {
+        let check = match pip_manager.check_dependencies(&repo_name.to_lowercase()) {
+            Ok(check) => check,
+            Err(e) => {
+                warn!("Dependency check of '{}' failed: {}", repo_name, e);
+                return Ok(());
+            }
+        };
+        if check.is_clean() {
+            if !pip_manager.command_runner().is_dry_run() {
+                println!("[PortableSource] Dependency check: no conflicts");
+            }
+            return Ok(());
+        }
+        println!("[PortableSource] Dependency check found {} conflict(s):", check.count());
+        print!("{}", check);
+        if self.options.strict {
+            return Err(PortableSourceError::installation(format!(
+                "{} dependency conflict(s) in '{}' (--strict)", check.count(), repo_name
+            )));
+        }
+        println!("[PortableSource] Use --strict to fail the install on dependency conflicts");
+        Ok(())
+    }
+
+    /// Offline, an existing checkout is used as is; a missing one is still cloned (e.g. from a local path)
+    async fn clone_or_update(&self, git_manager: &GitManager<'_>, repo_info: &GitRepositoryInfo, repo_path: &Path) -> Result<()> {
+        if self.options.offline_wheelhouse.is_some() && repo_path.join(".git").exists() {
+            println!("[PortableSource] Offline: using the existing checkout {:?}", repo_path);
+            return Ok(());
+        }
+        git_manager.clone_or_update_repository(repo_info, repo_path, self.options.local_changes).await
+    }
+
+    fn create_pip_manager<'r>(&'r self, command_runner: &'r CommandRunner<'r>, torch_backend: TorchBackend) -> PipManager<'r> {
+        let pip_manager = PipManager::new(command_runner, &self.config_manager).with_torch_backend(torch_backend);
+        match &self.options.offline_wheelhouse {
+            Some(dir) => pip_manager.with_wheelhouse(Wheelhouse::Offline(dir.clone())),
+            None => pip_manager,
+        }
+    }
+
+    /// Torch backend remembered in the manifest
+    fn manifest_torch_backend(manifest: &RepoManifest) -> Result<TorchBackend> {
+        match &manifest.torch_backend {
+            Some(stored) => TorchBackend::parse(stored).ok_or_else(|| PortableSourceError::config(format!(
+                "Invalid torch backend '{}' in the manifest of '{}'", stored, manifest.name
+            ))),
+            None => Ok(TorchBackend::Auto),
+        }
+    }
+
+    /// `--torch-backend` as stored in the manifest; auto is the default and is not stored
+    fn stored_torch_backend(backend: &TorchBackend) -> Option<String> {
+        (*backend != TorchBackend::Auto).then(|| backend.to_string())
+    }
+
+    /// `--requirements` files as stored in the manifest
+    fn requirements_option(&self) -> Vec<String> {
+        self.options.requirements.iter()
+            .map(|file| file.to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    fn create_command_runner(&self) -> CommandRunner<'_> {
+        if self.options.dry_run {
+            CommandRunner::new_dry_run(&self.env_manager)
+        } else {
+            CommandRunner::with_executor(&self.env_manager, self.executor.clone())
+        }
+    }
+
+    /// Print actions recorded by a dry-run runner (no-op for a real runner)
+    fn print_dry_run_report(&self, command_runner: &CommandRunner) {
+        if !command_runner.is_dry_run() {
+            return;
+        }
+        let actions = command_runner.planned_actions();
+
+        println!("\n=== Dry run: nothing was executed ===");
+        for (i, action) in actions.iter().enumerate() {
+            println!("{:>3}. {}", i + 1, action);
+        }
+
+        let mut index_urls: Vec<String> = Vec::new();
+        let mut files: Vec<PathBuf> = Vec::new();
+        for action in &actions {
+            match action {
+                PlannedAction::Command { args, .. } => {
+                    for pair in args.windows(2) {
+                        let is_index_flag = matches!(
+                            pair[0].as_str(),
+                            "--index-url" | "-i" | "--extra-index-url" | "--find-links" | "-f"
+                        );
+                        if is_index_flag && !index_urls.contains(&pair[1]) {
+                            index_urls.push(pair[1].clone());
+                        }
+                    }
+                }
+                PlannedAction::WriteFile(path) if !files.contains(path) => files.push(path.clone()),
+                _ => {}
+            }
+        }
+
+        if !index_urls.is_empty() {
+            println!("\nPackage indexes:");
+            for url in &index_urls {
+                println!("  - {}", url);
+            }
+        }
+        if !files.is_empty() {
+            println!("\nFiles that would be created:");
+            for file in &files {
+                println!("  - {}", file.display());
+            }
+        }
+    }
+
     fn is_repository_url(&self, input: &str) -> bool {
         input.starts_with("http://") || input.starts_with("https://") || input.starts_with("git@")
     }
@@ -357,21 +732,45 @@ impl RepositoryInstaller {
         Ok(name.to_string())
     }
 
-    fn get_repository_info(&self, repo_name: &str) -> Result<Option<FallbackRepo>> {
-        // Try server first
-        if let Ok(Some(server_repo)) = self.server_client.get_repository_info(repo_name) {
-            return Ok(Some(FallbackRepo {
+    /// Resolve repository info from the catalogs merged with the server answer
+    fn get_repository_info(&self, repo_name: &str) -> Result<Option<(CatalogEntry, RepoSource)>> {
+        let server_entry = self.server_client.get_repository_info(repo_name)
+            .ok()
+            .flatten()
+            .map(|server_repo| CatalogEntry {
                 url: server_repo.url,
                 main_file: server_repo.main_file,
                 program_args: server_repo.program_args,
-            }));
+                ..Default::default()
+            });
+        // User catalogs override the server; built-in entries are only a fallback for it
+        let source = if self.catalog.is_user_defined(repo_name) || server_entry.is_none() {
+            RepoSource::Catalog
+        } else {
+            RepoSource::Server
+        };
+        Ok(self.catalog.resolve(repo_name, server_entry).map(|entry| (entry, source)))
+    }
+
+    /// "Not found" error with "did you mean" suggestions from the catalogs and server search
+    fn not_found_error(&self, repo_name: &str) -> PortableSourceError {
+        let mut candidates: Vec<String> = self.catalog.list().into_iter().map(|(name, _, _)| name).collect();
+        if let Some(server_results) = self.server_client.search_repositories(repo_name) {
+            candidates.extend(server_results.into_iter().map(|r| r.name));
+        }
+        let suggestions = catalog::suggest_names(repo_name, candidates.iter().map(String::as_str), 3);
+        if suggestions.is_empty() {
+            PortableSourceError::repository(format!("Repository '{}' not found", repo_name))
+        } else {
+            PortableSourceError::repository(format!(
+                "Repository '{}' not found. Did you mean: {}?",
+                repo_name,
+                suggestions.join(", ")
+            ))
         }
-        
-        // Fallback to local list
-        Ok(self.fallback_repositories.get(repo_name).cloned())
     }
 
-    fn normalize_repo_name(&self, input_name: &str, repo_info: &FallbackRepo) -> Result<String> {
+    fn normalize_repo_name(&self, input_name: &str, repo_info: &CatalogEntry) -> Result<String> {
         if let Some(ref url) = repo_info.url {
             if let Ok(parsed_url) = Url::parse(url) {
                 return self.extract_repo_name_from_url(&parsed_url);
@@ -380,33 +779,142 @@ impl RepositoryInstaller {
         Ok(input_name.to_string())
     }
 
-    fn create_url_marker(&self, repo_path: &Path, repo_name: &str, repo_url: &str) -> Result<()> {
-        let marker_file = repo_path.join(".portablesource_url");
-        fs::write(&marker_file, format!("{}={}", repo_name, repo_url))?;
+    /// Fill in what was installed and write the repository manifest
+    fn save_manifest(
+        &self,
+        mut manifest: RepoManifest,
+        repo_path: &Path,
+        plan_source: PlanSource,
+        git_manager: &GitManager,
+        pip_manager: &PipManager,
+    ) -> Result<()> {
+        let command_runner = pip_manager.command_runner();
+        manifest.commit = git_manager.current_commit(repo_path).or(manifest.commit);
+        if manifest.url.is_none() {
+            manifest.url = git_manager.remote_url(repo_path);
+        }
+        manifest.plan_source = Some(plan_source);
+
+        let mut variants = pip_manager.installed_variants();
+        variants.merge_from(&manifest.variants);
+        manifest.variants = variants;
+
+        let python = pip_manager.get_python_in_env(&manifest.name.to_lowercase());
+        let version_cmd = vec![python.to_string_lossy().to_string(), "--version".to_string()];
+        if let Ok(output) = command_runner.output(&version_cmd, None) {
+            // "Python 3.11.9" (older interpreters print it to stderr)
+            let text = format!("{}{}", output.stdout, output.stderr);
+            if let Some(version) = text.split_whitespace().nth(1).filter(|_| output.success()) {
+                manifest.python_version = Some(version.to_string());
+            }
+        }
+
+        manifest.save(command_runner, repo_path)?;
+
+        // The lock is a convenience on top of the install; failing to write it is not fatal
+        if !command_runner.is_dry_run() {
+            if let Err(e) = Self::write_lock(&manifest, repo_path, pip_manager) {
+                warn!("Failed to write lockfile for '{}': {}", manifest.name, e);
+            }
+        }
+
+        if command_runner.is_dry_run() {
+            command_runner.record(PlannedAction::Note(format!(
+                "The installed torch/onnxruntime builds are checked in the environment and saved to {}", VERIFY_FILE
+            )));
+        } else {
+            Self::verify_runtime(&manifest, repo_path, pip_manager);
+        }
         Ok(())
     }
 
-    fn write_link_file(&self, repo_path: &Path, repo_url: &str) -> Result<()> {
-        let link_file = repo_path.join("link.txt");
-        fs::write(&link_file, repo_url)?;
-        Ok(())
+    /// Check that torch and onnxruntime actually work with the installed builds. Problems are
+    /// reported with hints and saved for check-env, but do not fail the install.
+    fn verify_runtime(manifest: &RepoManifest, repo_path: &Path, pip_manager: &PipManager) {
+        let command_runner = pip_manager.command_runner();
+        let python = pip_manager.get_python_in_env(&manifest.name.to_lowercase());
+        let verification = match Verification::run(command_runner, &python, &manifest.name, &manifest.variants) {
+            Ok(verification) => verification,
+            Err(e) => {
+                warn!("Runtime check of '{}' failed: {}", manifest.name, e);
+                return;
+            }
+        };
+        if verification.ok() {
+            println!("[PortableSource] Runtime check passed: {}", verification.summary());
+        } else {
+            println!("[PortableSource] Runtime check found problems ({}):", verification.summary());
+            for problem in &verification.problems {
+                println!("  - {}", problem.message);
+                println!("    hint: {}", problem.hint);
+            }
+            println!("[PortableSource] The report is kept for check-env");
+        }
+        if let Err(e) = verification.save(command_runner, repo_path) {
+            warn!("Failed to save runtime check of '{}': {}", manifest.name, e);
+        }
     }
-}
 
-fn default_fallback_repositories() -> HashMap<String, FallbackRepo> {
-    let mut repos = HashMap::new();
-    
-    repos.insert("stable-diffusion-webui".to_string(), FallbackRepo {
-        url: Some("https://github.com/AUTOMATIC1111/stable-diffusion-webui.git".to_string()),
-        main_file: Some("webui.py".to_string()),
-        program_args: None,
-    });
-    
-    repos.insert("comfyui".to_string(), FallbackRepo {
-        url: Some("https://github.com/comfyanonymous/ComfyUI.git".to_string()),
-        main_file: Some("main.py".to_string()),
-        program_args: None,
-    });
-    
-    repos
-}
\ No newline at end of file
+    /// Record the exact installed package set in the repository lockfile
+    fn write_lock(manifest: &RepoManifest, repo_path: &Path, pip_manager: &PipManager) -> Result<()> {
+        let freeze = pip_manager.freeze(&manifest.name.to_lowercase())?;
+        let mut lock = RepoLock::from_freeze(manifest.name.clone(), &freeze);
+        lock.commit = manifest.commit.clone();
+        lock.python_version = manifest.python_version.clone();
+        lock.torch_index_url = manifest.variants.torch_index_url.clone();
+        lock.extra_index_urls = manifest.variants.extra_index_urls.clone();
+        lock.generated_at = Some(now_rfc3339());
+        lock.save(pip_manager.command_runner(), &repo_path.join(LOCK_FILE))
+    }
+
+    /// Download everything an installed repository needs on this machine (its GPU, CUDA build,
+    /// torch backend, requirements files and extras) into a wheelhouse for `install-repo --offline`.
+    /// The repository environment decides the Python version and platform of the wheels.
+    pub async fn download_wheels(&self, repo_name: &str, wheelhouse: Option<&Path>) -> Result<PathBuf> {
+        let repo_path = self.install_path.join("repos").join(repo_name);
+        if !repo_path.exists() {
+            return Err(self.not_installed_error(repo_name));
+        }
+        if !self.install_path.join("envs").join(repo_name.to_lowercase()).exists() {
+            return Err(PortableSourceError::environment(format!(
+                "Environment of '{}' not found; reinstall the repository first", repo_name
+            )));
+        }
+        let manifest = self.repository_manifest(repo_name)?;
+        let dir = wheelhouse.map(Path::to_path_buf)
+            .unwrap_or_else(|| self.install_path.join(WHEELHOUSE_DIR).join(repo_name));
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| PortableSourceError::installation(format!("Failed to create wheelhouse {:?}: {}", dir, e)))?;
+
+        let command_runner = self.create_command_runner();
+        let pip_manager = PipManager::new(&command_runner, &self.config_manager)
+            .with_torch_backend(Self::manifest_torch_backend(&manifest)?)
+            .with_wheelhouse(Wheelhouse::Download(dir.clone()));
+        println!("[PortableSource] Downloading packages of '{}' for {} into {:?}", repo_name, pip_manager.hardware_profile(), dir);
+        DependencyInstaller::new(&pip_manager, &self.server_client, self.install_path.clone())
+            .with_requirements(manifest.requirements.iter().map(PathBuf::from).collect())
+            .with_extras(manifest.extras.clone())
+            .install_dependencies(&repo_path)
+            .await?;
+        Ok(dir)
+    }
+
+    /// Write the lockfile of an installed repository to `output`, or to the shared
+    /// lockfile in the repository folder so it can be committed
+    pub fn export_lock(&self, repo_name: &str, output: Option<&Path>) -> Result<PathBuf> {
+        let repo_path = self.install_path.join("repos").join(repo_name);
+        if !repo_path.exists() {
+            return Err(self.not_installed_error(repo_name));
+        }
+        let local_lock = repo_path.join(LOCK_FILE);
+        if !local_lock.exists() {
+            return Err(PortableSourceError::repository(format!(
+                "Repository '{}' has no lockfile yet; reinstall or update it to create one", repo_name
+            )));
+        }
+        let lock = RepoLock::load(&local_lock)?;
+        let target = output.map(Path::to_path_buf).unwrap_or_else(|| repo_path.join(SHARED_LOCK_FILE));
+        std::fs::write(&target, serde_json::to_string_pretty(&lock)?)?;
+        Ok(target)
+    }
+}
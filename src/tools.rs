@@ -0,0 +1,475 @@
+// portablesource
+// Copyright (C) 2025  PortableSource / NeuroDonu
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tool manifest for PortableSource
+//!
+//! Describes the portable tools (Python, git, ffmpeg, CUDA, micromamba) with their
+//! versions, download URLs and layout in `ps_env`. Layers are merged field by field,
+//! highest precedence first, except that a tool's version, URLs and sha256 describe
+//! one release and are taken together from the highest layer that sets any of them:
+//!
+//! 1. the local manifest (`<install_path>/tools.toml`);
+//! 2. the file named by `PORTABLESOURCE_TOOLS`, e.g. a team manifest pointing at an
+//!    internal mirror;
+//! 3. the manifest built into the binary (`src/tools.toml`).
+//!
+//! Installed tools are recorded in `ps_env/tools.json` so `check-env` can show
+//! their versions. `fetch-tools` writes a layer of its own next to the archives, with
+//! the sha256 of each fetched file, which `setup-env --from-dir` lays over the others.
+
+use crate::downloader::{file_url, sha256_file, Artifact, Downloader};
+use crate::{Result, PortableSourceError};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with an extra manifest file
+pub const TOOLS_MANIFEST_ENV: &str = "PORTABLESOURCE_TOOLS";
+/// Newest manifest layout this version understands
+pub const MANIFEST_VERSION: u32 = 1;
+/// Record of installed tools inside `ps_env`
+pub const INSTALLED_TOOLS_FILE: &str = "tools.json";
+/// Manifest layer `fetch-tools` writes into its directory
+pub const FETCHED_MANIFEST_FILE: &str = "tools.toml";
+
+const BUILTIN_MANIFEST: &str = include_str!("tools.toml");
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "tar.xz")]
+    TarXz,
+    #[serde(rename = "zip")]
+    Zip,
+    /// A single executable, stored as is
+    #[serde(rename = "binary")]
+    Binary,
+}
+
+impl ArchiveFormat {
+    /// Extension of the downloaded file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarXz => "tar.xz",
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Binary => "bin",
+        }
+    }
+}
+
+/// Build of a tool for one OS
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolBuild {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urls: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<ArchiveFormat>,
+    /// Folder the archive creates in ps_env
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract_path: Option<String>,
+    /// Executable relative to ps_env
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+}
+
+impl ToolBuild {
+    /// Whether this build names its own release (URLs or checksum)
+    fn sets_release(&self) -> bool {
+        self.urls.is_some() || self.sha256.is_some()
+    }
+
+    /// Fill fields that are still empty from a lower-precedence build; the URLs and
+    /// sha256 are taken only with `inherit_release`, never one without the other
+    pub fn merge_from(&mut self, lower: &ToolBuild, inherit_release: bool) {
+        if inherit_release {
+            self.urls = lower.urls.clone();
+            self.sha256 = lower.sha256.clone();
+        }
+        if self.format.is_none() { self.format = lower.format; }
+        if self.extract_path.is_none() { self.extract_path = lower.extract_path.clone(); }
+        if self.executable.is_none() { self.executable = lower.executable.clone(); }
+    }
+}
+
+/// Single tool description
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Installed only on demand, not by every setup-env
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows: Option<ToolBuild>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linux: Option<ToolBuild>,
+}
+
+impl ToolEntry {
+    /// Fill fields that are still empty from a lower-precedence entry. An entry that
+    /// sets its version, or URLs or sha256 for any build, pins a release of its own
+    /// and keeps none of those from `lower`
+    pub fn merge_from(&mut self, lower: &ToolEntry) {
+        let inherit_release = self.version.is_none()
+            && ![&self.windows, &self.linux].iter().any(|build| build.as_ref().is_some_and(ToolBuild::sets_release));
+        if inherit_release { self.version = lower.version.clone(); }
+        if self.optional.is_none() { self.optional = lower.optional; }
+        merge_build(&mut self.windows, &lower.windows, inherit_release);
+        merge_build(&mut self.linux, &lower.linux, inherit_release);
+    }
+
+    /// Build for the OS this binary runs on
+    pub fn current_build(&self) -> Option<&ToolBuild> {
+        if cfg!(windows) {
+            self.windows.as_ref()
+        } else if cfg!(target_os = "linux") {
+            self.linux.as_ref()
+        } else {
+            None
+        }
+    }
+}
+
+fn merge_build(build: &mut Option<ToolBuild>, lower: &Option<ToolBuild>, inherit_release: bool) {
+    if let Some(lower) = lower {
+        build.get_or_insert_with(ToolBuild::default).merge_from(lower, inherit_release);
+    }
+}
+
+/// On-disk manifest layout
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ToolManifest {
+    #[serde(default = "default_manifest_version")]
+    pub manifest_version: u32,
+    #[serde(default)]
+    pub tools: BTreeMap<String, ToolEntry>,
+}
+
+fn default_manifest_version() -> u32 {
+    MANIFEST_VERSION
+}
+
+impl ToolManifest {
+    pub fn parse(content: &str, origin: &str) -> Result<Self> {
+        let manifest: ToolManifest = toml::from_str(content)
+            .map_err(|e| PortableSourceError::config(format!("Failed to parse tool manifest {}: {}", origin, e)))?;
+        if manifest.manifest_version > MANIFEST_VERSION {
+            return Err(PortableSourceError::config(format!(
+                "Tool manifest {} has version {}, this PortableSource supports up to {}",
+                origin, manifest.manifest_version, MANIFEST_VERSION
+            )));
+        }
+        Ok(manifest)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, &path.display().to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| PortableSourceError::config(format!("Failed to serialize tool manifest: {}", e)))?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// The manifest built into the binary
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_MANIFEST, "built-in").expect("built-in tool manifest is valid")
+    }
+
+    /// Path of the user-editable manifest
+    pub fn local_manifest_path(install_path: &Path) -> PathBuf {
+        install_path.join("tools.toml")
+    }
+
+    /// Built-in manifest with the shared and local manifests laid over it.
+    /// Broken override files are skipped with a warning.
+    pub fn for_install_path(install_path: &Path) -> Self {
+        let mut layers: Vec<PathBuf> = vec![Self::local_manifest_path(install_path)];
+        if let Some(shared) = std::env::var_os(TOOLS_MANIFEST_ENV) {
+            layers.push(PathBuf::from(shared));
+        }
+        let mut manifest = Self::builtin();
+        for path in layers.iter().rev().filter(|p| p.is_file()) {
+            match Self::load(path) {
+                Ok(layer) => manifest = layer.over(&manifest),
+                Err(e) => warn!("Skipping tool manifest {:?}: {}", path, e),
+            }
+        }
+        manifest
+    }
+
+    /// This manifest with the missing fields and tools taken from `lower`
+    pub fn over(mut self, lower: &ToolManifest) -> Self {
+        for (name, entry) in &lower.tools {
+            match self.tools.get_mut(name) {
+                Some(higher) => higher.merge_from(entry),
+                None => { self.tools.insert(name.clone(), entry.clone()); }
+            }
+        }
+        self.manifest_version = self.manifest_version.max(lower.manifest_version);
+        self
+    }
+
+    /// Tools that have a usable build for this OS, by name
+    pub fn specs(&self) -> BTreeMap<String, PortableToolSpec> {
+        let mut specs = BTreeMap::new();
+        for (name, entry) in &self.tools {
+            let Some(build) = entry.current_build() else { continue };
+            match PortableToolSpec::resolve(name, entry, build) {
+                Some(spec) => { specs.insert(name.clone(), spec); }
+                None => warn!("Tool '{}' in the manifest needs urls and an executable, skipping it", name),
+            }
+        }
+        specs
+    }
+
+    pub fn spec(&self, name: &str) -> Option<PortableToolSpec> {
+        let entry = self.tools.get(name)?;
+        PortableToolSpec::resolve(name, entry, entry.current_build()?)
+    }
+
+    /// Tools for `setup-env --from-dir`: the layer `fetch-tools` wrote into `dir` (which
+    /// must be absolute) is laid over this manifest, and every tool is taken from `dir`
+    pub fn specs_in_dir(self, dir: &Path) -> Result<BTreeMap<String, PortableToolSpec>> {
+        let fetched = dir.join(FETCHED_MANIFEST_FILE);
+        let manifest = if fetched.is_file() { Self::load(&fetched)?.over(&self) } else { self };
+        Ok(manifest.specs().into_iter().map(|(name, spec)| (name, spec.in_dir(dir))).collect())
+    }
+}
+
+/// A tool as installed on this OS
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortableToolSpec {
+    pub name: String,
+    pub version: Option<String>,
+    /// Mirrors, tried in order
+    pub urls: Vec<String>,
+    pub sha256: Option<String>,
+    pub format: ArchiveFormat,
+    pub extract_path: String,
+    pub executable_path: String,
+    pub optional: bool,
+}
+
+impl PortableToolSpec {
+    fn resolve(name: &str, entry: &ToolEntry, build: &ToolBuild) -> Option<Self> {
+        let urls = build.urls.clone().filter(|urls| !urls.is_empty())?;
+        let executable_path = build.executable.clone()?;
+        Some(Self {
+            name: name.to_string(),
+            version: entry.version.clone(),
+            urls,
+            sha256: build.sha256.clone(),
+            format: build.format.unwrap_or_default(),
+            extract_path: build.extract_path.clone().unwrap_or_else(|| name.to_string()),
+            executable_path,
+            optional: entry.optional.unwrap_or(false),
+        })
+    }
+
+    pub fn artifact(&self) -> Artifact {
+        let mut artifact = Artifact::new(&self.name, &self.urls[0]).with_sha256(self.sha256.clone());
+        for url in &self.urls[1..] {
+            artifact = artifact.with_mirror(url);
+        }
+        artifact
+    }
+
+    /// File name of the downloaded archive
+    pub fn archive_name(&self) -> String {
+        format!("{}.{}", self.name, self.format.extension())
+    }
+
+    /// Download into `<dir>/<archive name>` for `setup-env --from-dir`, and record the
+    /// version, URLs and sha256 of the fetched file in the directory's manifest layer
+    pub fn fetch_into(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(self.archive_name());
+        Downloader::new().download(&self.artifact(), &path)?;
+
+        let layer_path = dir.join(FETCHED_MANIFEST_FILE);
+        let mut layer = if layer_path.is_file() {
+            ToolManifest::load(&layer_path)?
+        } else {
+            ToolManifest { manifest_version: MANIFEST_VERSION, tools: BTreeMap::new() }
+        };
+        let build = ToolBuild { urls: Some(self.urls.clone()), sha256: Some(sha256_file(&path)?), ..Default::default() };
+        let entry = layer.tools.entry(self.name.clone()).or_default();
+        entry.version = self.version.clone();
+        if cfg!(windows) { entry.windows = Some(build); } else { entry.linux = Some(build); }
+        layer.save(&layer_path)?;
+        Ok(path)
+    }
+
+    /// The same tool taken from `<dir>/<archive name>` instead of the mirrors; `dir` must be absolute
+    pub fn in_dir(&self, dir: &Path) -> Self {
+        let path = dir.join(self.archive_name());
+        let url = file_url(&path).unwrap_or_else(|| path.to_string_lossy().to_string());
+        Self { urls: vec![url], ..self.clone() }
+    }
+}
+
+/// What was installed into ps_env, per tool
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstalledTool {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// URL of the first mirror in the manifest at install time
+    pub source: String,
+}
+
+pub struct InstalledTools;
+
+impl InstalledTools {
+    pub fn load(ps_env: &Path) -> BTreeMap<String, InstalledTool> {
+        fs::read_to_string(ps_env.join(INSTALLED_TOOLS_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn record(ps_env: &Path, spec: &PortableToolSpec) -> Result<()> {
+        let mut installed = Self::load(ps_env);
+        installed.insert(spec.name.clone(), InstalledTool {
+            version: spec.version.clone(),
+            sha256: spec.sha256.clone(),
+            source: spec.urls[0].clone(),
+        });
+        fs::create_dir_all(ps_env)?;
+        fs::write(ps_env.join(INSTALLED_TOOLS_FILE), serde_json::to_string_pretty(&installed)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_manifest_overrides_fields() {
+        let local = ToolManifest::parse(r#"
+            [tools.git]
+            version = "2.47.1"
+            [tools.git.windows]
+            urls = ["https://mirror.example/git.tar.zst"]
+            [tools.git.linux]
+            urls = ["https://mirror.example/git-linux.tar.zst"]
+            executable = "git/bin/git"
+
+            [tools.node.windows]
+            urls = ["https://mirror.example/node.tar.zst"]
+            executable = "node/node.exe"
+        "#, "test").unwrap();
+        let manifest = local.over(&ToolManifest::builtin());
+
+        let git = &manifest.tools["git"];
+        assert_eq!(git.version.as_deref(), Some("2.47.1"));
+        let windows = git.windows.as_ref().unwrap();
+        assert_eq!(windows.urls.as_deref(), Some(&["https://mirror.example/git.tar.zst".to_string()][..]));
+        assert_eq!(windows.executable.as_deref(), Some("git/cmd/git.exe"));
+        assert!(manifest.tools.contains_key("node"));
+        assert_eq!(manifest.tools["cuda124"].optional, Some(true));
+
+        let builtin_python = ToolManifest::builtin().tools["python311"].windows.clone().unwrap();
+        let spec = PortableToolSpec::resolve("python311", &manifest.tools["python311"], &builtin_python).unwrap();
+        assert_eq!(spec.archive_name(), "python311.tar.zst");
+        assert_eq!(spec.version.as_deref(), Some("3.11"));
+
+        let local = spec.in_dir(Path::new("/srv/tools"));
+        assert_eq!(local.urls, vec!["file:///srv/tools/python311.tar.zst".to_string()]);
+        assert_eq!(crate::downloader::local_path(&local.urls[0]).unwrap(), Path::new("/srv/tools/python311.tar.zst"));
+
+        let future = ToolManifest::parse("manifest_version = 99", "test");
+        assert!(future.is_err());
+    }
+
+    #[test]
+    fn test_fetched_directory_installs_offline() {
+        // The built-in build for this OS, served by a local server instead of its mirrors
+        let name = if cfg!(windows) { "git" } else { "micromamba" };
+        let body: &'static [u8] = b"fetched tool";
+        let (base, _) = crate::downloader::tests::serve(body);
+        let builtin = ToolManifest::builtin();
+        let spec = PortableToolSpec { urls: vec![format!("{}/data", base)], ..builtin.spec(name).unwrap() };
+        let dir = tempfile::tempdir().unwrap();
+        spec.fetch_into(dir.path()).unwrap();
+
+        // Without the recorded checksum the local copy is refused
+        let target = dir.path().join("ps_env").join(&spec.executable_path);
+        let unverified = builtin.spec(name).unwrap().in_dir(dir.path());
+        assert!(Downloader::new().download(&unverified.artifact(), &target).is_err());
+
+        // setup-env --from-dir
+        let offline = builtin.specs_in_dir(dir.path()).unwrap().remove(name).unwrap();
+        assert_eq!(offline.version, spec.version);
+        assert_eq!(offline.executable_path, spec.executable_path);
+        assert_eq!(offline.sha256, Some(crate::downloader::sha256_file(&dir.path().join(spec.archive_name())).unwrap()));
+        Downloader::new().download(&offline.artifact(), &target).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), body);
+    }
+
+    #[test]
+    fn test_release_fields_are_not_mixed_across_layers() {
+        let lower = ToolManifest::parse(r#"
+            [tools.git]
+            version = "2.47.1"
+            [tools.git.linux]
+            urls = ["https://example.com/git-2.47.1.tar.zst"]
+            sha256 = "aaaa"
+            executable = "git/bin/git"
+        "#, "test").unwrap();
+
+        let mirror = ToolManifest::parse(r#"
+            [tools.git.linux]
+            urls = ["https://mirror.example/git.tar.zst"]
+        "#, "test").unwrap().over(&lower);
+        let git = &mirror.tools["git"];
+        let linux = git.linux.as_ref().unwrap();
+        assert_eq!(git.version, None);
+        assert_eq!(linux.sha256, None);
+        assert_eq!(linux.executable.as_deref(), Some("git/bin/git"));
+
+        let bumped = ToolManifest::parse(r#"
+            [tools.git]
+            version = "2.48.0"
+        "#, "test").unwrap().over(&lower);
+        let linux = bumped.tools["git"].linux.as_ref().unwrap();
+        assert_eq!(linux.urls, None);
+        assert_eq!(linux.sha256, None);
+        assert_eq!(linux.executable.as_deref(), Some("git/bin/git"));
+
+        let tweaked = ToolManifest::parse(r#"
+            [tools.git.linux]
+            executable = "git/usr/bin/git"
+        "#, "test").unwrap().over(&lower);
+        let git = &tweaked.tools["git"];
+        assert_eq!(git.version.as_deref(), Some("2.47.1"));
+        assert_eq!(git.linux.as_ref().unwrap().sha256.as_deref(), Some("aaaa"));
+    }
+}
@@ -18,6 +18,8 @@
 
 use crate::{Result, PortableSourceError};
 use crate::config::ConfigManager;
+use crate::downloader::{Artifact, Downloader};
+use crate::tools::{InstalledTools, ToolManifest};
 use crate::envs_manager::PortableEnvironmentManager;
 use crate::repository_installer::RepositoryInstaller;
 use crate::gpu::{GpuDetector, GpuType};
@@ -318,22 +320,6 @@ pub fn prompt_install_path_linux(default: &Path) -> Result<PathBuf> {
     validate_and_create_path(&chosen)
 }
 
-/// Simple HTTP(S) download helper
-pub fn download_file(url: &str, destination: &Path) -> Result<()> {
-    use reqwest::blocking::Client;
-    use std::io::copy;
-    if let Some(parent) = destination.parent() { std::fs::create_dir_all(parent)?; }
-    let client = Client::builder().timeout(Duration::from_secs(600)).build()?;
-    let mut resp = client.get(url).send()
-        .map_err(|e| PortableSourceError::environment(format!("Failed to GET {}: {}", url, e)))?;
-    if !resp.status().is_success() {
-        return Err(PortableSourceError::environment(format!("Download failed: HTTP {}", resp.status())));
-    }
-    let mut file = std::fs::File::create(destination)?;
-    copy(&mut resp, &mut file)?;
-    Ok(())
-}
-
 #[cfg(unix)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum LinuxMode { Cloud, Desk }
@@ -393,27 +379,23 @@ fn cuda_version_to_runtime_spec(v: &crate::config::CudaVersionLinux) -> &'static
     }
 }
 
+/// Folder with conda packages inside a `fetch-tools` directory
 #[cfg(unix)]
-pub fn setup_micromamba_base_env(install_path: &Path, cuda_version: Option<crate::config::CudaVersionLinux>) -> Result<()> {
-    use std::os::unix::fs::PermissionsExt;
-    // Ensure directory layout
-    create_directory_structure(install_path)?;
-    let mamba_bin = install_path.join("ps_env").join("micromamba-linux-64");
-    // Correct latest asset URL + fallback pinned version
-    let mamba_url_latest = "https://github.com/mamba-org/micromamba-releases/releases/latest/download/micromamba-linux-64";
-    let mamba_url_fallback = "https://github.com/mamba-org/micromamba-releases/releases/download/2.3.1-0/micromamba-linux-64";
-    if !mamba_bin.exists() {
-        if let Err(e) = download_file(mamba_url_latest, &mamba_bin) {
-            log::warn!("micromamba latest download failed: {} — trying fallback", e);
-            download_file(mamba_url_fallback, &mamba_bin)?;
-        }
-        let mut perms = std::fs::metadata(&mamba_bin)?.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(&mamba_bin, perms)?;
-    }
+pub const CONDA_PKGS_DIR: &str = "conda-pkgs";
 
-    let base_prefix = install_path.join("ps_env").join("mamba_env");
-    let root_prefix = install_path.join("ps_env");
+#[cfg(unix)]
+fn micromamba_spec(install_path: &Path, artifact_dir: Option<&Path>) -> Result<crate::tools::PortableToolSpec> {
+    // The pinned release comes from the tool manifest, or from the fetch-tools directory
+    let manifest = ToolManifest::for_install_path(install_path);
+    let spec = match artifact_dir {
+        Some(dir) => manifest.specs_in_dir(dir)?.remove("micromamba"),
+        None => manifest.spec("micromamba"),
+    };
+    spec.ok_or_else(|| PortableSourceError::environment("No micromamba build for Linux in the tool manifest"))
+}
+
+#[cfg(unix)]
+fn micromamba_create_args(root_prefix: &Path, base_prefix: &Path, cuda_version: Option<&crate::config::CudaVersionLinux>) -> Vec<String> {
     let mut args: Vec<String> = vec![
         "create".into(),
         "-y".into(),
@@ -422,23 +404,30 @@ pub fn setup_micromamba_base_env(install_path: &Path, cuda_version: Option<crate
         "-c".into(), "nvidia".into(), "-c".into(), "conda-forge".into(),
         "python=3.11".into(), "git".into(), "ffmpeg".into(),
     ];
-    let mut attempted_cuda = false;
-    if let Some(v) = cuda_version.as_ref() {
+    if let Some(v) = cuda_version {
         let spec = cuda_version_to_runtime_spec(v);
         args.push(format!("cuda-toolkit={}", spec));
         args.push("cudnn".into());
-        attempted_cuda = true;
     }
+    args
+}
+
+/// Run micromamba with a spinner showing its output; `pkgs_dir` replaces the package cache
+#[cfg(unix)]
+fn run_micromamba(mamba_bin: &Path, root_prefix: &Path, args: Vec<String>, pkgs_dir: Option<&Path>) -> Result<bool> {
     // auto-accept ToS/licenses
-    let mut child = std::process::Command::new(&mamba_bin)
-        .env("MAMBA_ALWAYS_YES", "true")
+    let mut cmd = std::process::Command::new(mamba_bin);
+    cmd.env("MAMBA_ALWAYS_YES", "true")
         .env("MAMBA_NO_RC", "true")
-        .env("MAMBA_ROOT_PREFIX", &root_prefix)
-        .current_dir(&root_prefix)
+        .env("MAMBA_ROOT_PREFIX", root_prefix)
+        .current_dir(root_prefix)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::inherit())
-        .args(args)
-        .spawn()
+        .args(args);
+    if let Some(pkgs_dir) = pkgs_dir {
+        cmd.env("CONDA_PKGS_DIRS", pkgs_dir);
+    }
+    let mut child = cmd.spawn()
         .map_err(|e| PortableSourceError::environment(format!("Failed to run micromamba: {}", e)))?;
 
     let pb = ProgressBar::new_spinner();
@@ -455,8 +444,60 @@ pub fn setup_micromamba_base_env(install_path: &Path, cuda_version: Option<crate
         }
     }
     let status = child.wait().map_err(|e| PortableSourceError::environment(format!("micromamba wait failed: {}", e)))?;
-    if status.success() {
-        pb.finish_with_message("micromamba: done");
+    pb.finish_with_message(if status.success() { "micromamba: done" } else { "micromamba: failed" });
+    Ok(status.success())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = std::fs::metadata(path)?.permissions();
+    perms.set_mode(0o755);
+    std::fs::set_permissions(path, perms)?;
+    Ok(())
+}
+
+/// Download micromamba and the conda packages of the base environment into `dir`
+/// for `setup-env --from-dir`
+#[cfg(unix)]
+pub fn fetch_micromamba_base_env(install_path: &Path, dir: &Path, cuda_version: Option<crate::config::CudaVersionLinux>) -> Result<()> {
+    let mamba_bin = micromamba_spec(install_path, None)?.fetch_into(dir)?;
+    make_executable(&mamba_bin)?;
+
+    // Solve and download into the package folder without creating an environment
+    let root_prefix = tempfile::tempdir_in(dir)?;
+    let mut args = micromamba_create_args(root_prefix.path(), &root_prefix.path().join("env"), cuda_version.as_ref());
+    args.push("--download-only".into());
+    if !run_micromamba(&mamba_bin, root_prefix.path(), args, Some(&dir.join(CONDA_PKGS_DIR)))? {
+        return Err(PortableSourceError::environment("micromamba failed to download the base environment packages"));
+    }
+    Ok(())
+}
+
+/// Create the micromamba base environment. With `artifact_dir` (a `fetch-tools`
+/// directory) micromamba and the packages come from there and nothing is downloaded.
+#[cfg(unix)]
+pub fn setup_micromamba_base_env(install_path: &Path, cuda_version: Option<crate::config::CudaVersionLinux>, artifact_dir: Option<&Path>) -> Result<()> {
+    // Ensure directory layout
+    create_directory_structure(install_path)?;
+    let ps_env = install_path.join("ps_env");
+    let mamba = micromamba_spec(install_path, artifact_dir)?;
+    let mamba_bin = ps_env.join(&mamba.executable_path);
+    if !mamba_bin.exists() {
+        Downloader::new().download(&mamba.artifact(), &mamba_bin)?;
+        make_executable(&mamba_bin)?;
+        InstalledTools::record(&ps_env, &mamba)?;
+    }
+
+    let base_prefix = install_path.join("ps_env").join("mamba_env");
+    let root_prefix = install_path.join("ps_env");
+    let mut args = micromamba_create_args(&root_prefix, &base_prefix, cuda_version.as_ref());
+    let attempted_cuda = cuda_version.is_some();
+    let pkgs_dir = artifact_dir.map(|dir| dir.join(CONDA_PKGS_DIR));
+    if pkgs_dir.is_some() {
+        args.push("--offline".into());
+    }
+    if run_micromamba(&mamba_bin, &root_prefix, args, pkgs_dir.as_deref())? {
         // Verify env created
         let py = base_prefix.join("bin").join("python");
         if !py.exists() {
@@ -466,7 +507,6 @@ pub fn setup_micromamba_base_env(install_path: &Path, cuda_version: Option<crate
             )));
         }
     } else {
-        pb.finish_with_message("micromamba: failed");
         return Err(PortableSourceError::environment("micromamba create failed"));
     }
     // Verify CUDA runtime presence on DESK: libcudart.so* must exist if we attempted CUDA
@@ -487,6 +527,13 @@ pub fn setup_micromamba_base_env(install_path: &Path, cuda_version: Option<crate
             if found { break; }
         }
         if !found {
+            // Offline: nothing to fall back to, the packages from fetch-tools lack the runtime
+            if let Some(dir) = pkgs_dir.as_deref() {
+                return Err(PortableSourceError::environment(format!(
+                    "CUDA runtime verification failed: libcudart not found, and the offline packages in {} do not provide it. Re-run fetch-tools with network access for this CUDA version",
+                    dir.display()
+                )));
+            }
             // Fallback: try install TensorRT via pip from NVIDIA PyPI if conda TRT missing
             let py = base_prefix.join("bin").join("python");
             if py.exists() {
@@ -591,8 +638,6 @@ fn check_msvc_registry() -> bool {
 
 /// Download and run MSVC Build Tools installer (blocking)
 pub fn install_msvc_build_tools() -> Result<()> {
-    use reqwest::blocking::Client;
-    use std::io::copy;
     
     // Prefer winget if available (often faster and more reliable)
     // Helper: choose one SDK depending on OS build (Win11 vs Win10)
@@ -680,21 +725,7 @@ pub fn install_msvc_build_tools() -> Result<()> {
 
     // Download file
     log::info!("Downloading installer to {:?}...", installer_path);
-    let client = Client::builder()
-        .timeout(Duration::from_secs(600))
-        .build()?;
-    let mut resp = client.get(&url).send()?;
-    if !resp.status().is_success() {
-        return Err(PortableSourceError::installation(format!(
-            "Failed to download installer: HTTP {}",
-            resp.status()
-        )));
-    }
-    let mut file = std::fs::File::create(&installer_path)?;
-    copy(&mut resp, &mut file)?;
-    // Ensure data is fully flushed and file handle is closed before executing (Windows lock avoidance)
-    let _ = file.sync_all();
-    drop(file);
+    Downloader::new().download(&Artifact::new("vs_buildtools", &url), &installer_path)?;
 
     // Run installer
     log::info!("Running installer (this may take a while)...");
@@ -726,8 +757,6 @@ pub fn install_msvc_build_tools() -> Result<()> {
 
 /// Install MSVC Build Tools using a provided install path for temp storage
 pub fn install_msvc_build_tools_with_path(install_path: &Path) -> Result<()> {
-    use reqwest::blocking::Client;
-    use std::io::copy;
 
     let (url, args) = ConfigManager::new(None)
         .map(|cm| cm.msvc_bt_config())
@@ -744,16 +773,7 @@ pub fn install_msvc_build_tools_with_path(install_path: &Path) -> Result<()> {
     let installer_path = temp_dir.join("vs_buildtools.exe");
 
     log::info!("Downloading installer to {:?}...", installer_path);
-    let client = Client::builder().timeout(Duration::from_secs(600)).build()?;
-    let mut resp = client.get(&url).send()?;
-    if !resp.status().is_success() {
-        return Err(PortableSourceError::installation(format!(
-            "Failed to download installer: HTTP {}",
-            resp.status()
-        )));
-    }
-    let mut file = std::fs::File::create(&installer_path)?;
-    copy(&mut resp, &mut file)?;
+    Downloader::new().download(&Artifact::new("vs_buildtools", &url), &installer_path)?;
 
     log::info!("Running installer (this may take a while)...");
     let status = Command::new(&installer_path)